@@ -0,0 +1,16 @@
+#include <signal.h>
+#include <stdio.h>
+
+volatile sig_atomic_t handled = 0;
+
+void on_alarm(int sig) {
+    handled = 1;
+}
+
+int main() {
+    signal(SIGALRM, on_alarm);
+    raise(SIGALRM);
+    int result = handled;
+    printf("handled = %d\n", result);
+    return result ? 0 : 1;
+}
@@ -0,0 +1,24 @@
+#include <stdio.h>
+
+struct point {
+    int x;
+    int y;
+};
+
+int primes[5] = {2, 3, 5, 7, 11};
+const char *motto = "print me";
+
+typedef float vec3[3];
+
+int main() {
+    char name[8] = "deet";
+    int grid[2][3] = {{1, 2, 3}, {4, 5, 6}};
+    struct point path[2] = {{0, 0}, {3, 4}};
+    vec3 corners[2] = {{0, 0, 0}, {1, 2, 3}};
+    long squares[300];
+    for (int i = 0; i < 300; i++) {
+        squares[i] = (long)i * i;
+    }
+    printf("%s %s %d %d %d %ld\n", motto, name, grid[1][2], path[1].y, primes[4], squares[299]);
+    return 0;
+}
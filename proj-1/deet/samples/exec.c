@@ -0,0 +1,14 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main(int argc, char *argv[]) {
+    if (argc < 2) {
+        printf("usage: %s <program> [args...]\n", argv[0]);
+        return 1;
+    }
+    printf("executing %s\n", argv[1]);
+    fflush(stdout);
+    execv(argv[1], &argv[1]);
+    perror("execv");
+    return 1;
+}
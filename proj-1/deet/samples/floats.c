@@ -0,0 +1,10 @@
+#include <stdio.h>
+
+double scale(double x, float factor) {
+    return x * factor;
+}
+
+int main() {
+    printf("%f\n", scale(2.5, 0.5f));
+    return 0;
+}
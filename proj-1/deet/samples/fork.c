@@ -0,0 +1,18 @@
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int twice(int x) {
+    return x * 2;
+}
+
+int main() {
+    pid_t pid = fork();
+    if (pid == 0) {
+        return twice(21) == 42 ? 0 : 1;
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    printf("child exited with %d\n", WEXITSTATUS(status));
+    return WIFEXITED(status) && WEXITSTATUS(status) == 0 ? 0 : 1;
+}
@@ -0,0 +1,12 @@
+#include <stdio.h>
+
+static inline __attribute__((always_inline)) int square(int x) {
+    return x * x;
+}
+
+int main(int argc, char *argv[]) {
+    int a = square(argc);
+    int b = square(argc + 1);
+    printf("%d %d\n", a, b);
+    return 0;
+}
@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+int main() {
+    printf("before the trap\n");
+    asm("int3");
+    printf("after the trap\n");
+    return 0;
+}
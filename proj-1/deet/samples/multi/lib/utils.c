@@ -0,0 +1,7 @@
+#include <stdio.h>
+
+int mul(int a, int b) {
+    int product = a * b;
+    printf("mul(%d, %d) = %d\n", a, b, product);
+    return product;
+}
@@ -0,0 +1,11 @@
+#include <stdio.h>
+
+int add(int a, int b);
+int mul(int a, int b);
+
+int main() {
+    int x = add(2, 3);
+    int y = mul(x, 4);
+    printf("result = %d\n", y);
+    return 0;
+}
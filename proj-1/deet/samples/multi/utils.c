@@ -0,0 +1,7 @@
+#include <stdio.h>
+
+int add(int a, int b) {
+    int sum = a + b;
+    printf("add(%d, %d) = %d\n", a, b, sum);
+    return sum;
+}
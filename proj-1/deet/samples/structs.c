@@ -0,0 +1,34 @@
+#include <stdio.h>
+
+enum color { RED, GREEN, BLUE = 4 };
+
+struct point {
+    int x;
+    int y;
+};
+
+typedef struct node {
+    int value;
+    struct node *next;
+    struct point where;
+    enum color color;
+} node_t;
+
+typedef struct link link_t;
+
+struct link {
+    int id;
+    link_t *next;
+};
+
+int main() {
+    struct point origin = {3, 7};
+    node_t tail = {2, NULL, {0, 1}, BLUE};
+    struct node head = {1, &tail, {5, -5}, GREEN};
+    enum color shade = RED;
+    enum color odd = 3;
+    link_t last = {2, NULL};
+    struct link first = {1, &last};
+    printf("%d %d %d %d\n", origin.x, head.value, shade, odd);
+    return 0;
+}
@@ -0,0 +1,22 @@
+#include <pthread.h>
+#include <stdio.h>
+
+int calls = 0;
+
+void work(int id) {
+    calls++;
+    printf("work done by %d\n", id);
+}
+
+void *worker(void *arg) {
+    work(1);
+    return NULL;
+}
+
+int main() {
+    pthread_t thread;
+    pthread_create(&thread, NULL, worker, NULL);
+    pthread_join(thread, NULL);
+    work(0);
+    return 0;
+}
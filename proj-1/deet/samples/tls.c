@@ -0,0 +1,9 @@
+#include <stdio.h>
+
+__thread int counter = 5;
+
+int main() {
+    counter += 2;
+    printf("counter = %d\n", counter);
+    return 0;
+}
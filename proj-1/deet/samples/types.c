@@ -0,0 +1,15 @@
+#include <stdbool.h>
+
+char grade = 'B';
+
+int main() {
+    int count = -3;
+    char letter = 'x';
+    unsigned char byte = 200;
+    bool done = true;
+    float ratio = 2.5;
+    double half = 0.5;
+    const char *name = "deet";
+    int *counter = &count;
+    return *counter + letter + byte + done + (int)(ratio + half) + name[0];
+}
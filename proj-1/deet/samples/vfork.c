@@ -0,0 +1,18 @@
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int report(int status) {
+    printf("child exited with %d\n", WEXITSTATUS(status));
+    return WIFEXITED(status) && WEXITSTATUS(status) == 0 ? 0 : 1;
+}
+
+int main() {
+    pid_t pid = vfork();
+    if (pid == 0) {
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return report(status);
+}
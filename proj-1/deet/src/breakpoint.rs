@@ -0,0 +1,224 @@
+use crate::inferior::Access;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+/// A user breakpoint.
+pub struct Breakpoint {
+    /// The number shown to the user. Numbers start at 1 and are never reused, so scripts can
+    /// rely on the number a breakpoint was assigned.
+    pub number: usize,
+    /// The resolved address the trap byte is written to
+    pub addr: usize,
+    /// The location as the user wrote it, e.g. "main" or "foo.c:12", so that it can be
+    /// resolved again against a new symbol table
+    pub location: String,
+    /// Expression that must evaluate to non-zero for the breakpoint to stop the program
+    pub condition: Option<String>,
+    /// Disabled breakpoints stay in the table but aren't installed in the inferior
+    pub enabled: bool,
+    /// Number of stops left before the breakpoint deletes itself (`break LOCATION -count N`)
+    pub delete_after: Option<usize>,
+    /// How many times the program reached the breakpoint with its condition true
+    pub hit_count: usize,
+    /// Number of upcoming hits to continue through without stopping (`ignore N COUNT`)
+    pub ignore_count: usize,
+    /// Whether the breakpoint lives in a debug register rather than a trap byte (`hbreak`)
+    pub hardware: bool,
+    /// Debugger commands run each time the breakpoint stops the program (`commands N`)
+    pub commands: Vec<String>,
+    /// The location couldn't be resolved when the breakpoint was set, so `addr` means nothing
+    /// yet. It is retried whenever new symbols are loaded.
+    pub pending: bool,
+    /// Format and argument expressions printed, instead of stopping, each time the breakpoint
+    /// is hit (`trace`)
+    pub printf: Option<(String, Vec<String>)>,
+    /// The only thread the breakpoint stops (`break LOCATION thread TID`). Other threads
+    /// that hit it are stepped over it and carry on.
+    pub thread: Option<Pid>,
+}
+
+/// What a catchpoint stops the program for.
+pub enum Catch {
+    /// Entry to or return from any of these system calls, or from any system call if empty
+    Syscall(Vec<u64>),
+    /// Delivery of any of these signals, or of any signal if empty
+    Signal(Vec<Signal>),
+    Fork,
+    VFork,
+    Exec,
+}
+
+/// A catchpoint, which stops the program at an event rather than at an address.
+pub struct Catchpoint {
+    /// Catchpoints are numbered separately from breakpoints, starting at 1
+    pub number: usize,
+    pub catch: Catch,
+}
+
+/// A watchpoint, which stops the program when it accesses a memory location. It is held in a
+/// debug register, so it costs nothing while the program runs, unless none was free.
+pub struct Watchpoint {
+    /// Watchpoints are numbered separately as well, starting at 1
+    pub number: usize,
+    /// The variable or address as the user wrote it
+    pub expr: String,
+    pub addr: usize,
+    /// Number of bytes watched: 1, 2, 4 or 8
+    pub len: usize,
+    pub kind: WatchKind,
+    /// The contents of the location when last looked at, to show what a write changed
+    pub value: Option<u64>,
+    /// The watchpoint is checked after every instruction because no debug register was free
+    /// for it. Only writes that change the value are noticed this way.
+    pub software: bool,
+    /// For a local variable, the return address and canonical frame address of the frame it
+    /// lives in. The watchpoint is deleted when that frame returns, as its stack slot is then
+    /// free for other frames to write.
+    pub scope: Option<(usize, usize)>,
+}
+
+/// What a watchpoint stops the program for.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchKind {
+    /// Writes to the location (`watch`)
+    Write,
+    /// Reads of the location (`rwatch`)
+    Read,
+    /// Reads or writes (`awatch`)
+    Access,
+}
+
+impl Watchpoint {
+    /// Returns the access the watchpoint's debug register traps on. Reads can only be caught
+    /// together with writes, so read watchpoints have to tell the two apart afterwards.
+    pub fn access(&self) -> Access {
+        match self.kind {
+            WatchKind::Write => Access::Write,
+            WatchKind::Read | WatchKind::Access => Access::ReadWrite,
+        }
+    }
+}
+
+/// The debugger's breakpoint table.
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    next_number: usize,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints {
+            list: Vec::new(),
+            next_number: 1,
+        }
+    }
+
+    /// Adds a breakpoint and returns the number assigned to it.
+    pub fn add(&mut self, addr: usize, location: &str) -> usize {
+        let number = self.next_number;
+        self.next_number += 1;
+        self.list.push(Breakpoint {
+            number,
+            addr,
+            location: location.to_string(),
+            condition: None,
+            enabled: true,
+            delete_after: None,
+            hit_count: 0,
+            ignore_count: 0,
+            hardware: false,
+            commands: Vec::new(),
+            pending: false,
+            printf: None,
+            thread: None,
+        });
+        number
+    }
+
+    /// Returns the number the next breakpoint added will get.
+    pub fn next_number(&self) -> usize {
+        self.next_number
+    }
+
+    /// Removes breakpoint `number` from the table, returning it if it existed.
+    pub fn remove(&mut self, number: usize) -> Option<Breakpoint> {
+        let index = self.list.iter().position(|bp| bp.number == number)?;
+        Some(self.list.remove(index))
+    }
+
+    pub fn get(&self, number: usize) -> Option<&Breakpoint> {
+        self.list.iter().find(|bp| bp.number == number)
+    }
+
+    pub fn get_mut(&mut self, number: usize) -> Option<&mut Breakpoint> {
+        self.list.iter_mut().find(|bp| bp.number == number)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Breakpoint> {
+        self.list.iter_mut()
+    }
+
+    /// Returns the enabled breakpoint at `addr`, if any.
+    pub fn find_by_addr(&self, addr: usize) -> Option<&Breakpoint> {
+        self.list.iter().find(|bp| bp.enabled && !bp.pending && bp.addr == addr)
+    }
+
+    /// Returns the addresses of all enabled software breakpoints, or of all enabled hardware
+    /// ones, e.g. for installing them in a new inferior.
+    pub fn addrs(&self, hardware: bool) -> Vec<usize> {
+        let mut addrs: Vec<usize> = self
+            .list
+            .iter()
+            .filter(|bp| bp.enabled && !bp.pending && bp.hardware == hardware)
+            .map(|bp| bp.addr)
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_numbers_start_at_one() {
+        let mut breakpoints = Breakpoints::new();
+        assert_eq!(breakpoints.add(0x401136, "0x401136"), 1);
+        assert_eq!(breakpoints.add(0x401150, "0x401150"), 2);
+    }
+
+    #[test]
+    fn test_numbers_not_reused_after_delete() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(0x401136, "0x401136");
+        let second = breakpoints.add(0x401150, "0x401150");
+        assert!(breakpoints.remove(second).is_some());
+        assert!(breakpoints.get(second).is_none());
+        assert_eq!(breakpoints.add(0x401170, "0x401170"), 3);
+        assert_eq!(breakpoints.remove(second).map(|bp| bp.number), None);
+    }
+
+    #[test]
+    fn test_disabled_breakpoints_are_not_installed() {
+        let mut breakpoints = Breakpoints::new();
+        let first = breakpoints.add(0x401136, "0x401136");
+        breakpoints.add(0x401150, "0x401150");
+        breakpoints.get_mut(first).unwrap().enabled = false;
+        assert_eq!(breakpoints.addrs(false), vec![0x401150]);
+        assert!(breakpoints.find_by_addr(0x401136).is_none());
+    }
+}
@@ -1,26 +1,146 @@
+use std::collections::{HashMap, VecDeque};
 use std::ops::RangeBounds;
 
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::Inferior;
-use crate::inferior::Status;
+use crate::breakpoint::{Breakpoint, Breakpoints, Catch, Catchpoint, WatchKind, Watchpoint};
+use crate::debugger_command::{DebuggerCommand, DeleteTarget, ExamineFormat};
+use crate::dwarf_data::{
+    DwarfData, Error as DwarfError, Function, Line, Location, Member, Type, TypeKind,
+};
+use crate::disasm;
+use crate::elf::{self, ElfClass, ElfInfo};
+use crate::expr::{self, Context, Expr};
+use crate::format::{self, char_literal, string_literal};
+use crate::inferior::{Checkpoints, Frame, Inferior, HW_BREAKPOINT_SLOTS};
+use crate::inferior::{signal_description, Status};
+use crate::json::{self, JsonValue};
+use crate::pager::{self, Pager};
+use crate::registers;
+use crate::syscalls;
 use libc::ptrace;
+use regex::Regex;
+use libc::user_regs_struct;
 use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
+/// Default number of commands kept in the history (`set history size`)
+const DEFAULT_HISTORY_SIZE: usize = 256;
+
+/// Longest string `x/s` shows before cutting it short, as in GDB
+const MAX_STRING_LEN: usize = 200;
+const DEFAULT_PRINT_ELEMENTS: usize = 200;
+/// Number of source lines `list` shows on either side of the line it centres on
+const LIST_CONTEXT: usize = 5;
+/// How often the prompt checks on a program running in the background, in milliseconds
+const BACKGROUND_POLL_MS: i32 = 100;
+/// Most bytes `hexdump` reads at once, so that a mistyped length can't exhaust our memory
+const MAX_HEXDUMP_LEN: usize = 4 << 20;
+
+const NO_DEBUG_INFO: &str = "no debug info: symbolic commands are unavailable (compile the target with -g)";
+
+/// What happens to the symbols when the inferior execs a new program (`set follow-exec-mode`)
+#[derive(Clone, Copy, PartialEq)]
+enum FollowExecMode {
+    /// Load the new program's symbols and re-resolve breakpoints against them
+    New,
+    /// Keep the current symbols, e.g. when a program re-execs itself
+    Same,
+}
+
+/// An expression shown every time the program stops, set up with `display`.
+#[derive(Debug, PartialEq)]
+struct AutoDisplay {
+    /// Displays are numbered separately as well, starting at 1
+    number: usize,
+    /// Format letter from `display/FMT`, if one was given
+    format: Option<char>,
+    expr: String,
+}
+
 pub struct Debugger {
     target: String,
     history_path: String,
+    /// Whether the history is written to `history_path` (`set history save`)
+    history_save: bool,
     readline: Editor<()>,
+    /// Process of the current inferior, if it has one
     inferior: Option<Inferior>,
+    /// Number of the current inferior (`inferior N`), counting from 1
+    current_inferior: usize,
+    /// Processes of all inferiors by number - 1. The current inferior's slot is empty while its
+    /// process is in `inferior`.
+    inferiors: Vec<Option<Inferior>>,
     debug_data: DwarfData,
-    breakpoints: Vec<usize>,
+    /// Program headers of the target, if it could be parsed as an ELF64 image
+    elf: Option<ElfInfo>,
+    /// Whether the target is a 32- or 64-bit program, if it's ELF at all
+    elf_class: Option<ElfClass>,
+    breakpoints: Breakpoints,
+    catchpoints: Vec<Catchpoint>,
+    /// Number the next catchpoint will get
+    next_catchpoint: usize,
+    watchpoints: Vec<Watchpoint>,
+    /// Number the next watchpoint will get
+    next_watchpoint: usize,
+    /// Expressions shown every time the program stops (`display`)
+    displays: Vec<AutoDisplay>,
+    /// Number the next display will get
+    next_display: usize,
+    checkpoints: Checkpoints,
+    /// Whether events are reported as JSON (`--json`)
+    json: bool,
+    pager: Pager,
+    /// Whether the inferior was resumed with `run &` / `continue &` and hasn't been waited on
+    background: bool,
+    /// Whether `stepi`/`nexti` print the registers the step changed
+    step_register_diff: bool,
+    /// Whether every stop also shows the instruction about to run (`set disassemble-next-line`)
+    disassemble_next_line: bool,
+    /// Whether the program stops at every system call entry and exit (`set stop-on-syscall`)
+    stop_on_syscall: bool,
+    /// Most elements of an array or characters of a string `print` shows; 0 for no limit
+    print_elements: usize,
+    /// Whether backtraces continue past `main` into the C runtime startup frames
+    backtrace_past_main: bool,
+    /// Whether `break FUNC` also stops at the places FUNC was inlined (`set breakpoint inline`)
+    break_inline: bool,
+    /// Whether a breakpoint whose location can't be resolved is kept as pending (`set breakpoint
+    /// pending`); None asks each time
+    break_pending: Option<bool>,
+    /// Whether to ask before doing something drastic such as killing the target
+    confirm: bool,
+    follow_exec_mode: FollowExecMode,
+    /// Results of `print`, referred to as $1, $2, ...
+    value_history: Vec<i64>,
+    /// Debugger-side variables defined with `set $name = value`
+    convenience_vars: HashMap<String, i64>,
+    /// Stack frame that `frame`, `up` and `down` moved to; 0 is the innermost. Variables are
+    /// looked up in this frame.
+    selected_frame: usize,
+    /// Memory regions saved with `snapshot`, as (start address, bytes)
+    snapshots: HashMap<String, (usize, Vec<u8>)>,
+    /// Variables added to the inferior's environment with `set environment`
+    environment: HashMap<String, String>,
+    /// Whether the inferior starts with a copy of our environment (`set environment-inherit`)
+    inherit_environment: bool,
+    /// Command list of the breakpoint the program last stopped at, still to be run. These are
+    /// executed before reading more input.
+    pending_commands: VecDeque<String>,
+    /// Remaining lines of the files being read with `source`, run after `pending_commands`
+    script_lines: VecDeque<String>,
+    /// Number and address of the breakpoint behind the last stop, kept so the stop can be
+    /// reported even when the breakpoint was deleted on reaching its stop count
+    last_breakpoint: Option<(usize, usize)>,
+    /// Number of the watchpoint behind the last stop, and the value it held before
+    last_watchpoint: Option<(usize, Option<u64>)>,
 }
 
 impl Debugger {
     /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
+    pub fn new(target: &str, json: bool) -> Debugger {
         let debug_data = match DwarfData::from_file(target) {
             Ok(val) => val,
             Err(DwarfError::ErrorOpeningFile) => {
@@ -29,201 +149,5418 @@ impl Debugger {
             }
             Err(DwarfError::DwarfFormatError(err)) => {
                 println!(
-                    "could not load debugging symbols from {}: {:?}",
+                    "could not load debugging symbols from {}: {}",
                     target, err
                 );
                 std::process::exit(1);
             }
         };
-        debug_data.print();
+        if debug_data.has_debug_info() {
+            debug_data.print();
+        } else {
+            println!(
+                "No debugging symbols found in {}; symbolic commands will be unavailable. Compile with -g.",
+                target
+            );
+        }
+
+        let elf_class = elf::class_of_file(target);
+        if elf_class == Some(ElfClass::Elf32) {
+            println!(
+                "warning: {} is a 32-bit program; 32-bit targets are not supported yet",
+                target
+            );
+        }
 
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         let mut readline = Editor::<()>::new();
+        readline.set_max_history_size(DEFAULT_HISTORY_SIZE);
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path);
 
         Debugger {
             target: target.to_string(),
             history_path,
+            history_save: true,
             readline,
             inferior: None,
+            current_inferior: 1,
+            inferiors: vec![None],
             debug_data,
-            breakpoints: Vec::new()
+            elf: ElfInfo::from_file(target),
+            elf_class,
+            breakpoints: Breakpoints::new(),
+            catchpoints: Vec::new(),
+            next_catchpoint: 1,
+            watchpoints: Vec::new(),
+            next_watchpoint: 1,
+            displays: Vec::new(),
+            next_display: 1,
+            checkpoints: Checkpoints::new(),
+            json,
+            pager: Pager::new(),
+            background: false,
+            step_register_diff: false,
+            disassemble_next_line: false,
+            stop_on_syscall: false,
+            print_elements: DEFAULT_PRINT_ELEMENTS,
+            backtrace_past_main: false,
+            break_inline: false,
+            break_pending: None,
+            confirm: true,
+            follow_exec_mode: FollowExecMode::New,
+            value_history: Vec::new(),
+            convenience_vars: HashMap::new(),
+            selected_frame: 0,
+            snapshots: HashMap::new(),
+            environment: HashMap::new(),
+            inherit_environment: true,
+            pending_commands: VecDeque::new(),
+            script_lines: VecDeque::new(),
+            last_breakpoint: None,
+            last_watchpoint: None,
+        }
+    }
+
+    /// Checks that debugging symbols are loaded, printing the standard error if they aren't.
+    fn require_debug_info(&self) -> bool {
+        if !self.debug_data.has_debug_info() {
+            println!("{}", NO_DEBUG_INFO);
+        }
+        self.debug_data.has_debug_info()
+    }
+
+    /// Describes where `addr` is in the source, e.g. "main (foo.c:12)".
+    fn describe_addr(&self, addr: usize) -> String {
+        match (
+            self.debug_data.get_function_from_addr(addr),
+            self.debug_data.get_line_from_addr(addr),
+        ) {
+            (Some(func), Some(line)) => format!("{} ({})", func, line),
+            (Some(func), None) => func,
+            _ => "??".to_string(),
         }
     }
 
-    pub fn parse_addr(&self, addr: &str) -> Option<usize> {
+    /// Resolves a location: `0x<address>`, `<line>`, `+<offset>`/`-<offset>` from the current
+    /// line, `<function>`, `<function>+<offset>`/`<function>-<offset>` in bytes,
+    /// `<file>:<line>` or `<file>:<function>`. Files may be given by basename when that's
+    /// unambiguous.
+    pub fn parse_addr(&self, addr: &str) -> Result<usize, String> {
         if addr.to_lowercase().starts_with("0x") {
             // address
-            return usize::from_str_radix(&addr[2..], 16).ok();
-        } else if String::from(addr).parse::<usize>().is_ok() {
+            return usize::from_str_radix(&addr[2..], 16)
+                .map_err(|_| format!("invalid address {}", addr));
+        }
+        let (file, spec) = split_location(addr);
+        if let Some(file) = file {
+            match self.debug_data.matching_files(file).as_slice() {
+                [] => return Err(format!("no source file named {}", file)),
+                [_] => {}
+                candidates => {
+                    return Err(format!(
+                        "ambiguous file name \"{}\", could be: {}",
+                        file,
+                        candidates.join(", ")
+                    ))
+                }
+            }
+        }
+        if file.is_none() && (spec.starts_with('+') || spec.starts_with('-')) {
+            // line relative to the current one
+            let offset = spec
+                .parse::<isize>()
+                .map_err(|_| format!("invalid line offset {}", spec))?;
+            let current = self
+                .current_line()
+                .ok_or("no current line to be relative to; the program is not stopped")?;
+            let line_num = current.number as isize + offset;
+            if line_num < 1 {
+                return Err(format!("line offset {} goes past the start of the file", spec));
+            }
+            return self
+                .debug_data
+                .get_addr_for_line(Some(&current.file), line_num as usize)
+                .ok_or(format!("no line {} in {}", line_num, current.file));
+        }
+        if let Some((func_name, offset)) = split_offset(spec) {
+            // function plus an instruction offset, e.g. main+0x1c from objdump output
+            let bad_offset = || format!("invalid offset {} from {}", offset, func_name);
+            let magnitude = offset[1..].to_lowercase();
+            let magnitude = if magnitude.starts_with("0x") {
+                isize::from_str_radix(&magnitude[2..], 16).map_err(|_| bad_offset())?
+            } else {
+                magnitude.parse::<isize>().map_err(|_| bad_offset())?
+            };
+            let base = self
+                .debug_data
+                .get_addr_for_function(file, func_name)
+                .ok_or(format!("function \"{}\" not defined", func_name))?;
+            let addr = if offset.starts_with('-') {
+                base.checked_sub(magnitude as usize)
+            } else {
+                base.checked_add(magnitude as usize)
+            };
+            return addr.ok_or_else(bad_offset);
+        }
+        if let Ok(line_num) = spec.parse::<usize>() {
             // line number
-            let line_num = String::from(addr).parse::<usize>().expect("can not parse line number");
-            return self.debug_data.get_addr_for_line(None, line_num);
+            self.debug_data
+                .get_addr_for_line(file, line_num)
+                .ok_or(format!("no line {} in {}", line_num, file.unwrap_or("the default file")))
         } else {
             // function name
-            return self.debug_data.get_addr_for_function(None, addr);
+            self.debug_data
+                .get_addr_for_function(file, spec)
+                .ok_or(format!("function \"{}\" not defined", spec))
         }
     }
 
+    /// Returns the source line the stopped inferior is at.
+    fn current_line(&self) -> Option<Line> {
+        let inferior = self.inferior.as_ref().filter(|inf| inf.is_alive())?;
+        let rip = ptrace::getregs(inferior.tid()).ok()?.rip as usize;
+        self.debug_data.get_line_from_addr(rip)
+    }
+
+    /// Reports why the inferior stopped or ended, followed by the next instruction (with `set
+    /// disassemble-next-line on`) and the `display` expressions if it is still around.
     pub fn print_status(&self, status: Status) {
+        let ended = matches!(status, Status::Exited(_) | Status::Signaled(_));
+        self.report_status(status);
+        if !ended {
+            if self.disassemble_next_line {
+                self.print_next_instruction();
+            }
+            self.show_displays();
+        }
+    }
+
+    fn report_status(&self, status: Status) {
         match status {
             Status::Exited(exit_code) => {
                 println!("target exited (status {})", exit_code);
             }
             Status::Signaled(signal) => {
-                println!("target signaled(killed) by {}", signal.as_str());
+                println!(
+                    "target signaled(killed) by {} ({})",
+                    signal.as_str(),
+                    signal_description(signal)
+                );
+            }
+            Status::Execed => match self.find_catchpoint(|catch| matches!(catch, Catch::Exec)) {
+                Some(number) => {
+                    let path = self.inferior.as_ref().and_then(|inf| inf.exe_path().ok());
+                    let path = path.map_or("?".to_string(), |path| path.display().to_string());
+                    println!("Catchpoint {} (exec'd {})", number, path);
+                }
+                None => println!("target is executing a new program"),
+            },
+            Status::Forked(child, vfork) => {
+                println!(
+                    "Catchpoint {} ({}, process {})",
+                    self.fork_catchpoint(vfork).unwrap_or(0),
+                    if vfork { "vforked" } else { "forked" },
+                    child
+                );
+            }
+            // should_stop always resumes past these
+            Status::VforkDone => {}
+            Status::SyscallEntry(number, args) => {
+                let args: Vec<String> = args.iter().map(|arg| format!("{:#x}", arg)).collect();
+                match self.catchpoint_for_syscall(number) {
+                    Some(catchpoint) => println!(
+                        "Catchpoint {} (call to syscall {}), arguments ({})",
+                        catchpoint,
+                        syscall_name(number),
+                        args.join(", ")
+                    ),
+                    // Stopped by `set stop-on-syscall`
+                    None => {
+                        println!("syscall entry: {}({})", syscall_name(number), args.join(", "))
+                    }
+                }
+            }
+            Status::SyscallExit(number, ret) => match self.catchpoint_for_syscall(number) {
+                Some(catchpoint) => println!(
+                    "Catchpoint {} (returned from syscall {}), result {}",
+                    catchpoint,
+                    syscall_name(number),
+                    ret
+                ),
+                None => println!("syscall exit: {} = {}", syscall_name(number), ret),
+            },
+            Status::Stopped(Signal::SIGTRAP, rip)
+                if self.breakpoints.find_by_addr(rip).is_none()
+                    && self.inferior.as_ref().map_or(false, |inf| inf.hit_foreign_int3(rip)) =>
+            {
+                // %rip is left just past the program's int3, so continuing carries on from there
+                println!(
+                    "Program received signal SIGTRAP (not a debugger breakpoint) at {:#x} in {}",
+                    rip,
+                    self.describe_addr(rip)
+                );
+            }
+            Status::Stopped(Signal::SIGTRAP, rip) if self.last_watchpoint.is_some() => {
+                let (number, old) = self.last_watchpoint.unwrap();
+                let wp = self.watchpoints.iter().find(|wp| wp.number == number).unwrap();
+                let show = |value: Option<u64>| value.map_or("??".to_string(), |v| v.to_string());
+                // DR6 only says which debug register fired, so for an access watchpoint a changed
+                // value is what marks the access as a write
+                let written = wp.kind == WatchKind::Write || old != wp.value;
+                let access = match wp.kind {
+                    WatchKind::Access if written => " (write)",
+                    WatchKind::Access => " (read)",
+                    _ => "",
+                };
+                let title = if wp.software {
+                    "Watchpoint".to_string()
+                } else {
+                    format!("Hardware {}", watchpoint_kind(wp.kind))
+                };
+                println!("\n{} {}: {}{}\n", title, number, wp.expr, access);
+                if written {
+                    println!("Old value = {}", show(old));
+                    println!("New value = {}", show(wp.value));
+                } else {
+                    println!("Value = {}", show(wp.value));
+                }
+                println!("{:#x} in {}", rip, self.describe_addr(rip));
+            }
+            Status::Stopped(Signal::SIGTRAP, rip)
+                if self.last_breakpoint.map_or(false, |(_, addr)| addr == rip) =>
+            {
+                let number = self.last_breakpoint.unwrap().0;
+                match (
+                    self.debug_data.get_function_from_addr(rip),
+                    self.debug_data.get_line_from_addr(rip),
+                ) {
+                    (Some(func), Some(line)) => {
+                        println!("Breakpoint {}, {} () at {}", number, func, line)
+                    }
+                    _ => {
+                        println!("Breakpoint {}, {:#x} in {}", number, rip, self.describe_addr(rip))
+                    }
+                }
+            }
+            Status::Stopped(signal, rip) if self.catchpoint_for_signal(signal).is_some() => {
+                println!(
+                    "Catchpoint {} (signal {}), {:#x} in {}",
+                    self.catchpoint_for_signal(signal).unwrap(),
+                    signal.as_str(),
+                    rip,
+                    self.describe_addr(rip)
+                );
             }
             Status::Stopped(signal, rip) => {
                 println!(
-                    "target stopped at {:#x} by signal {} in {} ({})",
+                    "target stopped at {:#x} by signal {} ({}) in {}",
                     rip,
                     signal.as_str(),
-                    self.debug_data.get_function_from_addr(rip).unwrap(),
-                    self.debug_data.get_line_from_addr(rip).unwrap()
+                    signal_description(signal),
+                    self.describe_addr(rip)
                 );
+                if signal == Signal::SIGSEGV {
+                    if let Some(Ok(addr)) = self.inferior.as_ref().map(|inf| inf.get_fault_addr()) {
+                        println!("invalid memory access at address {:#x}", addr);
+                    }
+                }
             }
         }
     }
 
-    pub fn run(&mut self) {
-        loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    // make sure no previous target exists
-                    if self.inferior.is_some() {
-                        match self.inferior.as_mut().unwrap().terminate() {
-                            Ok(status) => self.print_status(status),
-                            Err(err) => println!("failed to terminate previous target, {}", err),
-                        }
-                    }
+    /// Adds a breakpoint for `location` at `addr`, installing it right away if the program is
+    /// running. Hardware breakpoints are refused once all the debug registers are taken. Returns
+    /// the new breakpoint's number, if it was set.
+    fn set_breakpoint(
+        &mut self,
+        location: &str,
+        addr: usize,
+        count: Option<usize>,
+        hardware: bool,
+    ) -> Option<usize> {
+        if let Err(err) = self.check_executable(addr) {
+            println!("{}", err);
+            return None;
+        }
+        if hardware
+            && self.debug_registers_in_use() >= HW_BREAKPOINT_SLOTS
+            && !self.breakpoints.addrs(true).contains(&addr)
+        {
+            println!(
+                "cannot use more than {} hardware breakpoints and watchpoints; delete or disable \
+                 one first",
+                HW_BREAKPOINT_SLOTS
+            );
+            return None;
+        }
+        let number = self.breakpoints.add(addr, location);
+        self.convenience_vars.insert("bpnum".to_string(), number as i64);
+        let bp = self.breakpoints.get_mut(number).unwrap();
+        bp.delete_after = count;
+        bp.hardware = hardware;
+        for inferior in self.live_inferiors() {
+            // inferior is running, add breakpoint
+            install_trap(inferior, addr, hardware);
+        }
+        if self.json {
+            println!(
+                "{}",
+                json::event(
+                    "breakpoint-created",
+                    vec![
+                        ("number", JsonValue::Number(number as i64)),
+                        ("location", JsonValue::Str(location.to_string())),
+                        ("address", JsonValue::Str(format!("{:#x}", addr))),
+                    ]
+                )
+            );
+        } else {
+            let kind = if hardware { "hardware breakpoint" } else { "breakpoint" };
+            println!("set {} {} at position {:#x}", kind, number, addr);
+        }
+        if let Some(note) = self.check_breakpoint_function(location, addr) {
+            println!("{}", note);
+        }
+        Some(number)
+    }
 
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &self.breakpoints) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        match self.inferior.as_mut().unwrap().cont() {
-                            Ok(status) => self.print_status(status),
-                            Err(err) => {
-                                println!("failed to run command, {}", err);
-                            }
-                        }
-                    } else {
-                        println!("Error starting subprocess");
-                    }
-                }
-                DebuggerCommand::Continue => {
-                    if self.inferior.is_none() {
-                        println!("please run target first");
-                        continue;
-                    }
-                    // check if inferior is stopped at a breakpoint
-                    let inf_ref = self.inferior.as_mut().unwrap();
-                    let mut regs = ptrace::getregs(inf_ref.pid()).expect("can not read registers");
-                    let rip = regs.rip - 1;
-                    if inf_ref.replaced_values.contains_key(&(rip as usize)) {
-                        // this is a breakpoint, resume original byte
-                        let val = inf_ref.replaced_values.get(&(rip as usize)).unwrap();
-                        let trap_byte = inf_ref.write_byte(rip as usize, *val).expect("can not resume original byte");
-                        if trap_byte != 0xcc {
-                            panic!("failed to resume original byte");
-                        }
-                        regs.rip = rip;
-                        ptrace::setregs(inf_ref.pid(), regs).expect("can not set %rip");
+    /// Makes sure `addr` is in executable code, so that a mistyped address can't have a trap
+    /// written over the program's data. A running process is checked against its memory map,
+    /// which includes shared libraries; otherwise the ELF program headers are used.
+    fn check_executable(&self, addr: usize) -> Result<(), String> {
+        let executable = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => inferior.is_executable(addr).unwrap_or(true),
+            None => self.elf.as_ref().map_or(true, |elf| elf.is_executable(addr)),
+        };
+        if executable {
+            Ok(())
+        } else {
+            Err(format!(
+                "cannot set breakpoint at {:#x}: address is not in an executable segment",
+                addr
+            ))
+        }
+    }
 
-                        // step a intruction and reinstall breakpoint
-                        ptrace::step(inf_ref.pid(), None).expect("can not step target");
-                        inf_ref.wait(None).expect("can not stop after stepping");
-                        inf_ref.write_byte(rip as usize, 0xcc).expect("can not reinstall breakpoint");
-                    }
+    /// Handles a `break` whose location didn't resolve: depending on `set breakpoint pending`,
+    /// the breakpoint is kept as pending, to be resolved when new symbols are loaded, or refused.
+    /// The symbols only change when the program execs; shared libraries aren't loaded yet.
+    fn set_pending_breakpoint(&mut self, location: &str, err: String, count: Option<usize>) {
+        println!("{}", err);
+        let pending = match self.break_pending {
+            Some(pending) => pending,
+            None => self.query("Make breakpoint pending on future load?"),
+        };
+        if !pending {
+            return;
+        }
+        let number = self.breakpoints.add(0, location);
+        self.convenience_vars.insert("bpnum".to_string(), number as i64);
+        let bp = self.breakpoints.get_mut(number).unwrap();
+        bp.delete_after = count;
+        bp.pending = true;
+        println!("breakpoint {} ({}) pending", number, location);
+    }
 
-                    match self.inferior.as_mut().unwrap().cont() {
-                        Ok(status) => self.print_status(status),
-                        Err(err) => {
-                            println!("failed to run command, {}", err);
-                        }
-                    }
-                }
-                DebuggerCommand::BackTrace => {
-                    let _ = self
-                        .inferior
-                        .as_mut()
-                        .unwrap()
-                        .print_backtrace(&self.debug_data);
-                }
-                DebuggerCommand::Breakpoint(s) => {
-                    match self.parse_addr(&s) {
-                        Some(addr) => {
-                            self.breakpoints.push(addr);
-                            if self.inferior.is_some() {
-                                // inferior is running, add breakpoint
-                                match self.inferior.as_mut().unwrap().write_byte(addr, 0xcc) {
-                                    Ok(_) => {}
-                                    Err(err) => println!(
-                                        "failed to set breakpoint at position {:#x}, {}",
-                                        addr, err
-                                    ),
-                                }
-                            }
-                            println!(
-                                "set breakpoint {} at position {:#x}",
-                                self.breakpoints.len() - 1,
-                                addr
-                            );
-                        }
-                        None => println!("invalid breakpoint format"),
-                    };
+    /// If `location` names a function (optionally as file:function), returns the function name
+    /// and the addresses where it was inlined.
+    fn inline_sites(&self, location: &str) -> (String, Vec<usize>) {
+        let (file, spec) = split_location(location);
+        let ambiguous = file.map_or(false, |file| self.debug_data.matching_files(file).len() != 1);
+        if !is_function_spec(spec) || ambiguous {
+            return (spec.to_string(), Vec::new());
+        }
+        (spec.to_string(), self.debug_data.get_inline_sites(file, spec))
+    }
+
+    /// Checks that a breakpoint requested at a function really landed in that function, which
+    /// resolution mistakes could otherwise hide until a confusing stop. Returns a note if not.
+    fn check_breakpoint_function(&self, location: &str, addr: usize) -> Option<String> {
+        let (_, spec) = split_location(location);
+        if !is_function_spec(spec) {
+            return None;
+        }
+        let actual = self.debug_data.get_function_from_addr(addr)?;
+        if actual == spec {
+            return None;
+        }
+        Some(format!("Note: breakpoint at {:#x} is in {}, not {}", addr, actual, spec))
+    }
+
+    /// Writes every breakpoint into a freshly started inferior, and puts the watchpoints in its
+    /// debug registers.
+    fn install_breakpoints(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        for hardware in [false, true].iter() {
+            for addr in self.breakpoints.addrs(*hardware) {
+                install_trap(inferior, addr, *hardware);
+            }
+        }
+        // Locals of the old process are long gone
+        self.watchpoints.retain(|wp| wp.scope.is_none());
+        for wp in self.watchpoints.iter().filter(|wp| !wp.software) {
+            install_watch(inferior, wp);
+        }
+    }
+
+    /// Returns how many debug registers the hardware breakpoints and watchpoints need.
+    fn debug_registers_in_use(&self) -> usize {
+        self.breakpoints.addrs(true).len()
+            + self.watchpoints.iter().filter(|wp| !wp.software).count()
+    }
+
+    /// Watches a variable or address for writes (`watch`), reads (`rwatch`) or both (`awatch`).
+    /// A read watchpoint can't see a read by an instruction that also changes the value, e.g.
+    /// `x++`. Variables must be 1, 2, 4 or 8 bytes, which is what a debug register can cover; an
+    /// address is watched for the widest of those it is aligned to. A local variable is watched
+    /// at its address in the selected frame until that frame returns.
+    fn set_watchpoint(&mut self, expr: &str, kind: WatchKind) {
+        let software = self.debug_registers_in_use() >= HW_BREAKPOINT_SLOTS;
+        if software && kind != WatchKind::Write {
+            println!(
+                "all {} debug registers are in use, and only write watchpoints can do without \
+                 one; delete or disable a hardware breakpoint or watchpoint first",
+                HW_BREAKPOINT_SLOTS
+            );
+            return;
+        }
+        let (addr, len, scope) = match self.watch_location(expr) {
+            Ok(location) => location,
+            Err(err) => {
+                println!("cannot watch {}: {}", expr, err);
+                return;
+            }
+        };
+        let number = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        let wp = Watchpoint {
+            number,
+            expr: expr.to_string(),
+            addr,
+            len,
+            kind,
+            value: self.read_watched(addr, len),
+            software,
+            scope,
+        };
+        if let Some((return_addr, _)) = scope {
+            // Catches the frame returning
+            install_trap(self.inferior.as_mut().unwrap(), return_addr, false);
+        }
+        if software {
+            println!(
+                "Watchpoint {}: {} (no debug register is free, so the program will be \
+                 single-stepped and run much more slowly)",
+                number, expr
+            );
+        } else {
+            for inferior in self.live_inferiors() {
+                install_watch(inferior, &wp);
+            }
+            println!("Hardware {} {}: {}", watchpoint_kind(kind), number, expr);
+        }
+        self.watchpoints.push(wp);
+    }
+
+    /// Resolves what `watch` was given to the address and length to watch, and for a local
+    /// variable, the return address and canonical frame address of the selected frame.
+    fn watch_location(&self, expr: &str) -> Result<(usize, usize, Option<(usize, usize)>), String> {
+        if expr.to_lowercase().starts_with("0x") {
+            let addr = usize::from_str_radix(&expr[2..], 16)
+                .map_err(|_| format!("invalid address {}", expr))?;
+            let len = [8, 4, 2, 1].iter().find(|&&len| addr % len == 0).unwrap();
+            return Ok((addr, *len, None));
+        }
+        if !self.debug_data.has_debug_info() {
+            return Err(NO_DEBUG_INFO.to_string());
+        }
+        let mut scope = None;
+        let (var, addr) = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => {
+                let regs = self.frame_regs()?;
+                let var = self
+                    .debug_data
+                    .lookup_variable(regs.rip as usize, expr)
+                    .ok_or(format!("no symbol \"{}\" in current context", expr))?;
+                if let Location::FramePointerOffset(_) = var.location {
+                    // The return address sits just above the saved %rbp
+                    let return_addr = inferior
+                        .read_memory(regs.rbp as usize + 8, 8)
+                        .map_err(|err| format!("cannot read the return address, {}", err))?;
+                    let mut buf = [0u8; 8];
+                    buf.copy_from_slice(&return_addr);
+                    scope = Some((u64::from_le_bytes(buf) as usize, regs.rbp as usize + 16));
                 }
-                DebuggerCommand::Quit => {
-                    match self.inferior.as_mut().unwrap().terminate() {
-                        Ok(status) => self.print_status(status),
-                        Err(err) => {
-                            println!("failed to terminate target, {}", err);
-                        }
-                    }
-                    return;
+                (var, self.variable_addr(&var.location, &regs)?)
+            }
+            None => {
+                let var = self
+                    .debug_data
+                    .lookup_global(expr)
+                    .ok_or(format!("no global \"{}\"; run the program to watch locals", expr))?;
+                match var.location {
+                    Location::Address(addr) => (var, addr),
+                    _ => return Err("run the program first".to_string()),
                 }
             }
+        };
+        let len = var.entity_type.size;
+        if ![1, 2, 4, 8].contains(&len) || addr % len != 0 {
+            return Err(format!(
+                "a debug register only covers 1, 2, 4 or 8 aligned bytes, and \"{}\" is {} \
+                 bytes at {:#x}",
+                expr, len, addr
+            ));
         }
+        Ok((addr, len, scope))
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// Reads the `len` bytes a watchpoint covers, from the running program or else from the
+    /// executable.
+    fn read_watched(&self, addr: usize, len: usize) -> Option<u64> {
+        let bytes = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => inferior.read_memory(addr, len).ok()?,
+            None => self.elf.as_ref()?.read(addr, len)?,
+        };
+        let mut buf = [0u8; 8];
+        buf[..len].copy_from_slice(&bytes);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    /// Checks whether a watchpoint caused the SIGTRAP the inferior just stopped with, or for
+    /// software watchpoints, whether the value changed. If so, the watchpoint's value is
+    /// brought up to date and whether it stops the program is returned: read watchpoints trap
+    /// on writes too, which are recognized by the value changing.
+    fn watchpoint_hit(&mut self) -> Option<bool> {
+        let triggered = self.inferior.as_ref()?.triggered_watchpoints().ok()?;
+        let index = match triggered.first() {
+            Some(slot) => self.watchpoints.iter().position(|wp| {
+                !wp.software
+                    && wp.addr == slot.addr
+                    && wp.len == slot.len
+                    && wp.access() == slot.access
+            })?,
+            None => self.changed_software_watchpoint()?,
+        };
+        let (addr, len) = (self.watchpoints[index].addr, self.watchpoints[index].len);
+        let value = self.read_watched(addr, len);
+        let wp = &mut self.watchpoints[index];
+        let old = std::mem::replace(&mut wp.value, value);
+        if wp.kind == WatchKind::Read && old != value {
+            return Some(false);
+        }
+        self.last_watchpoint = Some((wp.number, old));
+        Some(true)
+    }
+
+    /// Returns the index of the first software watchpoint whose location no longer holds the
+    /// value last seen there.
+    fn changed_software_watchpoint(&self) -> Option<usize> {
+        self.watchpoints
+            .iter()
+            .position(|wp| wp.software && self.read_watched(wp.addr, wp.len) != wp.value)
+    }
+
+    /// Single-steps the inferior until a software watchpoint's value changes, it reaches a
+    /// breakpoint, or it stops for some other reason, and returns that stop.
+    fn step_watching(&mut self) -> Result<Status, nix::Error> {
         loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
+            let status = self.inferior.as_mut().unwrap().step()?;
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip,
+                status => return Ok(status),
+            };
+            if self.inferior.as_ref().unwrap().replaced_values.contains_key(&rip)
+                || self.breakpoints.find_by_addr(rip).is_some()
+                || self.changed_software_watchpoint().is_some()
+            {
+                return Ok(status);
+            }
+        }
+    }
+
+    fn print_watchpoints(&self) {
+        if self.watchpoints.is_empty() {
+            println!("No watchpoints.");
+            return;
+        }
+        let mut lines = vec![format!(
+            "{:<8}{:<17}{:<19}{:<6}{}",
+            "Num", "Type", "Address", "Bytes", "What"
+        )];
+        for wp in &self.watchpoints {
+            lines.push(format!(
+                "{:<8}{:<17}{:<19}{:<6}{}",
+                wp.number,
+                match wp.kind {
+                    WatchKind::Write if wp.software => "sw watchpoint",
+                    WatchKind::Write => "hw watchpoint",
+                    WatchKind::Read => "read watchpoint",
+                    WatchKind::Access => "acc watchpoint",
+                },
+                format!("{:#x}", wp.addr),
+                wp.len,
+                wp.expr
+            ));
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Handles every form of `delete`. Without numbers, everything of the given kind is deleted
+    /// once the user confirms.
+    fn delete(&mut self, target: DeleteTarget, numbers: Vec<usize>) {
+        let (kind, existing): (&str, Vec<usize>) = match target {
+            DeleteTarget::Breakpoints => {
+                ("breakpoint", self.breakpoints.iter().map(|bp| bp.number).collect())
+            }
+            DeleteTarget::Catchpoints => {
+                ("catchpoint", self.catchpoints.iter().map(|cp| cp.number).collect())
+            }
+            DeleteTarget::Watchpoints => {
+                ("watchpoint", self.watchpoints.iter().map(|wp| wp.number).collect())
+            }
+            DeleteTarget::Checkpoints => (
+                "checkpoint",
+                self.checkpoints.iter().map(|checkpoint| checkpoint.number).collect(),
+            ),
+            DeleteTarget::Displays => {
+                ("display", self.displays.iter().map(|display| display.number).collect())
+            }
+        };
+        let numbers = if numbers.is_empty() {
+            if existing.is_empty() {
+                println!("nothing to delete");
+                return;
+            }
+            if !self.query(&format!("Delete all {}s?", kind)) {
+                return;
+            }
+            existing.clone()
+        } else {
+            numbers
+        };
+        for number in numbers {
+            if !existing.contains(&number) {
+                println!("no {} number {}", kind, number);
+                continue;
+            }
+            match target {
+                DeleteTarget::Breakpoints => {
+                    let bp = self.breakpoints.remove(number).unwrap();
+                    self.uninstall_breakpoint(bp.addr, bp.hardware);
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                DeleteTarget::Catchpoints => self.catchpoints.retain(|cp| cp.number != number),
+                DeleteTarget::Watchpoints => self.remove_watchpoint(number),
+                DeleteTarget::Checkpoints => {
+                    self.checkpoints.remove(number);
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                DeleteTarget::Displays => self.displays.retain(|display| display.number != number),
+            }
+        }
+    }
+
+    /// Deletes watchpoint `number`, freeing its debug register and the trap watching for its
+    /// frame to return.
+    fn remove_watchpoint(&mut self, number: usize) {
+        let index = self.watchpoints.iter().position(|wp| wp.number == number).unwrap();
+        let wp = self.watchpoints.remove(index);
+        if let Some((return_addr, _)) = wp.scope {
+            self.uninstall_breakpoint(return_addr, false);
+        }
+        if wp.software {
+            return;
+        }
+        for inferior in self.live_inferiors() {
+            if let Err(err) = inferior.remove_watchpoint(wp.addr, wp.len, wp.access()) {
+                println!("failed to remove watchpoint {}, {}", number, err);
+            }
+        }
+    }
+
+    /// Deletes the watchpoints on locals of a frame that has just returned to `rip`. Returns
+    /// whether there were any.
+    fn end_watch_scopes(&mut self, rip: usize) -> bool {
+        let rsp = match self.inferior.as_ref().map(|inf| ptrace::getregs(inf.tid())) {
+            Some(Ok(regs)) => regs.rsp as usize,
+            _ => return false,
+        };
+        // The frame is gone once %rsp is back above it; a deeper recursive call returning to
+        // the same address leaves it lower
+        let ended: Vec<usize> = self
+            .watchpoints
+            .iter()
+            .filter(|wp| wp.scope.map_or(false, |(addr, cfa)| addr == rip && rsp >= cfa))
+            .map(|wp| wp.number)
+            .collect();
+        for number in &ended {
+            println!(
+                "\nWatchpoint {} deleted because the program has left the block in which its \
+                 expression is valid.",
+                number
+            );
+            self.remove_watchpoint(*number);
+        }
+        !ended.is_empty()
+    }
+
+    /// Adds a catchpoint for the named or numbered syscalls, or for all of them.
+    fn catch_syscalls(&mut self, names: &[String]) {
+        let mut numbers = Vec::new();
+        for name in names {
+            match name.parse::<u64>().ok().or_else(|| syscalls::number(name)) {
+                Some(number) => numbers.push(number),
+                None => {
+                    println!("unknown syscall name '{}'", name);
+                    return;
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
-                    }
+            }
+        }
+        let number = self.next_catchpoint;
+        self.next_catchpoint += 1;
+        if numbers.is_empty() {
+            println!("Catchpoint {} (any syscall)", number);
+        } else {
+            let names: Vec<String> = numbers.iter().map(|n| syscall_name(*n)).collect();
+            println!("Catchpoint {} (syscall {})", number, names.join(" "));
+        }
+        self.catchpoints.push(Catchpoint {
+            number,
+            catch: Catch::Syscall(numbers),
+        });
+    }
+
+    /// Returns the number of the first catchpoint whose event satisfies `caught`.
+    fn find_catchpoint<F: Fn(&Catch) -> bool>(&self, caught: F) -> Option<usize> {
+        self.catchpoints.iter().find(|cp| caught(&cp.catch)).map(|cp| cp.number)
+    }
+
+    /// Returns the number of the first catchpoint that catches syscall `number`.
+    fn catchpoint_for_syscall(&self, number: u64) -> Option<usize> {
+        self.find_catchpoint(|catch| match catch {
+            Catch::Syscall(numbers) => numbers.is_empty() || numbers.contains(&number),
+            _ => false,
+        })
+    }
+
+    /// Returns the number of the first catchpoint for forks, or for vforks if `vfork` is set.
+    fn fork_catchpoint(&self, vfork: bool) -> Option<usize> {
+        self.find_catchpoint(|catch| match catch {
+            Catch::Fork => !vfork,
+            Catch::VFork => vfork,
+            _ => false,
+        })
+    }
+
+    /// Adds a catchpoint for a fork, vfork or exec.
+    fn catch_event(&mut self, catch: Catch) {
+        let number = self.next_catchpoint;
+        self.next_catchpoint += 1;
+        let event = match catch {
+            Catch::Fork => "fork",
+            Catch::VFork => "vfork",
+            _ => "exec",
+        };
+        println!("Catchpoint {} ({})", number, event);
+        self.catchpoints.push(Catchpoint { number, catch });
+    }
+
+    /// Adds a catchpoint for the named signals (`SIGSEGV` or just `SEGV`), or for all of them.
+    fn catch_signals(&mut self, names: &[String]) {
+        let mut signals = Vec::new();
+        for name in names {
+            let full_name = if name.starts_with("SIG") {
+                name.to_string()
+            } else {
+                format!("SIG{}", name)
+            };
+            match full_name.parse::<Signal>() {
+                Ok(signal) => signals.push(signal),
+                Err(_) => {
+                    println!("unknown signal name '{}'", name);
+                    return;
                 }
             }
         }
+        let number = self.next_catchpoint;
+        self.next_catchpoint += 1;
+        if signals.is_empty() {
+            println!("Catchpoint {} (standard signals)", number);
+        } else {
+            let names: Vec<&str> = signals.iter().map(|signal| signal.as_str()).collect();
+            println!("Catchpoint {} (signal {})", number, names.join(" "));
+        }
+        self.catchpoints.push(Catchpoint {
+            number,
+            catch: Catch::Signal(signals),
+        });
+    }
+
+    /// Returns the number of the first catchpoint that catches `signal`. SIGTRAP is ours, so
+    /// it is never caught.
+    fn catchpoint_for_signal(&self, signal: Signal) -> Option<usize> {
+        if signal == Signal::SIGTRAP {
+            return None;
+        }
+        self.find_catchpoint(|catch| match catch {
+            Catch::Signal(signals) => signals.is_empty() || signals.contains(&signal),
+            _ => false,
+        })
+    }
+
+    /// Enables or disables the numbered breakpoints (every breakpoint if `numbers` is empty),
+    /// installing or removing the traps in a running inferior to match. The breakpoints keep
+    /// their locations, conditions and counts.
+    fn set_enabled(&mut self, numbers: &[usize], enable: bool) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        for number in numbers {
+            if self.breakpoints.get(*number).is_none() {
+                println!("no breakpoint number {}", number);
+            }
+        }
+        let changed: Vec<(usize, usize, bool, bool)> = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.enabled != enable)
+            .filter(|bp| numbers.is_empty() || numbers.contains(&bp.number))
+            .map(|bp| (bp.number, bp.addr, bp.hardware, bp.pending))
+            .collect();
+        for (number, addr, hardware, pending) in changed {
+            if enable
+                && hardware
+                && !pending
+                && self.debug_registers_in_use() >= HW_BREAKPOINT_SLOTS
+                && !self.breakpoints.addrs(true).contains(&addr)
+            {
+                println!(
+                    "cannot enable breakpoint {}: all {} debug registers are in use",
+                    number, HW_BREAKPOINT_SLOTS
+                );
+                continue;
+            }
+            self.breakpoints.get_mut(number).unwrap().enabled = enable;
+            if pending {
+                continue;
+            }
+            if !enable {
+                self.uninstall_breakpoint(addr, hardware);
+                continue;
+            }
+            let mut installed = true;
+            for inferior in self.live_inferiors() {
+                installed &= install_trap(inferior, addr, hardware);
+            }
+            if !installed {
+                // Leave it disabled rather than claim a breakpoint some inferior doesn't have
+                self.breakpoints.get_mut(number).unwrap().enabled = false;
+                self.uninstall_breakpoint(addr, hardware);
+            }
+        }
+        if numbers.is_empty() {
+            println!("All breakpoints {}.", if enable { "enabled" } else { "disabled" });
+        }
+    }
+
+    /// Takes the trap at `addr` (or the debug register watching it) out of the inferior, unless
+    /// another enabled breakpoint of the same kind still uses it.
+    fn uninstall_breakpoint(&mut self, addr: usize, hardware: bool) {
+        if self.breakpoints.addrs(hardware).contains(&addr) {
+            return;
+        }
+        let watching_return = |wp: &Watchpoint| wp.scope.map_or(false, |(ret, _)| ret == addr);
+        if !hardware && self.watchpoints.iter().any(watching_return) {
+            return;
+        }
+        for inferior in self.live_inferiors() {
+            let removed = if hardware {
+                inferior.remove_hw_breakpoint(addr)
+            } else {
+                inferior.remove_breakpoint(addr)
+            };
+            if let Err(err) = removed {
+                println!("failed to remove breakpoint at position {:#x}, {}", addr, err);
+            }
+        }
+    }
+
+    /// Returns the inferiors whose process is still alive, the current one first. Breakpoints
+    /// are shared, so they are installed in all of these.
+    fn live_inferiors(&mut self) -> impl Iterator<Item = &mut Inferior> {
+        self.inferior
+            .iter_mut()
+            .chain(self.inferiors.iter_mut().flatten())
+            .filter(|inferior| inferior.is_alive())
+    }
+
+    /// Makes inferior `number` the current one.
+    fn switch_inferior(&mut self, number: usize) {
+        if number == 0 || number > self.inferiors.len() {
+            println!("no inferior number {}", number);
+            return;
+        }
+        self.inferiors[self.current_inferior - 1] = self.inferior.take();
+        self.inferior = self.inferiors[number - 1].take();
+        self.current_inferior = number;
+        self.selected_frame = 0;
+        match &self.inferior {
+            Some(inferior) => {
+                println!("[Switching to inferior {} (process {})]", number, inferior.pid())
+            }
+            None => println!("[Switching to inferior {} (no process)]", number),
+        }
+    }
+
+    /// Lists the inferiors, marking the current one.
+    fn print_inferiors(&self) {
+        let mut lines = vec![format!("  {:<4} {:<20} {}", "Num", "Description", "Executable")];
+        for number in 1..=self.inferiors.len() {
+            let inferior = if number == self.current_inferior {
+                self.inferior.as_ref()
+            } else {
+                self.inferiors[number - 1].as_ref()
+            };
+            let description = match inferior {
+                Some(inferior) if inferior.is_alive() => format!("process {}", inferior.pid()),
+                _ => "<null>".to_string(),
+            };
+            let marker = if number == self.current_inferior { '*' } else { ' ' };
+            lines.push(format!("{} {:<4} {:<20} {}", marker, number, description, self.target));
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Lists the threads of the current inferior, marking the one whose registers are shown.
+    fn print_threads(&self) {
+        let inferior = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => inferior,
+            None => {
+                println!("No threads.");
+                return;
+            }
+        };
+        let mut lines = vec![format!("  {:<8} {}", "Id", "Where")];
+        for thread in inferior.threads() {
+            let place = match ptrace::getregs(thread) {
+                Ok(regs) => self.describe_addr(regs.rip as usize),
+                Err(err) => format!("<{}>", err),
+            };
+            let marker = if thread == inferior.tid() { '*' } else { ' ' };
+            lines.push(format!("{} {:<8} {}", marker, thread, place));
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Forks the stopped inferior into a new checkpoint.
+    fn checkpoint(&mut self) {
+        let inferior = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        if inferior.in_syscall() {
+            println!("cannot take a checkpoint in the middle of a system call");
+            return;
+        }
+        let taken = inferior.checkpoint().and_then(|pid| {
+            let rip = ptrace::getregs(inferior.pid())?.rip as usize;
+            Ok((pid, rip))
+        });
+        match taken {
+            Ok((pid, rip)) => {
+                let number = self.checkpoints.add(pid, rip);
+                println!("checkpoint {}: fork returned pid {}", number, pid);
+            }
+            Err(err) => println!("failed to take checkpoint, {}", err),
+        }
+    }
+
+    /// Replaces the inferior with a fresh copy of checkpoint `number`, so the program carries
+    /// on from there. The checkpoint is kept, to go back to again.
+    fn restore_checkpoint(&mut self, number: usize) {
+        let (pid, rip) = match self.checkpoints.get(number) {
+            Some(checkpoint) => (checkpoint.pid, checkpoint.rip),
+            None => {
+                println!("no checkpoint number {}", number);
+                return;
+            }
+        };
+        let inferior = match Inferior::from_checkpoint(pid) {
+            Ok(inferior) => inferior,
+            Err(err) => {
+                println!("failed to restore checkpoint {}, {}", number, err);
+                return;
+            }
+        };
+        if let Some(old) = self.inferior.as_mut().filter(|inf| inf.is_alive()) {
+            let _ = old.terminate();
+        }
+        self.inferior = Some(inferior);
+        self.install_breakpoints();
+        self.selected_frame = 0;
+        println!("Switching to checkpoint {}, {:#x} in {}", number, rip, self.describe_addr(rip));
+    }
+
+    fn print_checkpoints(&self) {
+        if self.checkpoints.iter().next().is_none() {
+            println!("No checkpoints.");
+            return;
+        }
+        let mut lines = vec![format!("{:<8}{:<12}{}", "Num", "Process", "Where")];
+        for checkpoint in self.checkpoints.iter() {
+            lines.push(format!(
+                "{:<8}{:<12}{:#x} in {}",
+                checkpoint.number,
+                checkpoint.pid,
+                checkpoint.rip,
+                self.describe_addr(checkpoint.rip)
+            ));
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Handles the inferior exec'ing a new program. The old image's breakpoints went away with
+    /// it, so they are installed again: at the same addresses in `same` mode, or re-resolved
+    /// against the new program's symbols in `new` mode.
+    fn follow_exec(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior.replaced_values.clear();
+        inferior.debug_slots = [None; HW_BREAKPOINT_SLOTS];
+        let pid = inferior.pid();
+        let path = match inferior.exe_path() {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(err) => {
+                println!("process {} is executing a program that can't be found: {}", pid, err);
+                return;
+            }
+        };
+        let mode = match self.follow_exec_mode {
+            FollowExecMode::New => "new",
+            FollowExecMode::Same => "same",
+        };
+        println!(
+            "process {} is executing new program: {} (follow-exec-mode {})",
+            pid, path, mode
+        );
+        if self.follow_exec_mode == FollowExecMode::New {
+            match DwarfData::from_file(&path) {
+                Ok(debug_data) => {
+                    self.debug_data = debug_data;
+                    self.elf = ElfInfo::from_file(&path);
+                }
+                Err(err) => println!("could not load debugging symbols from {}: {:?}", path, err),
+            }
+            self.reresolve_breakpoints();
+        }
+        self.install_breakpoints();
+    }
+
+    /// Resolves every breakpoint's location again after the symbols have changed. Breakpoints
+    /// whose location no longer exists are deleted, while pending ones stay pending.
+    fn reresolve_breakpoints(&mut self) {
+        let resolved: Vec<(usize, bool, Result<usize, String>)> = self
+            .breakpoints
+            .iter()
+            .map(|bp| (bp.number, bp.pending, self.parse_addr(&bp.location)))
+            .collect();
+        for (number, pending, addr) in resolved {
+            match addr {
+                Ok(addr) => {
+                    let bp = self.breakpoints.get_mut(number).unwrap();
+                    bp.addr = addr;
+                    bp.pending = false;
+                    if pending {
+                        println!("pending breakpoint {} resolved to {:#x}", number, addr);
+                    }
+                }
+                Err(_) if pending => {}
+                Err(err) => {
+                    println!(
+                        "deleting breakpoint {}, which can't be set in the new program: {}",
+                        number, err
+                    );
+                    self.breakpoints.remove(number);
+                }
+            }
+        }
+    }
+
+    /// Gets the inferior ready to resume, stepping over the breakpoint it is stopped at if there
+    /// is one. Returns the status after the step, or None if there was no breakpoint to step
+    /// over.
+    fn step_over_breakpoint(&mut self) -> Result<Option<Status>, nix::Error> {
+        // Every way of resuming the inferior comes through here, and the stack is about to change
+        self.selected_frame = 0;
+        self.last_breakpoint = None;
+        self.last_watchpoint = None;
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior.trace_syscalls = self.stop_on_syscall
+            || self.catchpoints.iter().any(|cp| match cp.catch {
+                Catch::Syscall(_) => true,
+                _ => false,
+            });
+        inferior.resume_from_breakpoint()
+    }
+
+    /// Decides whether a stop should be reported to the user. Stops at a breakpoint whose
+    /// condition evaluates to zero are not; a condition that fails to evaluate stops the program.
+    /// Exec events are handled here and never stop the program, and neither does the end of a
+    /// vfork.
+    fn should_stop(&mut self, status: &Status) -> bool {
+        if let Status::Execed = status {
+            self.follow_exec();
+            return self.find_catchpoint(|catch| matches!(catch, Catch::Exec)).is_some();
+        }
+        if let Status::Forked(_, vfork) = *status {
+            return self.fork_catchpoint(vfork).is_some();
+        }
+        if let Status::VforkDone = status {
+            return false;
+        }
+        if let Status::SyscallEntry(number, _) | Status::SyscallExit(number, _) = *status {
+            // Syscall stops come with any syscall catchpoint; only report the ones asked for
+            if !self.stop_on_syscall && self.catchpoint_for_syscall(number).is_none() {
+                return false;
+            }
+        }
+        if let Status::Stopped(signal, _) = *status {
+            if self.catchpoint_for_signal(signal).is_some() {
+                // Caught signals still reach the program once it's continued
+                self.inferior.as_ref().unwrap().pending_signal.set(Some(signal));
+                return true;
+            }
+            if passes_silently(signal) {
+                self.inferior.as_ref().unwrap().pending_signal.set(Some(signal));
+                return false;
+            }
+        }
+        // Whatever is left of the last breakpoint's command list is dropped at the next stop
+        self.pending_commands.clear();
+        self.last_breakpoint = None;
+        self.last_watchpoint = None;
+        if let Status::Stopped(Signal::SIGTRAP, _) = *status {
+            if let Some(stop) = self.watchpoint_hit() {
+                return stop;
+            }
+        }
+        if let Status::Stopped(Signal::SIGTRAP, rip) = *status {
+            if self.end_watch_scopes(rip) {
+                return true;
+            }
+            let watching_return = |wp: &Watchpoint| wp.scope.map_or(false, |(ret, _)| ret == rip);
+            if self.breakpoints.find_by_addr(rip).is_none()
+                && self.watchpoints.iter().any(watching_return)
+            {
+                // A recursive call returning, not the watched frame
+                return false;
+            }
+        }
+        if let Status::Stopped(Signal::SIGTRAP, rip) = *status {
+            let (number, condition) = match self.breakpoints.find_by_addr(rip) {
+                Some(bp) => (bp.number, bp.condition.clone()),
+                None => return true,
+            };
+            let thread = self.breakpoints.get(number).unwrap().thread;
+            if thread.map_or(false, |tid| tid != self.inferior.as_ref().unwrap().tid()) {
+                // Hit by some other thread, which continuing steps over the trap
+                return false;
+            }
+            if let Some(condition) = condition {
+                match expr::parse(&condition).and_then(|expr| expr.eval(self)) {
+                    Ok(0) => return false,
+                    Ok(_) => {}
+                    Err(err) => println!("error in condition of breakpoint {}: {}", number, err),
+                }
+            }
+            let bp = self.breakpoints.get_mut(number).unwrap();
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+                return false;
+            }
+            if let Some((format, args)) = bp.printf.clone() {
+                self.print_trace(number, &format, &args);
+                return false;
+            }
+            self.pending_commands = bp.commands.iter().cloned().collect();
+            self.last_breakpoint = Some((number, rip));
+            self.count_hit(number);
+        }
+        true
+    }
+
+    /// Prints the message of tracepoint `number`.
+    fn print_trace(&self, number: usize, format: &str, args: &[String]) {
+        let values: Result<Vec<i64>, String> = args
+            .iter()
+            .map(|arg| expr::parse(arg).and_then(|expr| expr.eval(self)))
+            .collect();
+        match values.and_then(|values| format_printf(format, &values)) {
+            Ok(message) => {
+                print!("{}", message);
+                let _ = std::io::Write::flush(&mut std::io::stdout());
+            }
+            Err(err) => println!("error in tracepoint {}: {}", number, err),
+        }
+    }
+
+    /// Counts down a `-count` breakpoint that just stopped the program, deleting it after its
+    /// last stop.
+    fn count_hit(&mut self, number: usize) {
+        let bp = self.breakpoints.get_mut(number).unwrap();
+        if let Some(remaining) = bp.delete_after.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                println!("breakpoint {} reached its stop count and was deleted", number);
+                let bp = self.breakpoints.remove(number).unwrap();
+                self.uninstall_breakpoint(bp.addr, bp.hardware);
+            }
+        }
+    }
+
+    /// Continues the inferior until it stops somewhere the user should hear about.
+    fn continue_inferior(&mut self) -> Result<Status, nix::Error> {
+        loop {
+            match self.step_over_breakpoint()? {
+                // The instruction under the breakpoint may itself be writing a watched location
+                Some(status @ Status::Stopped(Signal::SIGTRAP, _)) => {
+                    if self.watchpoint_hit() == Some(true) {
+                        return Ok(status);
+                    }
+                }
+                None => {}
+                Some(status) => return Ok(status),
+            }
+            let status = if self.watchpoints.iter().any(|wp| wp.software) {
+                self.step_watching()?
+            } else {
+                self.inferior.as_ref().unwrap().cont()?
+            };
+            if self.should_stop(&status) {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Resumes the inferior. In the foreground this blocks until the inferior stops and reports
+    /// the new status; in the background it returns immediately, leaving the stop to be picked up
+    /// by `poll_background` or the `wait` command.
+    fn resume_inferior(&mut self, background: bool) {
+        if background {
+            let resumed = self
+                .step_over_breakpoint()
+                .and_then(|_| self.inferior.as_ref().unwrap().resume());
+            match resumed {
+                Ok(_) => {
+                    self.background = true;
+                    println!("target running in the background; use \"wait\" to wait for it to stop");
+                }
+                Err(err) => println!("failed to run command, {}", err),
+            }
+        } else {
+            match self.continue_inferior() {
+                Ok(status) => self.print_status(status),
+                Err(err) => {
+                    println!("failed to run command, {}", err);
+                }
+            }
+        }
+    }
+
+    /// Continues past the next `count - 1` breakpoint stops without reporting them, then
+    /// reports where the inferior stops. Any other kind of stop ends the repetition early.
+    fn continue_repeatedly(&mut self, count: usize) {
+        for remaining in (0..count).rev() {
+            match self.continue_inferior() {
+                Ok(Status::Stopped(Signal::SIGTRAP, rip))
+                    if remaining > 0
+                        && self.last_breakpoint.map_or(false, |(_, addr)| addr == rip) => {}
+                Ok(status) => {
+                    self.print_status(status);
+                    return;
+                }
+                Err(err) => {
+                    println!("failed to run command, {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Executes a single instruction.
+    fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        match self.step_over_breakpoint()? {
+            Some(status) => Ok(status),
+            None => self.inferior.as_mut().unwrap().step(),
+        }
+    }
+
+    /// Returns where the instruction at `rip` returns to, if it is a call.
+    fn call_return_addr(&self, rip: usize) -> Option<usize> {
+        let call = self
+            .read_code(rip, 16)
+            .and_then(|code| disasm::disassemble(&code, rip))
+            .ok()
+            .and_then(|insns| insns.into_iter().next())
+            .filter(|insn| insn.is_call())?;
+        Some(call.address + call.size)
+    }
+
+    /// Executes a single instruction, but runs a call through to its return address.
+    fn next_instruction(&mut self) -> Result<Status, nix::Error> {
+        let regs = ptrace::getregs(self.inferior.as_ref().unwrap().tid())?;
+        let return_addr = match self.call_return_addr(regs.rip as usize) {
+            Some(return_addr) => return_addr,
+            None => return self.step_instruction(),
+        };
+        let last_regs = Some(regs);
+        let status = self.run_to_return(return_addr, regs.rsp);
+        if let Ok(Status::Stopped(..)) = status {
+            self.inferior.as_mut().unwrap().last_regs = last_regs;
+        }
+        status
+    }
+
+    /// Single-steps until the program reaches the start of a different source line (`step`).
+    /// Calls into functions with line information are followed, stopping after the line
+    /// holding their prologue, unless `over_calls` is set (`next`); calls into code without
+    /// any, such as the C library, are always run through to their return. Returning into the
+    /// middle of the caller's line carries on to the next line.
+    fn step_line(&mut self, over_calls: bool) -> Result<Status, nix::Error> {
+        let tid = self.inferior.as_ref().unwrap().tid();
+        let start = ptrace::getregs(tid)?.rip as usize;
+        let (mut range_start, mut range_end) = match self.debug_data.get_line_range(start) {
+            Some((line, end)) => (line.address, end),
+            None => return self.step_instruction(),
+        };
+        loop {
+            let regs = ptrace::getregs(tid)?;
+            let mut status = if over_calls {
+                self.next_instruction()?
+            } else {
+                self.step_instruction()?
+            };
+            if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                if self.debug_data.get_line_range(rip).is_none() {
+                    if let Some(return_addr) = self.call_return_addr(regs.rip as usize) {
+                        status = self.run_to_return(return_addr, regs.rsp)?;
+                    }
+                }
+            }
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip,
+                status => return Ok(status),
+            };
+            if range_start <= rip && rip < range_end {
+                continue;
+            }
+            let (line, end) = match self.debug_data.get_line_range(rip) {
+                Some(range) => range,
+                // e.g. main returning into the C library
+                None => return Ok(status),
+            };
+            let entered = self
+                .debug_data
+                .get_function_containing(rip)
+                .map_or(false, |func| func.address == rip);
+            if !entered && self.debug_data.is_line_start(rip) {
+                return Ok(status);
+            }
+            range_start = line.address;
+            range_end = end;
+        }
+    }
+
+    /// Continues until a function returns to `return_addr`, with the stack pointer back at
+    /// `caller_rsp`, using a temporary breakpoint if there isn't one there already. Stops
+    /// elsewhere (e.g. at breakpoints) are returned as usual.
+    fn run_to_return(&mut self, return_addr: usize, caller_rsp: u64) -> Result<Status, nix::Error> {
+        let tid = self.inferior.as_ref().unwrap().tid();
+        let temporary = self.inferior.as_mut().unwrap().install_temporary_breakpoint(return_addr)?;
+        let mut status = self.continue_inferior();
+        // A recursive call may hit the return address in a deeper frame first, and another
+        // thread running the same code may hit it at any time
+        while let Ok(Status::Stopped(Signal::SIGTRAP, addr)) = status {
+            let inferior = self.inferior.as_ref().unwrap();
+            let rsp = ptrace::getregs(inferior.tid())?.rsp;
+            if addr != return_addr || (rsp >= caller_rsp && inferior.tid() == tid) {
+                break;
+            }
+            status = self.continue_inferior();
+        }
+        if let Ok(Status::Stopped(..)) = status {
+            if temporary {
+                self.inferior.as_mut().unwrap().remove_breakpoint(return_addr)?;
+            }
+        }
+        status
+    }
+
+    /// Runs until the program reaches `location` in the current frame, or the current function
+    /// returns first (`until`). With `any_frame` set, reaching `location` in a function called
+    /// from here counts too (`advance`). Only temporary traps are used, so no breakpoint is left
+    /// behind.
+    fn run_until(&mut self, location: &str, any_frame: bool) -> Result<Status, String> {
+        let addr = self.parse_addr(location)?;
+        let tid = self.inferior.as_ref().ok_or("please run target first")?.tid();
+        let regs = ptrace::getregs(tid).map_err(|err| err.to_string())?;
+        let func = self
+            .debug_data
+            .get_function_containing(regs.rip as usize)
+            .cloned()
+            .ok_or("not meaningful outside a known function")?;
+        let (return_addr, caller_rsp) = self.return_address(&func, &regs)?;
+        let mut temporary = Vec::new();
+        for &trap in [addr, return_addr].iter() {
+            let inferior = self.inferior.as_mut().unwrap();
+            if inferior.install_temporary_breakpoint(trap).map_err(|err| err.to_string())? {
+                temporary.push(trap);
+            }
+        }
+        let status = loop {
+            let status = self.continue_inferior().map_err(|err| err.to_string())?;
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => rip,
+                _ => break status,
+            };
+            // Deeper recursive calls pass through both places too, as may other threads
+            let inferior = self.inferior.as_ref().unwrap();
+            let rsp = ptrace::getregs(inferior.tid()).map_err(|err| err.to_string())?.rsp;
+            let elsewhere = inferior.tid() != tid;
+            let deeper = (rip == addr && (rsp < regs.rsp && !any_frame || elsewhere))
+                || (rip == return_addr && (rsp < caller_rsp || elsewhere));
+            if !deeper || self.last_breakpoint.is_some() {
+                break status;
+            }
+        };
+        if let Status::Stopped(..) = status {
+            for trap in temporary {
+                let inferior = self.inferior.as_mut().unwrap();
+                inferior.remove_breakpoint(trap).map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Finds where the function executing at `rip` will return to, and the stack pointer after
+    /// it does. Looks at how much of the `push %rbp; mov %rsp, %rbp` prologue has run, since
+    /// the frame pointer can only be relied on afterwards.
+    fn return_address(
+        &self,
+        func: &Function,
+        regs: &user_regs_struct,
+    ) -> Result<(usize, u64), String> {
+        let rip = regs.rip as usize;
+        let prologue = self
+            .read_code(func.address, rip - func.address)
+            .and_then(|code| disasm::disassemble(&code, func.address))?;
+        let executed = |mnemonic: &str, operands: &str| {
+            prologue.iter().any(|insn| insn.mnemonic == mnemonic && insn.operands == operands)
+        };
+        let slot = if executed("movq", "%rsp, %rbp") {
+            regs.rbp + 8
+        } else if executed("pushq", "%rbp") {
+            regs.rsp + 8
+        } else {
+            regs.rsp
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let bytes = inferior
+            .read_memory(slot as usize, 8)
+            .map_err(|err| format!("cannot read the return address, {}", err))?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&bytes);
+        Ok((u64::from_le_bytes(buf) as usize, slot + 8))
+    }
+
+    /// Runs until the function of the selected frame returns, and reports its return value.
+    /// Returns the report to print, which is a `finished` event in JSON mode.
+    fn finish(&mut self) -> Result<Option<String>, String> {
+        let inferior = self.inferior.as_ref().ok_or("please run target first")?;
+        let regs = ptrace::getregs(inferior.tid()).map_err(|err| err.to_string())?;
+        let frame = match self.selected_frame {
+            0 => None,
+            _ => Some(*self.selected(&self.frames()?)?),
+        };
+        let rip = frame.map_or(regs.rip as usize, |frame| frame.rip);
+        let func = self
+            .debug_data
+            .get_function_containing(rip)
+            .cloned()
+            .ok_or("\"finish\" not meaningful outside a known function")?;
+        let (return_addr, caller_rsp) = match frame {
+            None => self.return_address(&func, &regs)?,
+            // Outer frames are past their prologue, so the return address is above the saved %rbp
+            Some(frame) => {
+                let bytes = inferior
+                    .read_memory(frame.rbp + 8, 8)
+                    .map_err(|err| format!("cannot read the return address, {}", err))?;
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&bytes);
+                (u64::from_le_bytes(buf) as usize, frame.rbp as u64 + 16)
+            }
+        };
+        println!("Run till exit from #{} {}", self.selected_frame, self.describe_addr(rip));
+        let status = self
+            .run_to_return(return_addr, caller_rsp)
+            .map_err(|err| format!("failed to run command, {}", err))?;
+        let returned =
+            matches!(status, Status::Stopped(Signal::SIGTRAP, addr) if addr == return_addr);
+        self.print_status(status);
+        if !returned {
+            // Stopped somewhere else first
+            return Ok(None);
+        }
+        let location = self
+            .debug_data
+            .get_line_from_addr(return_addr)
+            .map_or("??".to_string(), |line| line.to_string());
+        let mut fields = vec![("function", JsonValue::Str(func.name.clone()))];
+        let mut report = None;
+        if let Some(ty) = func.return_type.as_ref().filter(|ty| is_integer_type(ty)) {
+            let rax = ptrace::getregs(self.inferior.as_ref().unwrap().tid())
+                .map_err(|err| err.to_string())?
+                .rax;
+            let value = integer_value(ty, &rax.to_le_bytes()[..ty.size]);
+            self.value_history.push(value);
+            report = Some(format!("Value returned is ${} = {}", self.value_history.len(), value));
+            fields.push(("return_value", JsonValue::Number(value)));
+            fields.push(("return_type", JsonValue::Str(ty.name.clone())));
+        }
+        if self.json {
+            fields.push(("location", JsonValue::Str(location)));
+            return Ok(Some(json::event("finished", fields)));
+        }
+        Ok(report)
+    }
+
+    /// Prints the instruction the stopped inferior will execute next, as `x/i $pc` would.
+    fn print_next_instruction(&self) {
+        let rip = match self.dollar("pc") {
+            Ok(rip) => rip as usize,
+            Err(_) => return,
+        };
+        let insn = match self.read_code(rip, 16).and_then(|code| disasm::disassemble(&code, rip)) {
+            Ok(insns) if !insns.is_empty() => insns.into_iter().next().unwrap(),
+            _ => {
+                println!("=> {:#x}:\t(bad)", rip);
+                return;
+            }
+        };
+        let location = self.symbolize(rip).map_or(String::new(), |symbol| format!(" <{}>", symbol));
+        println!("=> {:#x}{}:\t{:<6} {}", rip, location, insn.mnemonic, insn.operands);
+    }
+
+    /// Formats the named registers of the selected frame (the general purpose ones if `names` is
+    /// empty; "vector" and "all" name groups) for `info registers`: the name, the value in hex,
+    /// and the value again in its natural form. That is decimal for most registers, the symbol
+    /// for ones holding code or stack addresses, and the set flags for `eflags` and `mxcsr`.
+    /// The %xmm registers are shown as vectors of floats, doubles and integers.
+    fn register_lines(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let regs = self.frame_regs()?;
+        let names: Vec<&str> = match names {
+            [] => registers::REGISTER_NAMES.to_vec(),
+            [group] if group == "vector" => registers::VECTOR_REGISTER_NAMES.to_vec(),
+            [group] if group == "all" => {
+                let mut names = registers::REGISTER_NAMES.to_vec();
+                names.extend_from_slice(&registers::VECTOR_REGISTER_NAMES);
+                names
+            }
+            names => names.iter().map(|name| name.as_str()).collect(),
+        };
+        // The SSE state isn't saved in stack frames, so it's always the innermost frame's
+        let fpregs = if names.iter().any(|name| registers::is_vector_register(name)) {
+            let inferior = self.inferior.as_ref().unwrap();
+            Some(inferior.get_fpregs().map_err(|err| err.to_string())?)
+        } else {
+            None
+        };
+        let mut lines = Vec::new();
+        for name in names {
+            if let (Some(fpregs), true) = (&fpregs, registers::is_vector_register(name)) {
+                lines.push(match registers::get_xmm(fpregs, name) {
+                    Some(xmm) => format!("{:<15}{}", name, registers::format_xmm(&xmm)),
+                    None => format!(
+                        "{:<15}{:<19}{}",
+                        name,
+                        format!("{:#x}", fpregs.mxcsr),
+                        registers::decode_mxcsr(fpregs.mxcsr)
+                    ),
+                });
+                continue;
+            }
+            let value = registers::get_register(&regs, name)
+                .ok_or(format!("Invalid register `{}'", name))?;
+            let natural = match name {
+                "eflags" => registers::decode_eflags(value),
+                _ if registers::is_address_register(name) => {
+                    match self.symbolize(value as usize) {
+                        Some(symbol) => format!("{:#x} <{}>", value, symbol),
+                        None => format!("{:#x}", value),
+                    }
+                }
+                _ => (value as i64).to_string(),
+            };
+            lines.push(format!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural));
+        }
+        Ok(lines)
+    }
+
+    /// Prints the registers that the last step changed, if `set step-register-diff` is on.
+    fn print_register_diff(&self) {
+        if !self.step_register_diff {
+            return;
+        }
+        let inferior = self.inferior.as_ref().unwrap();
+        let (before, after) = match (inferior.last_regs, ptrace::getregs(inferior.tid())) {
+            (Some(before), Ok(after)) => (before, after),
+            _ => return,
+        };
+        for (name, old, new) in registers::diff(&before, &after) {
+            println!("{:<8} {:#x} → {:#x}", name, old, new);
+        }
+    }
+
+    /// Checks, without blocking, whether a backgrounded inferior has stopped, and reports it.
+    fn poll_background(&mut self) {
+        if !self.background {
+            return;
+        }
+        match self.inferior.as_ref().unwrap().try_wait() {
+            Ok(Some(status)) => self.background_stopped(Ok(status)),
+            Ok(None) => {}
+            Err(err) => self.background_stopped(Err(err)),
+        }
+    }
+
+    /// Deals with a change in the state of a backgrounded inferior: reports the stop, or
+    /// resumes the inferior if it isn't one to stop for.
+    fn background_stopped(&mut self, stop: Result<Status, nix::Error>) {
+        match stop {
+            Ok(status) if !self.should_stop(&status) => {
+                // e.g. a conditional breakpoint whose condition is false; keep going
+                let resumed = self
+                    .step_over_breakpoint()
+                    .and_then(|_| self.inferior.as_ref().unwrap().resume());
+                if let Err(err) = resumed {
+                    self.background = false;
+                    println!("[background] failed to resume target, {}", err);
+                }
+            }
+            Ok(status) => {
+                self.background = false;
+                print!("[background] ");
+                self.print_status(status);
+            }
+            Err(err) => {
+                self.background = false;
+                println!("[background] failed to wait for target, {}", err);
+            }
+        }
+    }
+
+    /// While the inferior runs in the background, waits for the user to start typing and reports
+    /// stops as they happen rather than at the next command. On a terminal the prompt is shown
+    /// meanwhile, with echo off so that the keys typed are left for readline, and taken down
+    /// before a report or readline's own prompt.
+    fn wait_for_input(&mut self, prompt: &str) {
+        let terminal = pager::is_tty(libc::STDIN_FILENO) && pager::is_tty(libc::STDOUT_FILENO);
+        let saved = if terminal && self.background { quiet_input() } else { None };
+        while self.background {
+            if terminal {
+                print!("{}", prompt);
+                let _ = std::io::Write::flush(&mut std::io::stdout());
+            }
+            let stop = loop {
+                if input_ready(BACKGROUND_POLL_MS) {
+                    break None;
+                }
+                match self.inferior.as_ref().unwrap().try_wait() {
+                    Ok(Some(status)) => break Some(Ok(status)),
+                    Ok(None) => {}
+                    Err(err) => break Some(Err(err)),
+                }
+            };
+            if terminal {
+                print!("\r\x1b[K");
+            }
+            match stop {
+                Some(stop) => self.background_stopped(stop),
+                None => break,
+            }
+        }
+        if let Some(saved) = saved {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved) };
+        }
+    }
+
+    /// Asks a yes/no question. The answer is assumed to be yes when `set confirm` is off or
+    /// when input isn't coming from a terminal.
+    fn query(&mut self, question: &str) -> bool {
+        if !self.confirm || !pager::is_tty(libc::STDIN_FILENO) {
+            return true;
+        }
+        loop {
+            match self.readline.readline(&format!("{} (y or n) ", question)) {
+                Ok(answer) => match answer.trim() {
+                    "y" | "yes" => return true,
+                    "n" | "no" => return false,
+                    _ => println!("Please answer y or n."),
+                },
+                // EOF means yes, as in GDB
+                Err(ReadlineError::Eof) => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Runs the command loop until the user quits, and returns the exit code for DEET.
+    pub fn run(&mut self) -> i32 {
+        loop {
+            self.poll_background();
+            let cmd = self.get_next_command();
+            if self.background && !cmd.allowed_in_background() {
+                println!("target is running in the background; use \"wait\" to wait for it to stop first");
+                continue;
+            }
+            if let Some(exit_code) = self.execute(cmd) {
+                return exit_code;
+            }
+        }
+    }
+
+    /// Carries out a single command. Returns DEET's exit code if the command was `quit`.
+    fn execute(&mut self, cmd: DebuggerCommand) -> Option<i32> {
+        match cmd {
+            DebuggerCommand::Run(args, background) => {
+                // Registers would be read with the 64-bit layout and make no sense
+                if self.elf_class == Some(ElfClass::Elf32) {
+                    println!("32-bit targets are not supported yet");
+                    return None;
+                }
+                // make sure no previous target exists
+                if self.inferior.is_some() {
+                    match self.inferior.as_mut().unwrap().terminate() {
+                        Ok(status) => self.print_status(status),
+                        Err(err) => println!("failed to terminate previous target, {}", err),
+                    }
+                }
+
+                if let Some(inferior) = Inferior::new(
+                    &self.target,
+                    &args,
+                    &self.environment,
+                    self.inherit_environment,
+                ) {
+                    // Create the inferior, stopped before its first instruction, and arm the
+                    // breakpoints before letting it run
+                    self.inferior = Some(inferior);
+                    self.install_breakpoints();
+                    self.resume_inferior(background);
+                } else {
+                    println!("Error starting subprocess");
+                }
+            }
+            DebuggerCommand::Continue(count, background) => {
+                if self.inferior.is_none() {
+                    println!("please run target first");
+                    return None;
+                }
+                if count > 1 {
+                    if background {
+                        println!("cannot continue through breakpoints in the background");
+                    } else {
+                        self.continue_repeatedly(count);
+                    }
+                } else {
+                    self.resume_inferior(background);
+                }
+            }
+            DebuggerCommand::Wait => {
+                if !self.background {
+                    println!("target is not running in the background");
+                    return None;
+                }
+                self.background = false;
+                let status = match self.inferior.as_ref().unwrap().wait() {
+                    Ok(status) if !self.should_stop(&status) => self.continue_inferior(),
+                    other => other,
+                };
+                match status {
+                    Ok(status) => self.print_status(status),
+                    Err(err) => println!("failed to wait for target, {}", err),
+                }
+            }
+            DebuggerCommand::Step | DebuggerCommand::Next => {
+                if self.inferior.is_none() {
+                    println!("please run target first");
+                    return None;
+                }
+                if !self.require_debug_info() {
+                    return None;
+                }
+                match self.step_line(matches!(cmd, DebuggerCommand::Next)) {
+                    Ok(status) => self.print_status(status),
+                    Err(err) => println!("failed to step target, {}", err),
+                }
+            }
+            DebuggerCommand::StepInstruction | DebuggerCommand::NextInstruction => {
+                if self.inferior.is_none() {
+                    println!("please run target first");
+                    return None;
+                }
+                let status = match cmd {
+                    DebuggerCommand::StepInstruction => self.step_instruction(),
+                    _ => self.next_instruction(),
+                };
+                match status {
+                    Ok(status @ Status::Stopped(..)) => {
+                        self.print_status(status);
+                        if !self.disassemble_next_line {
+                            self.print_next_instruction();
+                        }
+                        self.print_register_diff();
+                    }
+                    Ok(status) => self.print_status(status),
+                    Err(err) => println!("failed to step target, {}", err),
+                }
+            }
+            DebuggerCommand::BackTrace(full) => {
+                if !self.require_debug_info() {
+                    return None;
+                }
+                if self.inferior.is_none() {
+                    println!("please run target first");
+                    return None;
+                }
+                match self.frames() {
+                    Ok(frames) => {
+                        let mut lines = Vec::new();
+                        for frame in &frames {
+                            lines.push(self.describe_frame(frame));
+                            if full {
+                                lines.extend(self.frame_locals(frame));
+                            }
+                        }
+                        self.pager.print(&lines);
+                    }
+                    Err(err) => println!("{}", err),
+                }
+            }
+            DebuggerCommand::Until(location) => match self.run_until(&location, false) {
+                Ok(status) => self.print_status(status),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::Advance(location) => match self.run_until(&location, true) {
+                Ok(status) => self.print_status(status),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::Finish => match self.finish() {
+                Ok(Some(report)) => println!("{}", report),
+                Ok(None) => {}
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::Frame(number) => {
+                let number = number.unwrap_or(self.selected_frame);
+                self.select_frame(number as isize);
+            }
+            DebuggerCommand::Up(count) => {
+                self.select_frame(self.selected_frame as isize + count as isize);
+            }
+            DebuggerCommand::Down(count) => {
+                self.select_frame(self.selected_frame as isize - count as isize);
+            }
+            DebuggerCommand::Breakpoint(s, count) => {
+                if !s.to_lowercase().starts_with("0x") && !self.require_debug_info() {
+                    return None;
+                }
+                let (func_name, sites) = self.inline_sites(&s);
+                if !sites.is_empty() {
+                    let out_of_line = self.parse_addr(&s).ok();
+                    if self.break_inline {
+                        for &addr in sites.iter().chain(out_of_line.iter()) {
+                            self.set_breakpoint(&s, addr, count, false);
+                        }
+                        println!(
+                            "{} is inlined; set {} breakpoints at inline sites",
+                            func_name,
+                            sites.len()
+                        );
+                        return None;
+                    } else if out_of_line.is_none() {
+                        println!(
+                            "{} is only inlined, at {} sites; use \"set breakpoint inline on\" to \
+                             break at all of them",
+                            func_name,
+                            sites.len()
+                        );
+                        return None;
+                    }
+                }
+                match self.parse_addr(&s) {
+                    Ok(addr) => {
+                        self.set_breakpoint(&s, addr, count, false);
+                    }
+                    Err(err) => self.set_pending_breakpoint(&s, err, count),
+                };
+            }
+            DebuggerCommand::ThreadBreakpoint(s, count, tid) => {
+                let thread = Pid::from_raw(tid);
+                let known = self.inferior.as_ref().filter(|inf| inf.is_alive());
+                if !known.map_or(false, |inferior| inferior.threads().contains(&thread)) {
+                    println!("unknown thread {}", tid);
+                    return None;
+                }
+                let first = self.breakpoints.next_number();
+                self.execute(DebuggerCommand::Breakpoint(s, count));
+                for bp in self.breakpoints.iter_mut().filter(|bp| bp.number >= first) {
+                    bp.thread = Some(thread);
+                }
+            }
+            DebuggerCommand::HardwareBreakpoint(s) => {
+                if !s.to_lowercase().starts_with("0x") && !self.require_debug_info() {
+                    return None;
+                }
+                match self.parse_addr(&s) {
+                    Ok(addr) => {
+                        self.set_breakpoint(&s, addr, None, true);
+                    }
+                    Err(err) => println!("{}", err),
+                };
+            }
+            DebuggerCommand::Trace(location, format, args) => {
+                if !location.to_lowercase().starts_with("0x") && !self.require_debug_info() {
+                    return None;
+                }
+                let addr = match self.parse_addr(&location) {
+                    Ok(addr) => addr,
+                    Err(err) => {
+                        println!("{}", err);
+                        return None;
+                    }
+                };
+                if let Some(number) = self.set_breakpoint(&location, addr, None, false) {
+                    self.breakpoints.get_mut(number).unwrap().printf = Some((format, args));
+                }
+            }
+            DebuggerCommand::RegexBreakpoint(pattern) => {
+                if !self.require_debug_info() {
+                    return None;
+                }
+                let regex = match Regex::new(&pattern) {
+                    Ok(regex) => regex,
+                    Err(err) => {
+                        println!("invalid regular expression {}: {}", pattern, err);
+                        return None;
+                    }
+                };
+                let matches: Vec<(String, usize)> = self
+                    .debug_data
+                    .functions()
+                    .iter()
+                    .filter(|func| regex.is_match(&func.name))
+                    .map(|func| (func.name.clone(), func.address))
+                    .collect();
+                for (name, addr) in &matches {
+                    self.set_breakpoint(name, *addr, None, false);
+                }
+                println!("set {} breakpoint(s) on functions matching {}", matches.len(), pattern);
+            }
+            DebuggerCommand::CatchSyscall(names) => self.catch_syscalls(&names),
+            DebuggerCommand::CatchSignal(names) => self.catch_signals(&names),
+            DebuggerCommand::Catch(catch) => self.catch_event(catch),
+            DebuggerCommand::Watch(expr, kind) => self.set_watchpoint(&expr, kind),
+            DebuggerCommand::Delete(target, numbers) => self.delete(target, numbers),
+            DebuggerCommand::Disable(numbers) => self.set_enabled(&numbers, false),
+            DebuggerCommand::Enable(numbers) => self.set_enabled(&numbers, true),
+            DebuggerCommand::Commands(number) => {
+                let number = match number.or(self.breakpoints.iter().last().map(|bp| bp.number)) {
+                    Some(number) if self.breakpoints.get(number).is_some() => number,
+                    Some(number) => {
+                        println!("no breakpoint number {}", number);
+                        return None;
+                    }
+                    None => {
+                        println!("no breakpoints to attach commands to");
+                        return None;
+                    }
+                };
+                let commands = self.read_command_list(number);
+                self.breakpoints.get_mut(number).unwrap().commands = commands;
+            }
+            DebuggerCommand::Condition(number, condition) => {
+                if let Some(expr) = &condition {
+                    if let Err(err) = expr::parse(expr) {
+                        println!("invalid condition \"{}\": {}", expr, err);
+                        return None;
+                    }
+                }
+                match self.breakpoints.get_mut(number) {
+                    Some(bp) => {
+                        match &condition {
+                            Some(expr) => println!("breakpoint {} now stops only if {}", number, expr),
+                            None => println!("breakpoint {} now unconditional", number),
+                        }
+                        bp.condition = condition;
+                    }
+                    None => println!("no breakpoint number {}", number),
+                }
+            }
+            DebuggerCommand::Ignore(number, count) => match self.breakpoints.get_mut(number) {
+                Some(bp) => {
+                    bp.ignore_count = count;
+                    match count {
+                        0 => println!("will stop next time breakpoint {} is reached", number),
+                        1 => println!("will ignore next crossing of breakpoint {}", number),
+                        _ => println!(
+                            "will ignore next {} crossings of breakpoint {}",
+                            count, number
+                        ),
+                    }
+                }
+                None => println!("no breakpoint number {}", number),
+            },
+            DebuggerCommand::Print(format, expr) => self.print_expr(&expr, format),
+            DebuggerCommand::Display(format, Some(expr)) => {
+                if let Err(err) = expr::parse(&expr) {
+                    println!("invalid expression \"{}\": {}", expr, err);
+                    return None;
+                }
+                let number = self.next_display;
+                self.next_display += 1;
+                self.displays.push(AutoDisplay { number, format, expr });
+                if self.inferior.is_some() {
+                    self.show_display(number);
+                }
+            }
+            DebuggerCommand::Display(_, None) => self.show_displays(),
+            DebuggerCommand::InfoDisplay => {
+                if self.displays.is_empty() {
+                    println!("There are no auto-display expressions now.");
+                    return None;
+                }
+                let mut lines = vec![
+                    "Auto-display expressions now in effect:".to_string(),
+                    "Num Expression".to_string(),
+                ];
+                for display in &self.displays {
+                    let format = display.format.map_or(String::new(), |c| format!("/{} ", c));
+                    let number = format!("{}:", display.number);
+                    lines.push(format!("{:<4}{}{}", number, format, display.expr));
+                }
+                self.pager.print(&lines);
+            }
+            DebuggerCommand::WhatIs(expr) => match self.expression_type(&expr) {
+                Ok(ty) => println!("type = {}", ty.name),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::PType(expr) => match self.expression_type(&expr) {
+                Ok(ty) => self.pager.print(&type_lines(&ty)),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::MaintenanceInfoLineTable(file) => {
+                if !self.require_debug_info() {
+                    return None;
+                }
+                self.print_line_tables(file.as_deref());
+            }
+            DebuggerCommand::MaintenanceInfoBreakpointBytes => self.print_breakpoint_bytes(),
+            DebuggerCommand::InfoSources => {
+                if !self.require_debug_info() {
+                    return None;
+                }
+                self.pager.print(&self.debug_data.source_files());
+            }
+            DebuggerCommand::InfoFunctions(pattern) => {
+                match self.function_lines(pattern.as_deref()) {
+                    Ok(lines) => self.pager.print(&lines),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            DebuggerCommand::InfoVariables(pattern) => {
+                match self.variable_lines(pattern.as_deref()) {
+                    Ok(lines) => self.pager.print(&lines),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            DebuggerCommand::InfoFrame => match self.frame_info_lines() {
+                Ok(lines) => self.pager.print(&lines),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::InfoProcMappings => match self.mapping_lines() {
+                Ok(lines) => self.pager.print(&lines),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::InfoSymbol(location) => match self.symbol_info(&location) {
+                Ok(info) => println!("{}", info),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::InfoBreakpoints(None) => self.print_breakpoints(),
+            DebuggerCommand::InfoBreakpoints(Some(number)) => {
+                match self.breakpoints.get(number) {
+                    Some(bp) => self.print_breakpoint_details(bp),
+                    None => println!("no breakpoint number {}", number),
+                }
+            }
+            DebuggerCommand::InfoWatchpoints => self.print_watchpoints(),
+            DebuggerCommand::InfoRegisters(names) => match self.register_lines(&names) {
+                Ok(lines) => self.pager.print(&lines),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::InfoLine(location) => self.info_line(location.as_deref()),
+            DebuggerCommand::List(location) => match self.list_source(location.as_deref()) {
+                Ok(lines) => self.pager.print(&lines),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::Disassemble(location) => self.disassemble(location.as_deref()),
+            DebuggerCommand::Examine(fmt, addr) => self.examine(&fmt, &addr),
+            DebuggerCommand::Hexdump(addr, len) => match self.hexdump_lines(&addr, &len) {
+                Ok(lines) => self.pager.print(&lines),
+                Err(err) => println!("{}", err),
+            },
+            DebuggerCommand::Patch(location, bytes) => {
+                if self.inferior.is_none() {
+                    println!("please run target first");
+                    return None;
+                }
+                let addr = match self.parse_addr(&location) {
+                    Ok(addr) => addr,
+                    Err(err) => {
+                        println!("{}", err);
+                        return None;
+                    }
+                };
+                match self.inferior.as_mut().unwrap().patch(addr, &bytes) {
+                    Ok(()) => {
+                        println!("patched {} bytes at {:#x}", bytes.len(), addr);
+                        println!("warning: patches are in memory only and are lost when the program is restarted");
+                    }
+                    Err(err) => println!("cannot access memory at address {:#x}, {}", addr, err),
+                }
+            }
+            DebuggerCommand::Snapshot(name, start, end) => self.snapshot(name, &start, &end),
+            DebuggerCommand::Diff(name) => self.diff_snapshot(&name),
+            DebuggerCommand::SaveBreakpoints(path) => {
+                let script = self.breakpoints_script();
+                match std::fs::write(&path, script) {
+                    Ok(()) => {
+                        println!("saved {} breakpoint(s) to {}", self.breakpoints.len(), path)
+                    }
+                    Err(err) => println!("could not write {}: {}", path, err),
+                }
+            }
+            DebuggerCommand::Source(path) => match std::fs::read_to_string(&path) {
+                // Nested files run before the rest of the file that sourced them
+                Ok(script) => {
+                    for line in script.lines().rev() {
+                        self.script_lines.push_front(line.to_string());
+                    }
+                }
+                Err(err) => println!("could not read {}: {}", path, err),
+            },
+            DebuggerCommand::Show(args) => self.show(&args),
+            DebuggerCommand::Set(args) => self.set(&args),
+            DebuggerCommand::Quit(code) => {
+                if self.live_inferiors().next().is_some() {
+                    if !self.query("A debugging session is active. Quit anyway?") {
+                        return None;
+                    }
+                    self.background = false;
+                    self.checkpoints.clear();
+                    let statuses: Vec<Result<Status, nix::Error>> =
+                        self.live_inferiors().map(|inferior| inferior.terminate()).collect();
+                    for status in statuses {
+                        match status {
+                            Ok(status) => self.print_status(status),
+                            Err(err) => {
+                                println!("failed to terminate target, {}", err);
+                            }
+                        }
+                    }
+                }
+                return Some(code.unwrap_or(0));
+            }
+            DebuggerCommand::AddInferior => {
+                self.inferiors.push(None);
+                println!("Added inferior {}", self.inferiors.len());
+            }
+            DebuggerCommand::Inferior(number) => self.switch_inferior(number),
+            DebuggerCommand::InfoInferiors => self.print_inferiors(),
+            DebuggerCommand::InfoThreads => self.print_threads(),
+            DebuggerCommand::Checkpoint => self.checkpoint(),
+            DebuggerCommand::Restart(number) => self.restore_checkpoint(number),
+            DebuggerCommand::ReverseContinue => match self.checkpoints.last() {
+                Some(checkpoint) => self.restore_checkpoint(checkpoint.number),
+                None => println!("no checkpoint to go back to; take one with \"checkpoint\""),
+            },
+            DebuggerCommand::InfoCheckpoints => self.print_checkpoints(),
+        }
+        None
+    }
+
+    /// Evaluates an expression, records it in the value history as `$N` and prints it. Registers
+    /// that hold addresses are shown in hex, and `$eflags` is additionally decoded into its set
+    /// flags. A variable on its own is shown the way its type says, which also lets floating
+    /// point variables be printed even though expressions are integer-only.
+    fn print_expr(&mut self, expr_str: &str, format: Option<char>) {
+        match self.evaluate(expr_str, format) {
+            Ok((val, text)) => {
+                self.value_history.push(val);
+                let number = self.value_history.len();
+                // Without a process, variables come from the executable's .data/.bss
+                if self.inferior.as_ref().filter(|inf| inf.is_alive()).is_none()
+                    && expr::parse(expr_str).map_or(false, |expr| expr.uses_variables())
+                {
+                    println!("${} = {} (static initializer)", number, text)
+                } else {
+                    println!("${} = {}", number, text)
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Evaluates an expression as `print` and `display` show it, returning its value along with
+    /// the text to show: in the given format letter if there is one, otherwise variables are
+    /// formatted by their type and address registers in hex.
+    fn evaluate(&self, expr_str: &str, format: Option<char>) -> Result<(i64, String), String> {
+        let expr = expr::parse(expr_str)
+            .map_err(|err| format!("invalid expression \"{}\": {}", expr_str, err))?;
+        if let Expr::Variable(name) = &expr {
+            let (ty, bytes) = self.variable_value(name)?;
+            return match format {
+                Some(format) => format_as(&ty, &bytes, format),
+                None => self.format_value(&ty, &bytes),
+            };
+        }
+        let val = expr.eval(self)?;
+        let text = if let Some(format) = format {
+            format::format_integer(val as u64, 8, format, false)
+        } else {
+            match &expr {
+                Expr::Dollar(name) if name == "eflags" => {
+                    format!("{:#x} {}", val, registers::decode_eflags(val as u64))
+                }
+                Expr::Dollar(name) if registers::is_address_register(name) => {
+                    format!("{:#x}", val)
+                }
+                _ => val.to_string(),
+            }
+        };
+        Ok((val, text))
+    }
+
+    /// Shows every `display` expression, as after the program stops.
+    fn show_displays(&self) {
+        for display in &self.displays {
+            self.show_display(display.number);
+        }
+    }
+
+    /// Shows display `number` as "1: count = 3", or "1: /x count = 0x3" with a format.
+    /// Expressions that can't be evaluated at this stop, such as locals of another function, show
+    /// the error instead.
+    fn show_display(&self, number: usize) {
+        let display = match self.displays.iter().find(|display| display.number == number) {
+            Some(display) => display,
+            None => return,
+        };
+        let format = display.format.map_or(String::new(), |c| format!("/{} ", c));
+        let text = match self.evaluate(&display.expr, display.format) {
+            Ok((_, text)) => text,
+            Err(err) => format!("<error: {}>", err),
+        };
+        println!("{}: {}{} = {}", number, format, display.expr, text);
+    }
+
+    /// Lists the functions with code, or those whose names match `pattern`, by source file:
+    /// "0x40115d  9:  void func2(int a, int b);", with a tab after the line number.
+    fn function_lines(&self, pattern: Option<&str>) -> Result<Vec<String>, String> {
+        let regex = self.symbol_regex(pattern)?;
+        let mut lines = vec![match pattern {
+            Some(pattern) => format!("All functions matching regular expression \"{}\":", pattern),
+            None => "All defined functions:".to_string(),
+        }];
+        for (file, functions) in self.debug_data.functions_by_file() {
+            let functions: Vec<_> = functions
+                .into_iter()
+                .filter(|func| regex.as_ref().map_or(true, |regex| regex.is_match(&func.name)))
+                .collect();
+            if functions.is_empty() {
+                continue;
+            }
+            lines.push(String::new());
+            lines.push(format!("File {}:", file));
+            for func in functions {
+                let params: Vec<String> = func
+                    .variables
+                    .iter()
+                    .filter(|var| var.is_parameter)
+                    .map(|var| declaration(&var.entity_type.name, &var.name))
+                    .collect();
+                let return_type = func.return_type.as_ref().map_or("void", |ty| ty.name.as_str());
+                lines.push(format!(
+                    "{:#x}  {}:\t{}({});",
+                    func.address,
+                    func.line_number,
+                    declaration(return_type, &func.name),
+                    params.join(", ")
+                ));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Lists the global and static variables, or those whose names match `pattern`, by source
+    /// file: "0x404028  3:  int global;", with a tab after the line number. Thread-local
+    /// variables show their offset into the thread's TLS block instead of an address.
+    fn variable_lines(&self, pattern: Option<&str>) -> Result<Vec<String>, String> {
+        let regex = self.symbol_regex(pattern)?;
+        let mut lines = vec![match pattern {
+            Some(pattern) => format!("All variables matching regular expression \"{}\":", pattern),
+            None => "All defined variables:".to_string(),
+        }];
+        for (file, globals) in self.debug_data.globals_by_file() {
+            let globals: Vec<_> = globals
+                .into_iter()
+                .filter(|var| regex.as_ref().map_or(true, |regex| regex.is_match(&var.name)))
+                .collect();
+            if globals.is_empty() {
+                continue;
+            }
+            lines.push(String::new());
+            lines.push(format!("File {}:", file));
+            for var in globals {
+                let location = match var.location {
+                    Location::Address(addr) => format!("{:#x}", addr),
+                    Location::TlsOffset(offset) => format!("TLS+{:#x}", offset),
+                    Location::FramePointerOffset(offset) => format!("FP{:+}", offset),
+                };
+                lines.push(format!(
+                    "{}  {}:\t{};",
+                    location,
+                    var.line_number,
+                    declaration(&var.entity_type.name, &var.name)
+                ));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Compiles the pattern `info functions` and `info variables` filter names with. Also
+    /// checks there are symbols to list.
+    fn symbol_regex(&self, pattern: Option<&str>) -> Result<Option<Regex>, String> {
+        if !self.debug_data.has_debug_info() {
+            return Err(NO_DEBUG_INFO.to_string());
+        }
+        match pattern {
+            Some(pattern) => Regex::new(pattern)
+                .map(Some)
+                .map_err(|err| format!("invalid regular expression {}: {}", pattern, err)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the running program's memory mappings, as `info proc mappings`.
+    fn mapping_lines(&self) -> Result<Vec<String>, String> {
+        let inferior = self.inferior.as_ref().ok_or("the program is not being run")?;
+        let mappings = inferior
+            .mappings()
+            .map_err(|err| format!("failed to read the memory mappings, {}", err))?;
+        let mut lines = vec![
+            format!("process {}", inferior.pid()),
+            "Mapped address spaces:".to_string(),
+            String::new(),
+            format!(
+                "{:>18} {:>18} {:>10} {:>10}  {:<5}  {}",
+                "Start Addr", "End Addr", "Size", "Offset", "Perms", "objfile"
+            ),
+        ];
+        for mapping in mappings {
+            let line = format!(
+                "{:>18} {:>18} {:>10} {:>10}  {:<5}  {}",
+                format!("{:#x}", mapping.start),
+                format!("{:#x}", mapping.end),
+                format!("{:#x}", mapping.end - mapping.start),
+                format!("{:#x}", mapping.offset),
+                mapping.perms,
+                mapping.path
+            );
+            lines.push(line.trim_end().to_string());
+        }
+        Ok(lines)
+    }
+
+    /// Works out the type of a variable, or of an expression (which is always a long, as
+    /// expressions are evaluated in 64 bits), without reading the program's memory.
+    fn expression_type(&self, expr_str: &str) -> Result<Type, String> {
+        let expr = expr::parse(expr_str)
+            .map_err(|err| format!("invalid expression \"{}\": {}", expr_str, err))?;
+        if !expr.variables().is_empty() && !self.debug_data.has_debug_info() {
+            return Err(NO_DEBUG_INFO.to_string());
+        }
+        let mut types = Vec::new();
+        for name in expr.variables() {
+            let var = match &self.inferior {
+                Some(_) => {
+                    let rip = self.frame_regs()?.rip as usize;
+                    self.debug_data.lookup_variable(rip, name)
+                }
+                None => self.debug_data.lookup_global(name),
+            };
+            let var = var.ok_or(format!("no symbol \"{}\" in current context", name))?;
+            types.push(var.entity_type.clone());
+        }
+        match expr {
+            Expr::Variable(_) => Ok(types.pop().unwrap()),
+            _ => Ok(Type::new("long".to_string(), 8, TypeKind::Signed)),
+        }
+    }
+
+    /// Handles `set <setting> <value>`.
+    fn set(&mut self, args: &[String]) {
+        if args.first().map_or(false, |arg| arg.starts_with('$')) {
+            self.set_convenience_var(&args.join(" "));
+            return;
+        }
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        match args.as_slice() {
+            ["env", rest @ ..] | ["environment", rest @ ..] if !rest.is_empty() => {
+                self.set_environment(&rest.join(" "))
+            }
+            ["environment-inherit", value] => match parse_on_off(value) {
+                Some(on) => self.inherit_environment = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["pagination", value] => match parse_on_off(value) {
+                Some(on) => self.pager.enabled = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["step-register-diff", value] => match parse_on_off(value) {
+                Some(on) => self.step_register_diff = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["disassemble-next-line", value] => match parse_on_off(value) {
+                Some(on) => self.disassemble_next_line = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["stop-on-syscall", value] => match parse_on_off(value) {
+                Some(on) => self.stop_on_syscall = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["print", "elements", limit] => match limit.parse::<usize>() {
+                Ok(limit) => self.print_elements = limit,
+                Err(_) => println!("integer expected"),
+            },
+            ["backtrace", "past-main", value] => match parse_on_off(value) {
+                Some(on) => self.backtrace_past_main = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["breakpoint", "inline", value] => match parse_on_off(value) {
+                Some(on) => self.break_inline = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["breakpoint", "pending", "auto"] => self.break_pending = None,
+            ["breakpoint", "pending", value] => match parse_on_off(value) {
+                Some(on) => self.break_pending = Some(on),
+                None => println!("\"on\", \"off\" or \"auto\" expected"),
+            },
+            ["history", "size", size] => match size.parse::<usize>() {
+                Ok(size) => self.readline.set_max_history_size(size),
+                Err(_) => println!("integer expected"),
+            },
+            ["history", "save", value] => match parse_on_off(value) {
+                Some(on) => self.history_save = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            ["follow-exec-mode", "new"] => self.follow_exec_mode = FollowExecMode::New,
+            ["follow-exec-mode", "same"] => self.follow_exec_mode = FollowExecMode::Same,
+            ["follow-exec-mode", _] => println!("\"new\" or \"same\" expected"),
+            ["confirm", value] => match parse_on_off(value) {
+                Some(on) => self.confirm = on,
+                None => println!("\"on\" or \"off\" expected"),
+            },
+            _ => println!("unknown setting: set {}", args.join(" ")),
+        }
+    }
+
+    /// Handles `set environment NAME=VALUE` (or `NAME VALUE`). The change applies from the next
+    /// `run`.
+    fn set_environment(&mut self, assignment: &str) {
+        let idx = assignment
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or_else(|| assignment.len());
+        let name = assignment[..idx].trim();
+        let value = assignment[idx..].trim_start();
+        let value = if value.starts_with('=') { value[1..].trim_start() } else { value };
+        if name.is_empty() {
+            println!("expected \"set environment NAME=VALUE\"");
+            return;
+        }
+        self.environment.insert(name.to_string(), value.to_string());
+    }
+
+    /// Handles `set $name = expression`.
+    fn set_convenience_var(&mut self, assignment: &str) {
+        let (name, value) = match assignment.find('=') {
+            Some(idx) => (assignment[1..idx].trim(), assignment[idx + 1..].trim()),
+            None => {
+                println!("expected \"set $name = expression\"");
+                return;
+            }
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            println!("invalid convenience variable name ${}", name);
+            return;
+        }
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            println!("${} is a value history entry and can't be assigned", name);
+            return;
+        }
+        match expr::parse(value).and_then(|expr| expr.eval(self)) {
+            Ok(val) if registers::is_register(name) => {
+                if let Err(err) = self.set_register(name, val) {
+                    println!("{}", err);
+                }
+            }
+            Ok(val) => {
+                self.convenience_vars.insert(name.to_string(), val);
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Writes `val` to one of the inferior's registers (`set $rax = 0x10`).
+    fn set_register(&mut self, name: &str, val: i64) -> Result<(), String> {
+        let inferior = self.inferior.as_ref().ok_or("the program is not being run")?;
+        // Outer frames' registers are only worked out from the stack, so there's nothing to
+        // write them back to
+        if self.selected_frame > 0 {
+            return Err("can only write registers in the innermost frame (frame 0)".to_string());
+        }
+        let mut regs = ptrace::getregs(inferior.tid()).map_err(|err| err.to_string())?;
+        *registers::register_mut(&mut regs, name).unwrap() = val as u64;
+        ptrace::setregs(inferior.tid(), regs).map_err(|err| err.to_string())
+    }
+
+    /// Handles `show <property>`.
+    fn show(&self, args: &[String]) {
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        match args.as_slice() {
+            ["architecture"] => match self.elf_class {
+                Some(ElfClass::Elf64) => {
+                    println!("The target architecture is i386:x86-64 (64-bit ELF).")
+                }
+                Some(ElfClass::Elf32) => println!(
+                    "The target architecture is i386 (32-bit ELF); 32-bit targets are not supported yet."
+                ),
+                None => println!("The target architecture is unknown (not an ELF file)."),
+            },
+            _ => println!("unknown property: show {}", args.join(" ")),
+        }
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        let mut lines = Vec::new();
+        lines.push(format!("{:<8}{:<4}{:<4}{:<19}{}", "Num", "Enb", "Ins", "Address", "What"));
+        for bp in self.breakpoints.iter() {
+            let (addr, what) = if bp.pending {
+                ("<PENDING>".to_string(), bp.location.clone())
+            } else {
+                (format!("{:#x}", bp.addr), self.describe_addr(bp.addr))
+            };
+            lines.push(format!(
+                "{:<8}{:<4}{:<4}{:<19}{}",
+                bp.number,
+                if bp.enabled { "y" } else { "n" },
+                match self.is_installed(bp) {
+                    Some(true) => "y",
+                    Some(false) => "n",
+                    None => "-",
+                },
+                addr,
+                what
+            ));
+            if let Some(condition) = &bp.condition {
+                lines.push(format!("        stop only if {}", condition));
+            }
+            if let Some(thread) = bp.thread {
+                lines.push(format!("        stop only in thread {}", thread));
+            }
+            if let Some(remaining) = bp.delete_after {
+                lines.push(format!("        delete after {} more stop(s)", remaining));
+            }
+            if bp.hardware {
+                lines.push("        hardware breakpoint".to_string());
+            }
+            if let Some((format, args)) = &bp.printf {
+                lines.push(format!("        printf {}", printf_spec(format, args)));
+            }
+            for command in &bp.commands {
+                lines.push(format!("        {}", command));
+            }
+            if bp.hit_count > 0 {
+                lines.push(format!("        breakpoint already hit {} time(s)", bp.hit_count));
+            }
+            if bp.ignore_count > 0 {
+                lines.push(format!("        will ignore next {} crossing(s)", bp.ignore_count));
+            }
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Returns whether `bp` is currently installed in the current inferior, as a trap byte or in
+    /// a debug register, or None if there's no live process.
+    fn is_installed(&self, bp: &Breakpoint) -> Option<bool> {
+        let inferior = self.inferior.as_ref().filter(|inf| inf.is_alive())?;
+        if bp.hardware {
+            return Some(inferior.has_hw_breakpoint(bp.addr));
+        }
+        Some(inferior.replaced_values.contains_key(&bp.addr))
+    }
+
+    fn print_breakpoint_details(&self, bp: &Breakpoint) {
+        println!("Breakpoint {}", bp.number);
+        println!("  enabled:   {}", if bp.enabled { "yes" } else { "no" });
+        if bp.pending {
+            println!("  address:   <PENDING>");
+            println!("  location:  {}", bp.location);
+        } else {
+            println!("  address:   {:#x}", bp.addr);
+            println!("  location:  {}", self.describe_addr(bp.addr));
+        }
+        println!(
+            "  installed: {}",
+            match self.is_installed(bp) {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "no process",
+            }
+        );
+        println!(
+            "  condition: {}",
+            bp.condition.as_ref().map(|s| s.as_str()).unwrap_or("none")
+        );
+        if let Some(remaining) = bp.delete_after {
+            println!("  deleted after {} more stop(s)", remaining);
+        }
+        println!("  hits:      {}", bp.hit_count);
+        if bp.ignore_count > 0 {
+            println!("  ignoring next {} hit(s)", bp.ignore_count);
+        }
+        if !bp.commands.is_empty() {
+            println!("  commands:");
+            for command in &bp.commands {
+                println!("    {}", command);
+            }
+        }
+    }
+
+    /// Walks the stack of the stopped inferior.
+    fn frames(&self) -> Result<Vec<Frame>, String> {
+        let inferior = self.inferior.as_ref().ok_or("no stack")?;
+        inferior
+            .frames(&self.debug_data, self.backtrace_past_main)
+            .map_err(|err| format!("failed to walk the stack, {}", err))
+    }
+
+    /// Selects frame `number` and prints it.
+    fn select_frame(&mut self, number: isize) {
+        let frames = match self.frames() {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        if number < 0 {
+            println!("bottom (innermost) frame selected; you cannot go down");
+            return;
+        }
+        let number = number as usize;
+        match frames.get(number) {
+            Some(frame) => {
+                self.selected_frame = number;
+                println!("{}", self.describe_frame(frame));
+                if let Some(line) = self.debug_data.get_line_from_addr(frame.line_addr()) {
+                    let lines = source_lines(&line.file).unwrap_or_default();
+                    if let Some(text) = lines.get(line.number.wrapping_sub(1)) {
+                        println!("{}\t{}", line.number, text);
+                    }
+                }
+            }
+            None => println!("initial frame selected; you cannot go up"),
+        }
+    }
+
+    /// Formats a stack frame with its function's arguments, e.g.
+    /// "#1 0x4011b5 in func2(a=42, b=5) at function_calls.c:13".
+    fn describe_frame(&self, frame: &Frame) -> String {
+        let func = match self.debug_data.get_function_containing(frame.rip) {
+            Some(func) => func,
+            None => return format!("#{} {:#x} in ??", frame.level, frame.rip),
+        };
+        let args = self.frame_arguments(frame, func);
+        let mut description =
+            format!("#{} {:#x} in {}({})", frame.level, frame.rip, func.name, args.join(", "));
+        if let Some(line) = self.debug_data.get_line_from_addr(frame.line_addr()) {
+            description.push_str(&format!(" at {}", line));
+        }
+        description
+    }
+
+    /// Returns `func`'s arguments in `frame`, as "name=value".
+    fn frame_arguments(&self, frame: &Frame, func: &Function) -> Vec<String> {
+        func.variables
+            .iter()
+            .filter(|var| var.is_parameter)
+            .map(|var| {
+                let value = self.frame_variable(frame, &var.location, &var.entity_type);
+                format!("{}={}", var.name, value)
+            })
+            .collect()
+    }
+
+    /// Describes the selected frame for `info frame`: its canonical frame address (the stack
+    /// pointer before the call that made it), and where the caller's %rbp and %rip were saved.
+    /// These come from following the frame pointers, so are only right once the function's
+    /// prologue has set up %rbp.
+    fn frame_info_lines(&self) -> Result<Vec<String>, String> {
+        let frames = self.frames()?;
+        let frame = *self.selected(&frames)?;
+        let inferior = self.inferior.as_ref().unwrap();
+        // Past the saved %rbp and the return address
+        let cfa = |frame: &Frame| frame.rbp + 16;
+        let saved_rip = match ptrace::read(inferior.pid(), (frame.rbp + 8) as ptrace::AddressType) {
+            Ok(rip) => format!("{:#x}", rip as u64),
+            Err(_) => "<unavailable>".to_string(),
+        };
+        let mut lines = vec![format!("Stack level {}, frame at {:#x}:", frame.level, cfa(&frame))];
+        let location = match self.debug_data.get_line_from_addr(frame.line_addr()) {
+            Some(line) => format!(" ({})", line),
+            None => String::new(),
+        };
+        let func = self.debug_data.get_function_containing(frame.rip);
+        lines.push(format!(
+            " rip = {:#x} in {}{}; saved rip = {}",
+            frame.rip,
+            func.map_or("??", |func| func.name.as_str()),
+            location,
+            saved_rip
+        ));
+        let mut neighbours = Vec::new();
+        if let Some(caller) = frames.get(frame.level + 1) {
+            neighbours.push(format!("called by frame at {:#x}", cfa(caller)));
+        }
+        if frame.level > 0 {
+            neighbours.push(format!("caller of frame at {:#x}", cfa(&frames[frame.level - 1])));
+        }
+        if !neighbours.is_empty() {
+            lines.push(format!(" {}", neighbours.join(", ")));
+        }
+        let args = func.map(|func| self.frame_arguments(&frame, func)).unwrap_or_default();
+        let args = format!(" Arglist at {:#x}, args: {}", frame.rbp, args.join(", "));
+        lines.push(args.trim_end().to_string());
+        lines.push(format!(
+            " Locals at {:#x}, Previous frame's sp is {:#x}",
+            frame.rbp,
+            cfa(&frame)
+        ));
+        lines.push(" Saved registers:".to_string());
+        lines.push(format!("  rbp at {:#x}, rip at {:#x}", frame.rbp, frame.rbp + 8));
+        Ok(lines)
+    }
+
+    /// Returns a line for each of `frame`'s local variables and its value, for `backtrace full`.
+    fn frame_locals(&self, frame: &Frame) -> Vec<String> {
+        let func = match self.debug_data.get_function_containing(frame.rip) {
+            Some(func) => func,
+            None => return Vec::new(),
+        };
+        let locals: Vec<String> = func
+            .variables
+            .iter()
+            .filter(|var| !var.is_parameter)
+            .map(|var| {
+                let value = self.frame_variable(frame, &var.location, &var.entity_type);
+                format!("        {} = {}", var.name, value)
+            })
+            .collect();
+        if locals.is_empty() {
+            vec!["No locals.".to_string()]
+        } else {
+            locals
+        }
+    }
+
+    /// Reads and formats the value of a variable in `frame`, or "<unavailable>".
+    fn frame_variable(&self, frame: &Frame, location: &Location, ty: &Type) -> String {
+        let inferior = self.inferior.as_ref().unwrap();
+        let value = ptrace::getregs(inferior.tid())
+            .map_err(|err| err.to_string())
+            .and_then(|mut regs| {
+                regs.rbp = frame.rbp as u64;
+                self.variable_addr(location, &regs)
+            })
+            .and_then(|addr| inferior.read_memory(addr, ty.size).map_err(|err| err.to_string()))
+            .and_then(|bytes| self.format_value(ty, &bytes));
+        match value {
+            Ok((_, text)) => text,
+            Err(_) => "<unavailable>".to_string(),
+        }
+    }
+
+    /// Picks the selected frame out of `frames`. The selection outlives the frame list it was
+    /// made from, and a later unwind may come up shorter (e.g. after `set backtrace past-main
+    /// off`), so it may be gone.
+    fn selected<'a>(&self, frames: &'a [Frame]) -> Result<&'a Frame, String> {
+        frames
+            .get(self.selected_frame)
+            .ok_or_else(|| format!("frame {} is no longer on the stack", self.selected_frame))
+    }
+
+    /// Returns the registers as seen from the selected frame. Only %rip, %rsp and %rbp are
+    /// recovered for outer frames.
+    fn frame_regs(&self) -> Result<user_regs_struct, String> {
+        let inferior = self.inferior.as_ref().ok_or("the program is not being run")?;
+        let mut regs = ptrace::getregs(inferior.tid()).map_err(|err| err.to_string())?;
+        if self.selected_frame > 0 {
+            let frames = self.frames()?;
+            let frame = self.selected(&frames)?;
+            regs.rip = frame.rip as u64;
+            regs.rbp = frame.rbp as u64;
+            // The callee's saved %rbp and return address are popped off on return
+            regs.rsp = frames[self.selected_frame - 1].rbp as u64 + 16;
+        }
+        Ok(regs)
+    }
+
+    /// Reads bytes from the running inferior, with our breakpoints' 0xcc bytes replaced by
+    /// the original instruction bytes, or from the executable if the program isn't running.
+    fn read_code(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => {
+                let mut bytes = inferior
+                    .read_memory_bulk(addr, len)
+                    .map_err(|err| format!("cannot access memory at address {:#x}, {}", addr, err))?;
+                for (bp_addr, orig_byte) in inferior.replaced_values.iter() {
+                    if addr <= *bp_addr && *bp_addr < addr + len {
+                        bytes[bp_addr - addr] = *orig_byte;
+                    }
+                }
+                Ok(bytes)
+            }
+            None => self
+                .elf
+                .as_ref()
+                .and_then(|elf| elf.read(addr, len))
+                .ok_or(format!("cannot access memory at address {:#x}", addr)),
+        }
+    }
+
+    /// Describes where an address (an expression, or a location as for `break`) is, as
+    /// `info symbol`: e.g. "main + 4 in section .text of /path/to/program".
+    fn symbol_info(&self, location: &str) -> Result<String, String> {
+        let addr = match expr::parse(location).and_then(|expr| expr.eval(self)) {
+            Ok(addr) => addr as usize,
+            Err(_) => self.parse_addr(location.trim_start_matches('*'))?,
+        };
+        let symbol = self
+            .symbolize(addr)
+            .ok_or_else(|| format!("No symbol matches {}.", location))?
+            .replacen('+', " + ", 1);
+        let mut info = symbol;
+        if let Some(section) = self.elf.as_ref().and_then(|elf| elf.section_containing(addr)) {
+            info = format!("{} in section {}", info, section.name);
+        }
+        let mapping = self.inferior.as_ref().and_then(|inferior| {
+            inferior.mappings().ok()?.into_iter().find(|m| m.start <= addr && addr < m.end)
+        });
+        let objfile = match mapping {
+            Some(mapping) if !mapping.path.is_empty() => mapping.path,
+            _ => self.target.clone(),
+        };
+        Ok(format!("{} of {}", info, objfile))
+    }
+
+    /// Names the function or global variable containing `addr`, e.g. "main+4".
+    fn symbolize(&self, addr: usize) -> Option<String> {
+        let (name, offset) = match self.debug_data.get_function_containing(addr) {
+            Some(func) => (func.name.clone(), addr - func.address),
+            None => {
+                let (var, offset) = self.debug_data.get_global_containing(addr)?;
+                (var.name.clone(), offset)
+            }
+        };
+        if offset == 0 {
+            Some(name)
+        } else {
+            Some(format!("{}+{}", name, offset))
+        }
+    }
+
+    /// Prints the range of addresses that belong to the source line at `location` (or at the
+    /// current pc).
+    fn info_line(&self, location: Option<&str>) {
+        if !self.require_debug_info() {
+            return;
+        }
+        let addr = match location {
+            // gdb spells raw addresses *ADDRESS; accept that as well as a bare address
+            Some(location) => match self.parse_addr(location.trim_start_matches('*')) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => match self.frames().ok().and_then(|frames| {
+                frames.get(self.selected_frame).map(|frame| frame.line_addr())
+            }) {
+                Some(addr) => addr,
+                None => {
+                    println!("no frame selected");
+                    return;
+                }
+            },
+        };
+        match self.debug_data.get_line_range(addr) {
+            Some((line, end)) => println!(
+                "Line {} of \"{}\" starts at address {:#x} <{}> and ends at {:#x} <{}>.",
+                line.number,
+                line.file,
+                line.address,
+                self.symbolize(line.address).unwrap_or_else(|| "??".to_string()),
+                end,
+                self.symbolize(end).unwrap_or_else(|| "??".to_string()),
+            ),
+            None => println!("no line number information for address {:#x}", addr),
+        }
+    }
+
+    /// Returns the numbered source lines around a location, or around the selected frame's
+    /// line (`main` if the program isn't running).
+    fn list_source(&self, location: Option<&str>) -> Result<Vec<String>, String> {
+        if !self.debug_data.has_debug_info() {
+            return Err(NO_DEBUG_INFO.to_string());
+        }
+        let addr = match location {
+            Some(location) => self.parse_addr(location.trim_start_matches('*'))?,
+            None if self.inferior.is_some() => self.selected(&self.frames()?)?.line_addr(),
+            None => self.parse_addr("main")?,
+        };
+        let line = self
+            .debug_data
+            .get_line_from_addr(addr)
+            .ok_or_else(|| format!("no line number information for address {:#x}", addr))?;
+        let source = std::fs::read_to_string(&line.file)
+            .map_err(|err| format!("failed to read {}, {}", line.file, err))?;
+        let first = line.number.saturating_sub(LIST_CONTEXT).max(1);
+        Ok(source
+            .lines()
+            .enumerate()
+            .skip(first - 1)
+            .take(2 * LIST_CONTEXT + 1)
+            .map(|(i, text)| format!("{}\t{}", i + 1, text.trim_end()))
+            .collect())
+    }
+
+    fn disassemble(&self, location: Option<&str>) {
+        if !self.require_debug_info() {
+            return;
+        }
+        let addr = match location {
+            Some(location) => match self.parse_addr(location) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None if self.inferior.as_ref().filter(|inf| inf.is_alive()).is_none() => {
+                println!("no frame selected");
+                return;
+            }
+            None => match self.frame_regs() {
+                Ok(regs) => regs.rip as usize,
+                Err(err) => {
+                    println!("failed to read registers, {}", err);
+                    return;
+                }
+            },
+        };
+        let func = match self.debug_data.get_function_containing(addr) {
+            Some(func) => func,
+            None => {
+                println!("no function contains specified address");
+                return;
+            }
+        };
+        let instructions = match self
+            .read_code(func.address, func.text_length)
+            .and_then(|code| disasm::disassemble(&code, func.address))
+        {
+            Ok(instructions) => instructions,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let pc = self.frame_regs().ok().map(|regs| regs.rip as usize);
+        let mut lines = Vec::new();
+        lines.push(format!("Dump of assembler code for function {}:", func.name));
+        let mut last_line = None;
+        // Each source file is read once, however many lines of it the function spans
+        let mut sources: HashMap<String, Option<Vec<String>>> = HashMap::new();
+        for insn in instructions {
+            if self.debug_data.is_line_start(insn.address) {
+                if let Some(line) = self.debug_data.get_line_from_addr(insn.address) {
+                    if last_line != Some(line.number) {
+                        let source = sources
+                            .entry(line.file.clone())
+                            .or_insert_with(|| source_lines(&line.file));
+                        let text = source
+                            .as_ref()
+                            .and_then(|source| source.get(line.number.wrapping_sub(1)));
+                        lines.push(match text {
+                            Some(text) => format!("{}\t{}", line.number, text),
+                            None => format!("{}:{}", line.file, line.number),
+                        });
+                        last_line = Some(line.number);
+                    }
+                }
+            }
+            let mut annotation = String::new();
+            if let Some(target) = insn.branch_target.or(insn.rip_relative_target) {
+                if let Some(symbol) = self.symbolize(target) {
+                    annotation = format!(" <{}>", symbol);
+                }
+            }
+            lines.push(format!(
+                "{}{:#x} <+{}>:\t{:<6} {}{}",
+                if pc == Some(insn.address) { "=> " } else { "   " },
+                insn.address,
+                insn.address - func.address,
+                insn.mnemonic,
+                insn.operands,
+                annotation
+            ));
+        }
+        lines.push("End of assembler dump.".to_string());
+        self.pager.print(&lines);
+    }
+
+    /// Prints memory starting at the address `addr_expr` evaluates to, as numbers, characters,
+    /// strings (`s`) or instructions (`i`).
+    fn examine(&self, fmt: &ExamineFormat, addr_expr: &str) {
+        if self.inferior.as_ref().filter(|inf| inf.is_alive()).is_none() {
+            println!("please run target first");
+            return;
+        }
+        let addr = match expr::parse(addr_expr).and_then(|expr| expr.eval(self)) {
+            Ok(addr) => addr as usize,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let label = |addr: usize| match self.symbolize(addr) {
+            Some(symbol) => format!("{:#x} <{}>:", addr, symbol),
+            None => format!("{:#x}:", addr),
+        };
+        let mut lines = Vec::new();
+        if fmt.format == 'i' {
+            let pc = self.dollar("pc").ok().map(|pc| pc as usize);
+            // x86-64 instructions are at most 15 bytes long
+            let instructions = match self
+                .read_code(addr, fmt.count * 15)
+                .and_then(|code| disasm::disassemble(&code, addr))
+            {
+                Ok(instructions) => instructions,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            };
+            for insn in instructions.iter().take(fmt.count) {
+                let marker = if Some(insn.address) == pc { "=> " } else { "   " };
+                lines.push(format!(
+                    "{}{}\t{:<6} {}",
+                    marker,
+                    label(insn.address),
+                    insn.mnemonic,
+                    insn.operands
+                ));
+            }
+        } else if fmt.format == 's' {
+            let inferior = self.inferior.as_ref().unwrap();
+            let mut addr = addr;
+            for _ in 0..fmt.count {
+                let bytes = match inferior.read_string(addr, MAX_STRING_LEN) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        println!("cannot access memory at address {:#x}, {}", addr, err);
+                        break;
+                    }
+                };
+                let more = if bytes.len() == MAX_STRING_LEN { "..." } else { "" };
+                lines.push(format!("{}\t{}{}", label(addr), string_literal(&bytes), more));
+                addr += bytes.len() + 1;
+            }
+        } else {
+            let bytes = match self.read_code(addr, fmt.count * fmt.size) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            };
+            let per_line = match fmt.size {
+                8 => 2,
+                4 => 4,
+                _ => 8,
+            };
+            for (i, unit) in bytes.chunks(fmt.size).enumerate() {
+                if i % per_line == 0 {
+                    lines.push(label(addr + i * fmt.size));
+                }
+                let mut buf = [0u8; 8];
+                buf[..fmt.size].copy_from_slice(unit);
+                let text = format::format_integer(
+                    u64::from_le_bytes(buf),
+                    fmt.size,
+                    fmt.format,
+                    true,
+                );
+                let line = lines.last_mut().unwrap();
+                line.push('\t');
+                line.push_str(&text);
+            }
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Reads the memory for `hexdump`, from the executable if the program isn't running.
+    fn hexdump_lines(&self, addr: &str, len: &str) -> Result<Vec<String>, String> {
+        let eval = |s: &str| expr::parse(s).and_then(|expr| expr.eval(self));
+        let (addr, len) = (eval(addr)? as usize, eval(len)?);
+        if len <= 0 {
+            return Err("the length must be positive".to_string());
+        }
+        if len as usize > MAX_HEXDUMP_LEN {
+            return Err(format!("cannot dump more than {} bytes at once", MAX_HEXDUMP_LEN));
+        }
+        Ok(hexdump(addr, &self.read_code(addr, len as usize)?))
+    }
+
+    /// Saves a copy of the memory in [start, end) under `name`.
+    fn snapshot(&mut self, name: String, start: &str, end: &str) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                println!("please run target first");
+                return;
+            }
+        };
+        let eval = |s: &str| expr::parse(s).and_then(|expr| expr.eval(self));
+        let (start, end) = match (eval(start), eval(end)) {
+            (Ok(start), Ok(end)) if start < end => (start as usize, end as usize),
+            (Ok(_), Ok(_)) => {
+                println!("the end of the region must come after its start");
+                return;
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        match inferior.read_memory(start, end - start) {
+            Ok(bytes) => {
+                println!("saved {} bytes at {:#x} as {}", bytes.len(), start, name);
+                self.snapshots.insert(name, (start, bytes));
+            }
+            Err(err) => println!("cannot access memory at address {:#x}, {}", start, err),
+        }
+    }
+
+    /// Re-reads a snapshot's region and prints each run of bytes that changed, old → new.
+    fn diff_snapshot(&self, name: &str) {
+        let (start, old) = match self.snapshots.get(name) {
+            Some(snapshot) => snapshot,
+            None => {
+                println!("no snapshot named {}", name);
+                return;
+            }
+        };
+        let new = match self.inferior.as_ref().map(|inf| inf.read_memory(*start, old.len())) {
+            Some(Ok(bytes)) => bytes,
+            Some(Err(err)) => {
+                println!("cannot access memory at address {:#x}, {}", start, err);
+                return;
+            }
+            None => {
+                println!("please run target first");
+                return;
+            }
+        };
+        let hex = |bytes: &[u8]| {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ")
+        };
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < old.len() {
+            if old[offset] == new[offset] {
+                offset += 1;
+                continue;
+            }
+            // Group adjacent changed bytes, at most 16 to a line
+            let run_start = offset;
+            while offset < old.len() && old[offset] != new[offset] && offset - run_start < 16 {
+                offset += 1;
+            }
+            let addr = start + run_start;
+            let label = match self.symbolize(addr) {
+                Some(symbol) => format!("{:#x} <{}>", addr, symbol),
+                None => format!("{:#x}", addr),
+            };
+            lines.push(format!(
+                "{} (+{}): {} → {}",
+                label,
+                run_start,
+                hex(&old[run_start..offset]),
+                hex(&new[run_start..offset])
+            ));
+        }
+        if lines.is_empty() {
+            println!("no changes in {}", name);
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Dumps the raw line-number program rows, similar to `readelf --debug-dump=decodedline`.
+    fn print_line_tables(&self, file: Option<&str>) {
+        let tables = self.debug_data.line_tables(file);
+        if tables.is_empty() {
+            println!("no compilation unit matches {}", file.unwrap_or(""));
+        }
+        let mut lines = Vec::new();
+        for (unit, rows) in tables {
+            lines.push(format!("compilation unit: {}", unit));
+            lines.push(format!(
+                "{:<18} {:>6} {:>7} {:>7}  {}",
+                "ADDRESS", "LINE", "IS_STMT", "END_SEQ", "FILE"
+            ));
+            for row in rows {
+                lines.push(format!(
+                    "{:<18} {:>6} {:>7} {:>7}  {}",
+                    format!("{:#x}", row.address),
+                    row.line,
+                    if row.is_stmt { "Y" } else { "" },
+                    if row.end_sequence { "Y" } else { "" },
+                    row.file
+                ));
+            }
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Reads variable `name`, in the selected frame if it's a local, and returns its type and
+    /// bytes. Without a process, globals are read from the executable's .data/.bss.
+    fn variable_value(&self, name: &str) -> Result<(Type, Vec<u8>), String> {
+        if !self.debug_data.has_debug_info() {
+            return Err(NO_DEBUG_INFO.to_string());
+        }
+        let inferior = match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => inferior,
+            None => return self.static_variable(name),
+        };
+        let regs = self.frame_regs()?;
+        let var = self
+            .debug_data
+            .lookup_variable(regs.rip as usize, name)
+            .ok_or(format!("no symbol \"{}\" in current context", name))?;
+        let addr = self.variable_addr(&var.location, &regs)?;
+        let bytes = inferior
+            .read_memory(addr, var.entity_type.size)
+            .map_err(|err| format!("cannot access memory at address {:#x}, {}", addr, err))?;
+        Ok((var.entity_type.clone(), bytes))
+    }
+
+    /// Formats a value of type `ty`: scalars as `format_scalar` does, structs as `{x = 3, y = 7}`
+    /// and arrays as `{1, 2, 3}`, showing at most `print_elements` elements. char arrays and
+    /// Rust `&str`s are shown as strings, and the elements of Rust slices are read from memory.
+    /// Aggregates are 0 in the value history.
+    fn format_value(&self, ty: &Type, bytes: &[u8]) -> Result<(i64, String), String> {
+        match ty.kind {
+            TypeKind::Struct => {
+                if let Some(text) = self.format_slice(ty, bytes)? {
+                    return Ok((0, text));
+                }
+                let mut fields = Vec::new();
+                for member in &ty.members {
+                    let end = member.offset + member.ty.size;
+                    let member_bytes =
+                        bytes.get(member.offset..end).ok_or("struct member out of range")?;
+                    let (_, text) = self.format_value(&member.ty, member_bytes)?;
+                    fields.push(format!("{} = {}", member.name, text));
+                }
+                Ok((0, format!("{{{}}}", fields.join(", "))))
+            }
+            TypeKind::Array => {
+                let element = ty.element.as_ref().unwrap();
+                if element.kind == TypeKind::Char {
+                    // Up to the terminating NUL, if there is one
+                    let len = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+                    return Ok((0, self.format_string(&bytes[..len])));
+                }
+                let elements = bytes.chunks(element.size.max(1)).collect::<Vec<_>>();
+                Ok((0, self.format_elements(element, &elements, elements.len())?))
+            }
+            // char pointers are followed to the string they point to
+            TypeKind::Pointer
+                if ty.element.as_ref().map_or(false, |element| element.kind == TypeKind::Char) =>
+            {
+                let (value, text) = format_scalar(ty, bytes)?;
+                if value == 0 {
+                    return Ok((value, text));
+                }
+                let string = match self.read_c_string(value as usize) {
+                    Ok(string) => self.format_string(&string),
+                    Err(err) => format!("<{}>", err),
+                };
+                Ok((value, format!("{} {}", text, string)))
+            }
+            _ => format_scalar(ty, bytes),
+        }
+    }
+
+    /// Formats a Rust slice or `&str` (a struct of `data_ptr` and `length`), reading its
+    /// elements from memory. Returns None for other structs.
+    fn format_slice(&self, ty: &Type, bytes: &[u8]) -> Result<Option<String>, String> {
+        let (data_ptr, length) = match ty.members.as_slice() {
+            [data_ptr, length] if data_ptr.name == "data_ptr" && length.name == "length" => {
+                (data_ptr, length)
+            }
+            _ => return Ok(None),
+        };
+        let element = match &data_ptr.ty.element {
+            Some(element) => element,
+            None => return Ok(None),
+        };
+        let field = |member: &Member| {
+            bytes
+                .get(member.offset..member.offset + member.ty.size)
+                .filter(|_| is_integer_type(&member.ty))
+                .map(|field| integer_value(&member.ty, field) as usize)
+                .ok_or("malformed slice")
+        };
+        let (addr, len) = (field(data_ptr)?, field(length)?);
+        let shown = if self.print_elements > 0 { len.min(self.print_elements) } else { len };
+        let data = self.read_code(addr, shown * element.size)?;
+        if ty.name == "&str" {
+            let text = string_literal(&data);
+            return Ok(Some(if shown < len { format!("{}...", text) } else { text }));
+        }
+        let elements = data.chunks(element.size.max(1)).collect::<Vec<_>>();
+        Ok(Some(self.format_elements(element, &elements, len)?))
+    }
+
+    /// Formats elements of type `element` as `{1, 2, 3}`, stopping after `print_elements`.
+    /// `total` is the number of elements there are, which may be more than were read.
+    fn format_elements(
+        &self,
+        element: &Type,
+        elements: &[&[u8]],
+        total: usize,
+    ) -> Result<String, String> {
+        let limit = if self.print_elements > 0 { self.print_elements } else { total };
+        let mut texts = Vec::new();
+        for bytes in elements.iter().take(limit) {
+            texts.push(self.format_value(element, bytes)?.1);
+        }
+        if total > texts.len() {
+            texts.push("...".to_string());
+        }
+        Ok(format!("{{{}}}", texts.join(", ")))
+    }
+
+    /// Reads the NUL-terminated string at `addr`, without the NUL, from the program's memory or
+    /// from the executable if it isn't running. At most one byte more than `print_elements` is
+    /// read, so that `format_string` can tell the string was cut off.
+    fn read_c_string(&self, addr: usize) -> Result<Vec<u8>, String> {
+        let max = match self.print_elements {
+            0 => usize::MAX,
+            limit => limit + 1,
+        };
+        let error = |err| format!("cannot access memory at address {:#x}{}", addr, err);
+        match self.inferior.as_ref().filter(|inf| inf.is_alive()) {
+            Some(inferior) => {
+                inferior.read_string(addr, max).map_err(|err| error(format!(", {}", err)))
+            }
+            None => {
+                let elf = self.elf.as_ref().ok_or_else(|| error(String::new()))?;
+                let mut bytes = Vec::new();
+                while bytes.len() < max {
+                    match elf.read(addr + bytes.len(), 1) {
+                        Some(byte) if byte[0] == 0 => break,
+                        Some(byte) => bytes.push(byte[0]),
+                        None => return Err(error(String::new())),
+                    }
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Formats bytes as a string literal, stopping after `print_elements` characters.
+    fn format_string(&self, bytes: &[u8]) -> String {
+        if self.print_elements > 0 && bytes.len() > self.print_elements {
+            format!("{}...", string_literal(&bytes[..self.print_elements]))
+        } else {
+            string_literal(bytes)
+        }
+    }
+
+    /// Reads a global variable's initial value from the executable, for when the program isn't
+    /// running.
+    fn static_variable(&self, name: &str) -> Result<(Type, Vec<u8>), String> {
+        let var = self.debug_data.lookup_global(name).ok_or("the program is not being run")?;
+        let addr = match var.location {
+            Location::Address(addr) => addr,
+            _ => return Err("the program is not being run".to_string()),
+        };
+        let bytes = self
+            .elf
+            .as_ref()
+            .and_then(|elf| elf.read(addr, var.entity_type.size))
+            .ok_or(format!("cannot read \"{}\" from the executable", name))?;
+        Ok((var.entity_type.clone(), bytes))
+    }
+
+    /// Prints, for every address that should hold one of our traps, the byte actually in memory
+    /// and the original byte we'll restore, flagging anything inconsistent.
+    fn print_breakpoint_bytes(&self) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                println!("please run target first");
+                return;
+            }
+        };
+        // Temporary traps (e.g. from nexti) are only in replaced_values
+        let mut addrs = self.breakpoints.addrs(false);
+        addrs.extend(inferior.replaced_values.keys());
+        addrs.sort();
+        addrs.dedup();
+        let mut lines = vec![format!("{:<18} {:>6} {:>8}", "ADDRESS", "MEMORY", "ORIGINAL")];
+        for addr in addrs {
+            let memory = inferior.read_memory(addr, 1).ok().map(|bytes| bytes[0]);
+            let original = inferior.replaced_values.get(&addr).copied();
+            let problem = match (memory, original) {
+                (None, _) => "cannot read memory",
+                (Some(_), None) => "not installed",
+                (Some(byte), Some(_)) if byte != 0xcc => "memory no longer holds 0xcc",
+                (Some(_), Some(0xcc)) => "recorded original is itself 0xcc",
+                _ => "",
+            };
+            let show = |byte: Option<u8>| byte.map_or("??".to_string(), |b| format!("{:#04x}", b));
+            let line = format!(
+                "{:<18} {:>6} {:>8}  {}",
+                format!("{:#x}", addr),
+                show(memory),
+                show(original),
+                problem
+            );
+            lines.push(line.trim_end().to_string());
+        }
+        self.pager.print(&lines);
+    }
+
+    /// Computes the address of a variable in the frame described by `regs`.
+    fn variable_addr(&self, location: &Location, regs: &user_regs_struct) -> Result<usize, String> {
+        match *location {
+            Location::Address(addr) => Ok(addr),
+            // DWARF frame offsets are relative to the canonical frame address, which lies past
+            // the saved %rbp and return address
+            Location::FramePointerOffset(offset) => {
+                Ok((regs.rbp as isize + 16 + offset) as usize)
+            }
+            // The main executable's TLS block sits just below the thread pointer (%fs_base).
+            // Other threads' copies aren't supported yet.
+            Location::TlsOffset(offset) => {
+                let block_offset = self
+                    .elf
+                    .as_ref()
+                    .and_then(|elf| elf.tls_block_offset())
+                    .ok_or("cannot locate the thread-local storage segment")?;
+                Ok(regs.fs_base as usize - block_offset + offset)
+            }
+        }
+    }
+
+    /// Reads the lines of a breakpoint command list up to `end`. An empty list removes the
+    /// breakpoint's commands.
+    fn read_command_list(&mut self, number: usize) -> Vec<String> {
+        if pager::is_tty(libc::STDIN_FILENO) {
+            println!("Type commands for breakpoint {}, one per line.", number);
+            println!("End with a line saying just \"end\".");
+        }
+        let mut commands = Vec::new();
+        loop {
+            // The list comes from the script if we're sourcing one
+            let line = match self.script_lines.pop_front() {
+                Some(line) => Ok(line),
+                None => self.readline.readline(">"),
+            };
+            match line {
+                Ok(line) if line.trim() == "end" => break,
+                Ok(line) if line.trim().is_empty() => {}
+                Ok(line) => commands.push(line.trim().to_string()),
+                Err(_) => break,
+            }
+        }
+        commands
+    }
+
+    /// Takes the next command from the pending breakpoint command list, or else from the file
+    /// being sourced, if any.
+    fn next_pending_command(&mut self) -> Option<DebuggerCommand> {
+        loop {
+            let line = match self.pending_commands.pop_front() {
+                Some(line) => line,
+                None => self.script_lines.pop_front()?,
+            };
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            match self.parse_command(&line) {
+                Some(cmd) => return Some(cmd),
+                None => println!("Unrecognized command: {}", line.trim()),
+            }
+        }
+    }
+
+    /// Parses a line of input. `$bpnum`, the number of the last breakpoint set, may stand in
+    /// for any argument, which is how saved breakpoints refer to themselves.
+    fn parse_command(&self, line: &str) -> Option<DebuggerCommand> {
+        let bpnum = self.convenience_vars.get("bpnum").map(|n| n.to_string());
+        let tokens: Vec<&str> = line
+            .split_whitespace()
+            .map(|token| match &bpnum {
+                Some(bpnum) if token == "$bpnum" => bpnum.as_str(),
+                _ => token,
+            })
+            .collect();
+        DebuggerCommand::from_tokens(&tokens)
+    }
+
+    /// Returns commands that recreate the breakpoint table, for `save breakpoints`. Locations
+    /// are saved as the user wrote them so that they're resolved again when loaded. Thread IDs are
+    /// saved too, though they can only be loaded while that thread is still running.
+    fn breakpoints_script(&self) -> String {
+        let mut script = String::new();
+        for bp in self.breakpoints.iter() {
+            let set = if let Some((format, args)) = &bp.printf {
+                format!("trace {} {}", bp.location, printf_spec(format, args))
+            } else if bp.hardware {
+                format!("hbreak {}", bp.location)
+            } else {
+                let mut set = match bp.delete_after {
+                    Some(1) if bp.thread.is_none() => format!("tbreak {}", bp.location),
+                    Some(count) => format!("break {} -count {}", bp.location, count),
+                    None => format!("break {}", bp.location),
+                };
+                if let Some(tid) = bp.thread {
+                    set.push_str(&format!(" thread {}", tid));
+                }
+                set
+            };
+            script.push_str(&format!("{}\n", set));
+            if let Some(condition) = &bp.condition {
+                script.push_str(&format!("condition $bpnum {}\n", condition));
+            }
+            if bp.ignore_count > 0 {
+                script.push_str(&format!("ignore $bpnum {}\n", bp.ignore_count));
+            }
+            if !bp.enabled {
+                script.push_str("disable $bpnum\n");
+            }
+            if !bp.commands.is_empty() {
+                script.push_str("commands $bpnum\n");
+                for command in &bp.commands {
+                    script.push_str(&format!("  {}\n", command));
+                }
+                script.push_str("end\n");
+            }
+        }
+        script
+    }
+
+    /// Returns the next command to run: a pending breakpoint or sourced command if there is one,
+    /// and otherwise one the user types at the prompt, re-prompting until the line parses. While
+    /// the inferior runs in the background, stops are reported as they happen until the user
+    /// starts typing. Each line is added to the history, saved to disk under `set history save`,
+    /// and parsed with `$bpnum` standing for the last breakpoint number.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        if let Some(cmd) = self.next_pending_command() {
+            return cmd;
+        }
+        let prompt = "(deet) ";
+        loop {
+            self.wait_for_input(prompt);
+            // Print prompt and get next line of user input
+            match self.readline.readline(prompt) {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return DebuggerCommand::Quit(None);
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() == 0 {
+                        continue;
+                    }
+                    self.readline.add_history_entry(line.as_str());
+                    if self.history_save {
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
+                    }
+                    if let Some(cmd) = self.parse_command(&line) {
+                        return cmd;
+                    } else {
+                        println!("Unrecognized command.");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Installs a breakpoint at `addr` in `inferior`, as a trap byte or in a debug register, and
+/// reports any failure. Returns whether the breakpoint was installed.
+fn install_trap(inferior: &mut Inferior, addr: usize, hardware: bool) -> bool {
+    let installed = if hardware {
+        inferior.install_hw_breakpoint(addr)
+    } else {
+        inferior.install_breakpoint(addr).map(|_| true)
+    };
+    match installed {
+        Ok(true) => return true,
+        Ok(false) => println!("no free debug register for hardware breakpoint at {:#x}", addr),
+        Err(err) => println!("failed to set breakpoint at position {:#x}, {}", addr, err),
+    }
+    false
+}
+
+/// Puts watchpoint `wp` in one of `inferior`'s debug registers, and reports any failure.
+fn install_watch(inferior: &mut Inferior, wp: &Watchpoint) {
+    match inferior.install_watchpoint(wp.addr, wp.len, wp.access()) {
+        Ok(true) => {}
+        Ok(false) => println!("no free debug register for watchpoint {}", wp.number),
+        Err(err) => println!("failed to set watchpoint {}, {}", wp.number, err),
+    }
+}
+
+/// Names a kind of watchpoint the way messages refer to it.
+fn watchpoint_kind(kind: WatchKind) -> &'static str {
+    match kind {
+        WatchKind::Write => "watchpoint",
+        WatchKind::Read => "read watchpoint",
+        WatchKind::Access => "access (read/write) watchpoint",
+    }
+}
+
+/// Writes a tracepoint's format and arguments as `trace` takes them: `"format", arg, ...`.
+fn printf_spec(format: &str, args: &[String]) -> String {
+    let mut spec = format!("{:?}", format);
+    for arg in args {
+        spec.push_str(&format!(", {}", arg));
+    }
+    spec
+}
+
+/// Formats `values` like printf(3) would with `format`. Supports the integer conversions
+/// %d, %i, %u, %x, %X, %o and %c, with the l, ll, h and hh length modifiers, and %%.
+fn format_printf(format: &str, values: &[i64]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut values = values.iter();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut conversion = chars.next().ok_or("format ends in the middle of a conversion")?;
+        while conversion == 'l' || conversion == 'h' {
+            conversion = chars.next().ok_or("format ends in the middle of a conversion")?;
+        }
+        if conversion == '%' {
+            out.push('%');
+            continue;
+        }
+        let value = *values.next().ok_or("not enough arguments for the format")?;
+        match conversion {
+            'd' | 'i' => out.push_str(&value.to_string()),
+            'u' => out.push_str(&(value as u64).to_string()),
+            'x' => out.push_str(&format!("{:x}", value)),
+            'X' => out.push_str(&format!("{:X}", value)),
+            'o' => out.push_str(&format!("{:o}", value)),
+            'c' => out.push(value as u8 as char),
+            c => return Err(format!("unsupported format conversion %{}", c)),
+        }
+    }
+    if values.next().is_some() {
+        return Err("more arguments than the format uses".to_string());
+    }
+    Ok(out)
+}
+
+/// Returns whether `signal` is passed straight on to the program rather than stopping it, unless
+/// it's caught. These are the ones programs use routinely, as in GDB's defaults.
+fn passes_silently(signal: Signal) -> bool {
+    match signal {
+        Signal::SIGALRM
+        | Signal::SIGURG
+        | Signal::SIGCHLD
+        | Signal::SIGIO
+        | Signal::SIGVTALRM
+        | Signal::SIGPROF
+        | Signal::SIGWINCH => true,
+        _ => false,
+    }
+}
+
+/// Returns the name of syscall `number`, or the number itself if we don't know its name.
+fn syscall_name(number: u64) -> String {
+    syscalls::name(number).map_or(number.to_string(), |name| name.to_string())
+}
+
+/// Splits a location into its file (if any) and the line, function or address within it.
+fn split_location(location: &str) -> (Option<&str>, &str) {
+    match location.rfind(':') {
+        Some(idx) => (Some(&location[..idx]), &location[idx + 1..]),
+        None => (None, location),
+    }
+}
+
+/// Returns whether the part of a location after the file names a function.
+fn is_function_spec(spec: &str) -> bool {
+    !spec.is_empty()
+        && !spec.to_lowercase().starts_with("0x")
+        && !spec.starts_with('+')
+        && !spec.starts_with('-')
+        && spec.parse::<usize>().is_err()
+        && split_offset(spec).is_none()
+}
+
+/// Splits `func+0x1c` or `func-8` into the function name and the signed offset.
+fn split_offset(spec: &str) -> Option<(&str, &str)> {
+    let idx = spec.char_indices().skip(1).find(|&(_, c)| c == '+' || c == '-')?.0;
+    Some(spec.split_at(idx))
+}
+
+/// Parses the value of a boolean setting.
+fn parse_on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" | "1" | "yes" | "enable" => Some(true),
+        "off" | "0" | "no" | "disable" => Some(false),
+        _ => None,
+    }
+}
+
+/// Turns off echo and line buffering on the terminal, so that a key typed is available at once
+/// and left unechoed. Returns the settings to restore afterwards.
+fn quiet_input() -> Option<libc::termios> {
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return None;
+    }
+    let mut quiet = saved;
+    quiet.c_lflag &= !(libc::ICANON | libc::ECHO);
+    quiet.c_cc[libc::VMIN] = 1;
+    quiet.c_cc[libc::VTIME] = 0;
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &quiet) };
+    Some(saved)
+}
+
+/// Waits up to `timeout_ms` for input on stdin. End of input counts, as there is something to
+/// read, and so does an error, so that the caller goes on to find out what it is.
+fn input_ready(timeout_ms: i32) -> bool {
+    let mut fds = [libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 }];
+    unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) != 0 }
+}
+
+/// Returns the lines of the source file at `path`, without trailing whitespace, if it can be
+/// read.
+fn source_lines(path: &str) -> Option<Vec<String>> {
+    let source = std::fs::read_to_string(path).ok()?;
+    Some(source.lines().map(|line| line.trim_end().to_string()).collect())
+}
+
+/// Returns whether values of type `ty` can be handled as integers. Characters, booleans and
+/// pointers can.
+fn is_integer_type(ty: &Type) -> bool {
+    ty.size > 0 && ty.size <= 8 && ty.kind != TypeKind::Float && ty.kind != TypeKind::Struct
+}
+
+/// Interprets the little-endian bytes of a value of integer type `ty`.
+fn integer_value(ty: &Type, bytes: &[u8]) -> i64 {
+    let size = bytes.len();
+    let mut buf = [0u8; 8];
+    buf[..size].copy_from_slice(bytes);
+    let val = i64::from_le_bytes(buf);
+    // Sign-extend signed types narrower than 64 bits
+    let signed = match ty.kind {
+        TypeKind::Signed | TypeKind::Enum => true,
+        TypeKind::Char => !ty.name.contains("unsigned"),
+        _ => false,
+    };
+    if signed && size < 8 {
+        let shift = 64 - 8 * size as u32;
+        (val << shift) >> shift
+    } else {
+        val
+    }
+}
+
+/// Describes type `ty` for `ptype`. Structs (including ones pointed to or in arrays) are shown
+/// with each member's offset and size, and enums with their values.
+fn type_lines(ty: &Type) -> Vec<String> {
+    // The struct or enum under any pointers and arrays, and what they add to its name
+    let mut base = ty;
+    while let (TypeKind::Pointer, Some(element)) | (TypeKind::Array, Some(element)) =
+        (base.kind, &base.element)
+    {
+        base = element;
+    }
+    let suffix = if ty.name.starts_with(&base.name) { &ty.name[base.name.len()..] } else { "" };
+    match base.kind {
+        TypeKind::Struct => {
+            let declarations: Vec<String> = base
+                .members
+                .iter()
+                .map(|member| format!("{};", declaration(&member.ty.name, &member.name)))
+                .collect();
+            let width = declarations.iter().map(|decl| decl.len()).max().unwrap_or(0);
+            let mut lines = vec![format!("type = {} {{", base.name)];
+            for (member, decl) in base.members.iter().zip(declarations) {
+                lines.push(format!(
+                    "    {:<width$}  /* offset {}, size {} */",
+                    decl,
+                    member.offset,
+                    member.ty.size,
+                    width = width
+                ));
+            }
+            lines.push(format!("}}{} /* size {} */", suffix, base.size));
+            lines
+        }
+        TypeKind::Enum => {
+            let mut next = 0;
+            let enumerators: Vec<String> = base
+                .enumerators
+                .iter()
+                .map(|(name, value)| {
+                    // Values are only spelled out where they don't follow on from the last one
+                    let text =
+                        if *value == next { name.clone() } else { format!("{} = {}", name, value) };
+                    next = value + 1;
+                    text
+                })
+                .collect();
+            vec![format!("type = {} {{{}}}{}", base.name, enumerators.join(", "), suffix)]
+        }
+        _ => vec![format!("type = {}", ty.name)],
+    }
+}
+
+/// Writes a C declaration of `name` as a `type_name`, e.g. `int grid[2][3]` or `char *name`.
+fn declaration(type_name: &str, name: &str) -> String {
+    match type_name.find(" [") {
+        Some(idx) => format!("{} {}{}", &type_name[..idx], name, &type_name[idx + 1..]),
+        None if type_name.ends_with('*') => format!("{}{}", type_name, name),
+        None => format!("{} {}", type_name, name),
+    }
+}
+
+/// Formats a value of a scalar (non-aggregate) type `ty` the way C would show it, e.g. `65 'A'`
+/// for a char or `(int *) 0x7ffd2a10` for a pointer. Also returns the value as an integer for
+/// the value history; floating point values are truncated.
+fn format_scalar(ty: &Type, bytes: &[u8]) -> Result<(i64, String), String> {
+    if ty.kind == TypeKind::Float {
+        let value = match bytes.len() {
+            4 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            8 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(bytes);
+                f64::from_le_bytes(buf)
+            }
+            _ => return Err(format!("cannot show values of type {}", ty.name)),
+        };
+        return Ok((value as i64, value.to_string()));
+    }
+    if !is_integer_type(ty) {
+        return Err(format!("cannot show values of type {}", ty.name));
+    }
+    let value = integer_value(ty, bytes);
+    let text = match ty.kind {
+        TypeKind::Boolean if value == 0 => "false".to_string(),
+        TypeKind::Boolean => "true".to_string(),
+        TypeKind::Char => format!("{} {}", value, char_literal(value as u8)),
+        TypeKind::Pointer => format!("({}) {:#x}", ty.name, value),
+        TypeKind::Enum => match ty.enumerators.iter().find(|(_, v)| *v == value) {
+            Some((name, _)) => name.clone(),
+            None => value.to_string(),
+        },
+        _ => value.to_string(),
+    };
+    Ok((value, text))
+}
+
+/// Formats a value with a `/FMT` letter regardless of its type: scalars of up to 8 bytes as
+/// integers of their size (so a float shows its bits), and arrays and structs element by
+/// element. Also returns the value as an integer for the value history.
+fn format_as(ty: &Type, bytes: &[u8], format: char) -> Result<(i64, String), String> {
+    match ty.kind {
+        TypeKind::Struct => {
+            let mut fields = Vec::new();
+            for member in &ty.members {
+                let end = member.offset + member.ty.size;
+                let member_bytes =
+                    bytes.get(member.offset..end).ok_or("struct member out of range")?;
+                let (_, text) = format_as(&member.ty, member_bytes, format)?;
+                fields.push(format!("{} = {}", member.name, text));
+            }
+            Ok((0, format!("{{{}}}", fields.join(", "))))
+        }
+        TypeKind::Array => {
+            let element = ty.element.as_ref().unwrap();
+            let elements = bytes
+                .chunks(element.size.max(1))
+                .map(|chunk| format_as(element, chunk, format).map(|(_, text)| text))
+                .collect::<Result<Vec<String>, String>>()?;
+            Ok((0, format!("{{{}}}", elements.join(", "))))
+        }
+        _ if bytes.is_empty() || bytes.len() > 8 => {
+            Err(format!("cannot show values of type {} with /{}", ty.name, format))
+        }
+        _ => {
+            let mut buf = [0u8; 8];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            let raw = u64::from_le_bytes(buf);
+            let value = if is_integer_type(ty) { integer_value(ty, bytes) } else { raw as i64 };
+            Ok((value, format::format_integer(raw, bytes.len(), format, false)))
+        }
+    }
+}
+
+/// Formats bytes read from `addr` like `hexdump -C`: the address, 16 bytes in hex split into
+/// two groups of 8, and the printable ones as ASCII, followed by the address just past the end.
+fn hexdump(addr: usize, bytes: &[u8]) -> Vec<String> {
+    let mut lines: Vec<String> = bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = (0..16)
+                .map(|j| chunk.get(j).map_or("  ".to_string(), |byte| format!("{:02x}", byte)))
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| if (0x20..=0x7e).contains(&byte) { byte as char } else { '.' })
+                .collect();
+            let (low, high) = (hex[..8].join(" "), hex[8..].join(" "));
+            format!("{:08x}  {}  {}  |{}|", addr + i * 16, low, high, ascii)
+        })
+        .collect();
+    lines.push(format!("{:08x}", addr + bytes.len()));
+    lines
+}
+
+impl Context for Debugger {
+    fn variable(&self, name: &str) -> Result<i64, String> {
+        let (ty, bytes) = self.variable_value(name)?;
+        if !is_integer_type(&ty) {
+            return Err(format!("\"{}\" is not an integer ({})", name, ty.name));
+        }
+        Ok(integer_value(&ty, &bytes))
+    }
+
+    fn dollar(&self, name: &str) -> Result<i64, String> {
+        // $N refers to the N-th printed value
+        if let Ok(number) = name.parse::<usize>() {
+            return number
+                .checked_sub(1)
+                .and_then(|i| self.value_history.get(i))
+                .copied()
+                .ok_or(format!("history has not yet reached ${}", number));
+        }
+        // Registers shadow convenience variables of the same name
+        if !registers::is_register(name) {
+            return self
+                .convenience_vars
+                .get(name)
+                .copied()
+                .ok_or(format!("no register or convenience variable ${}", name));
+        }
+        let regs = self.frame_regs()?;
+        registers::get_register(&regs, name)
+            .map(|val| val as i64)
+            .ok_or(format!("unknown register ${}", name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_thread_local() {
+        let mut debugger = Debugger::new("samples/tls", false);
+        // Stop on the printf, after counter has been incremented
+        let addr = debugger.debug_data.get_addr_for_line(None, 7).unwrap();
+        debugger.execute(DebuggerCommand::Breakpoint(format!("{:#x}", addr), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("counter"), Ok(7));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_breakpoint_set_before_run() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let inferior = debugger
+            .inferior
+            .as_mut()
+            .expect("Could not start samples/hello. Have you run make?");
+        assert!(inferior.is_alive());
+        let main = debugger.debug_data.get_addr_for_function(None, "main").unwrap();
+        assert_eq!(debugger.dollar("pc"), Ok(main as i64));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_environment_not_inherited() {
+        assert!(std::env::var("HOME").is_ok());
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::Set(vec![
+            "environment-inherit".to_string(),
+            "off".to_string(),
+        ]));
+        debugger.execute(DebuggerCommand::Set(vec![
+            "env".to_string(),
+            "GREETING=hi".to_string(),
+        ]));
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let pid = debugger.inferior.as_ref().unwrap().pid();
+        let environ = std::fs::read(format!("/proc/{}/environ", pid)).unwrap();
+        let vars: Vec<&[u8]> = environ.split(|&b| b == 0).filter(|v| !v.is_empty()).collect();
+        assert_eq!(vars, vec![&b"GREETING=hi"[..]]);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_follow_exec_new() {
+        let mut debugger = Debugger::new("samples/exec", false);
+        let wrapper_main = debugger.debug_data.get_addr_for_function(None, "main").unwrap();
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(vec!["samples/hello".to_string()], false));
+        assert_eq!(debugger.dollar("pc"), Ok(wrapper_main as i64));
+        // The breakpoint is re-resolved against samples/hello once it is exec'd
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        let hello_main = DwarfData::from_file("samples/hello")
+            .unwrap()
+            .get_addr_for_function(None, "main")
+            .unwrap();
+        assert_ne!(hello_main, wrapper_main);
+        assert_eq!(debugger.dollar("pc"), Ok(hello_main as i64));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_pending_breakpoint_resolved_after_exec() {
+        let mut debugger = Debugger::new("samples/exec", false);
+        // hello.c is not part of samples/exec, only of the program it execs
+        debugger.execute(DebuggerCommand::Breakpoint("hello.c:4".to_string(), None));
+        assert!(debugger.breakpoints.get(1).unwrap().pending);
+        debugger.execute(DebuggerCommand::Run(vec!["samples/hello".to_string()], false));
+        let hello_line = DwarfData::from_file("samples/hello")
+            .unwrap()
+            .get_addr_for_line(Some("hello.c"), 4)
+            .unwrap();
+        assert!(!debugger.breakpoints.get(1).unwrap().pending);
+        assert_eq!(debugger.dollar("pc"), Ok(hello_line as i64));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_reset_breakpoint_keeps_original_byte() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        let main = debugger.debug_data.get_addr_for_function(None, "main").unwrap();
+        let orig_byte = debugger.elf.as_ref().unwrap().read(main, 1).unwrap()[0];
+        assert_ne!(orig_byte, 0xcc);
+
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        debugger.execute(DebuggerCommand::StepInstruction);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        // A second breakpoint at the same address must not record the trap as the original
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        let inferior = debugger.inferior.as_ref().unwrap();
+        assert_eq!(inferior.read_memory(main, 1).unwrap(), vec![0xcc]);
+        assert_eq!(inferior.replaced_values.get(&main), Some(&orig_byte));
+
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![2, 3]));
+        let inferior = debugger.inferior.as_mut().unwrap();
+        assert_eq!(inferior.read_memory(main, 1).unwrap(), vec![orig_byte]);
+        inferior.terminate().unwrap();
+    }
+
+    #[test]
+    fn test_patch_instruction() {
+        let mut debugger = Debugger::new("samples/exit", false);
+        let main = debugger.debug_data.get_addr_for_function(None, "main").unwrap();
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // main passes 0 to exit via %edx; without that move %edi still holds argc (1)
+        let code = debugger.elf.as_ref().unwrap().read(main, 32).unwrap();
+        let insn = disasm::disassemble(&code, main)
+            .unwrap()
+            .into_iter()
+            .find(|insn| insn.mnemonic == "movl" && insn.operands == "%edx, %edi")
+            .expect("movl %edx, %edi not found in main");
+        let nops = vec![0x90; insn.size];
+        debugger.execute(DebuggerCommand::Patch(format!("{:#x}", insn.address), nops));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 1),
+            _ => panic!("samples/exit should have exited"),
+        }
+    }
+
+    #[test]
+    fn test_foreign_int3() {
+        let mut debugger = Debugger::new("samples/int3", false);
+        let status = debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(status, None);
+        let inferior = debugger.inferior.as_ref().unwrap();
+        let rip = debugger.dollar("pc").unwrap() as usize;
+        // The trap isn't ours, so %rip must not have been rewound onto the int3
+        assert_eq!(inferior.read_memory(rip - 1, 1).unwrap(), vec![0xcc]);
+        assert!(inferior.hit_foreign_int3(rip));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/int3 should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_frame_selection() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        // Past func3's prologue, so that its frame is set up
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("a"), Ok(100));
+        // func3 <- func2 <- func1 <- main
+        debugger.execute(DebuggerCommand::Up(2));
+        assert_eq!(debugger.selected_frame, 2);
+        assert_eq!(debugger.variable("a"), Ok(42));
+        debugger.execute(DebuggerCommand::Frame(None));
+        assert_eq!(debugger.selected_frame, 2);
+        debugger.execute(DebuggerCommand::Up(5));
+        assert_eq!(debugger.selected_frame, 2);
+        debugger.execute(DebuggerCommand::Down(1));
+        assert_eq!(debugger.variable("sum"), Ok(47));
+        // Registers are unwound too: func2's stack pointer is where it was before the call
+        let frames = debugger.frames().unwrap();
+        assert_eq!(debugger.dollar("pc"), Ok(frames[1].rip as i64));
+        assert_eq!(debugger.dollar("sp"), Ok(frames[0].rbp as i64 + 16));
+        debugger.execute(DebuggerCommand::Frame(Some(0)));
+        assert_eq!(debugger.variable("a"), Ok(100));
+        // A frame selected past main is gone once backtraces stop at main again
+        let past_main = |value: &str| {
+            DebuggerCommand::Set(vec![
+                "backtrace".to_string(),
+                "past-main".to_string(),
+                value.to_string(),
+            ])
+        };
+        debugger.execute(past_main("on"));
+        let outermost = debugger.frames().unwrap().len() - 1;
+        debugger.execute(DebuggerCommand::Frame(Some(outermost)));
+        assert_eq!(debugger.selected_frame, outermost);
+        debugger.execute(past_main("off"));
+        assert!(debugger.frames().unwrap().len() <= outermost);
+        assert!(debugger.frame_regs().is_err());
+        assert!(debugger.frame_info_lines().is_err());
+        assert!(debugger.list_source(None).is_err());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_backtrace_arguments() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let frames = debugger.frames().unwrap();
+        let described: Vec<String> = frames
+            .iter()
+            .map(|frame| {
+                let description = debugger.describe_frame(frame);
+                description[description.find(" in ").unwrap() + 4..].to_string()
+            })
+            .collect();
+        assert!(described[0].starts_with("func3(a=100) at "));
+        assert!(described[1].starts_with("func2(a=42, b=5) at "));
+        assert!(described[1].ends_with("function_calls.c:13"));
+        assert!(described[2].starts_with("func1(a=42) at "));
+        assert!(described[3].starts_with("main() at "));
+        assert_eq!(debugger.frame_locals(&frames[1]), vec!["        sum = 47".to_string()]);
+        assert_eq!(debugger.frame_locals(&frames[0]), vec!["No locals.".to_string()]);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_info_frame() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Up(1));
+        let frames = debugger.frames().unwrap();
+        let lines = debugger.frame_info_lines().unwrap();
+        assert_eq!(lines[0], format!("Stack level 1, frame at {:#x}:", frames[1].rbp + 16));
+        assert!(lines[1].starts_with(&format!(" rip = {:#x} in func2 (", frames[1].rip)));
+        assert!(lines[1].ends_with(&format!("saved rip = {:#x}", frames[2].rip)));
+        assert_eq!(
+            lines[2],
+            format!(
+                " called by frame at {:#x}, caller of frame at {:#x}",
+                frames[2].rbp + 16,
+                frames[0].rbp + 16
+            )
+        );
+        assert_eq!(lines[3], format!(" Arglist at {:#x}, args: a=42, b=5", frames[1].rbp));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_global_variable() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        // Read from the executable's .data before the program runs
+        assert_eq!(debugger.variable("global"), Ok(5));
+        assert!(debugger.variable("sum").is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("func3".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // func3 has no variable of that name in scope, but globals are always visible
+        assert_eq!(debugger.variable("global"), Ok(5));
+        // Once the program has exited, globals come from the executable again
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert!(!debugger.inferior.as_ref().unwrap().is_alive());
+        assert_eq!(debugger.variable("global"), Ok(5));
+        assert_eq!(debugger.evaluate("global", None), Ok((5, "5".to_string())));
+    }
+
+    #[test]
+    fn test_finish_json() {
+        let mut debugger = Debugger::new("samples/factorial", true);
+        // Stop at the very first instruction, before the frame is set up
+        debugger.execute(DebuggerCommand::Breakpoint("factorial".to_string(), Some(1)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let report = debugger.finish().unwrap().unwrap();
+        assert!(report.starts_with(concat!(
+            r#"{"event":"finished","function":"factorial","return_value":120,"#,
+            r#""return_type":"int","location":""#
+        )));
+        assert!(report.ends_with(r#"factorial.c:11"}"#));
+        assert_eq!(debugger.dollar("1"), Ok(120));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_finish_selected_frame() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        // func3 called from func2, called from func1
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), Some(1)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Up(1));
+        debugger.finish().unwrap();
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        assert_eq!(debugger.debug_data.get_function_from_addr(pc), Some("func1".to_string()));
+        // Just after the call to func2, which ends line 18
+        assert_eq!(debugger.current_line().map(|line| line.number), Some(19));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_continue_count() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        debugger.execute(DebuggerCommand::Continue(4, false));
+        assert_eq!(debugger.variable("i"), Ok(4));
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 5);
+        // The program exits before the breakpoint is reached another 20 times
+        debugger.execute(DebuggerCommand::Continue(20, false));
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 10);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Checkpoint);
+        debugger.execute(DebuggerCommand::Continue(3, false));
+        assert_eq!(debugger.variable("i"), Ok(3));
+        let pid = debugger.inferior.as_ref().unwrap().pid();
+
+        debugger.execute(DebuggerCommand::Restart(1));
+        assert_ne!(debugger.inferior.as_ref().unwrap().pid(), pid);
+        assert_eq!(debugger.variable("i"), Ok(0));
+        assert_eq!(debugger.variable("total"), Ok(0));
+        // The breakpoints are back in place, and the checkpoint can be gone back to again
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        debugger.execute(DebuggerCommand::ReverseContinue);
+        assert_eq!(debugger.variable("i"), Ok(0));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_list() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        let lines = debugger.list_source(Some("func2")).unwrap();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "4\t");
+        assert_eq!(lines[5], "9\tvoid func2(int a, int b) {");
+        // Near the end of the file there are fewer lines after the centre
+        let lines = debugger.list_source(None).unwrap();
+        assert_eq!(lines.first().unwrap(), "18\t    func2(a, global);");
+        assert_eq!(lines.last().unwrap(), "25\t}");
+
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.list_source(None).unwrap()[0], "1\t#include <stdio.h>");
+        debugger.execute(DebuggerCommand::Up(1));
+        assert_eq!(debugger.list_source(None).unwrap()[0], "8\t");
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_until() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), Some(1)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Until("8".to_string()));
+        assert_eq!(debugger.current_line().map(|line| line.number), Some(8));
+        assert_eq!(debugger.variable("total"), Ok(45));
+        assert!(debugger.inferior.as_ref().unwrap().replaced_values.is_empty());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), Some(1)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Advance("func3".to_string()));
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        assert_eq!(debugger.debug_data.get_function_from_addr(pc), Some("func3".to_string()));
+        assert!(debugger.breakpoints.is_empty());
+        assert!(debugger.inferior.as_ref().unwrap().replaced_values.is_empty());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_until_stops_when_function_returns() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("func3".to_string(), Some(1)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Line 20 is in func1, and func3 returns to func2 before getting there
+        debugger.execute(DebuggerCommand::Until("20".to_string()));
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        assert_eq!(debugger.debug_data.get_function_from_addr(pc), Some("func2".to_string()));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_breakpoint_in_other_function() {
+        let debugger = Debugger::new("samples/function_calls", false);
+        let func3 = debugger.debug_data.get_addr_for_function(None, "func3").unwrap();
+        assert_eq!(debugger.check_breakpoint_function("func3", func3), None);
+        assert_eq!(debugger.check_breakpoint_function("function_calls.c:func3", func3), None);
+        // Lines and addresses aren't checked
+        assert_eq!(debugger.check_breakpoint_function("6", func3), None);
+        assert_eq!(
+            debugger.check_breakpoint_function("func2", func3),
+            Some(format!("Note: breakpoint at {:#x} is in func3, not func2", func3))
+        );
+    }
+
+    #[test]
+    fn test_multiple_inferiors() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        let first_pid = debugger.inferior.as_ref().unwrap().pid();
+
+        debugger.execute(DebuggerCommand::AddInferior);
+        debugger.execute(DebuggerCommand::Inferior(2));
+        assert!(debugger.inferior.is_none());
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        assert_ne!(debugger.inferior.as_ref().unwrap().pid(), first_pid);
+
+        // Breakpoints are shared, so deleting one takes it out of both processes
+        debugger.execute(DebuggerCommand::Inferior(1));
+        assert_eq!(debugger.inferior.as_ref().unwrap().pid(), first_pid);
+        assert_eq!(debugger.variable("i"), Ok(1));
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("inferior 1 should have run to completion"),
+        }
+        debugger.execute(DebuggerCommand::Inferior(2));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("inferior 2 should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_disable_single_breakpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        let main = debugger.debug_data.get_addr_for_function(None, "main").unwrap();
+        let orig_byte = debugger.elf.as_ref().unwrap().read(main, 1).unwrap()[0];
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Disable(vec![1]));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Breakpoint 1 is skipped, and its byte was never replaced
+        assert_eq!(debugger.variable("i"), Ok(0));
+        let inferior = debugger.inferior.as_ref().unwrap();
+        assert_eq!(inferior.read_memory(main, 1).unwrap(), vec![orig_byte]);
+
+        debugger.execute(DebuggerCommand::Disable(vec![2]));
+        debugger.execute(DebuggerCommand::Enable(vec![2]));
+        assert_eq!(debugger.breakpoints.get(2).unwrap().location, "6");
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_ignore_count() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Ignore(1, 3));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(3));
+        let bp = debugger.breakpoints.get(1).unwrap();
+        assert_eq!((bp.hit_count, bp.ignore_count), (4, 0));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(4));
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 5);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_temporary_breakpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        let cmd = DebuggerCommand::from_tokens(&vec!["tbreak", "6"]).unwrap();
+        debugger.execute(cmd);
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        assert!(debugger.breakpoints.is_empty());
+        assert!(debugger.inferior.as_ref().unwrap().replaced_values.is_empty());
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/loop should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_hardware_breakpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::HardwareBreakpoint("6".to_string()));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        let inferior = debugger.inferior.as_ref().unwrap();
+        // Nothing is patched into the code
+        let addr = debugger.breakpoints.get(1).unwrap().addr;
+        assert!(inferior.replaced_values.is_empty());
+        assert_ne!(inferior.read_memory(addr, 1).unwrap()[0], 0xcc);
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        debugger.execute(DebuggerCommand::Disable(vec![1]));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/loop should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_enable_hardware_breakpoint_without_free_register() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        for line in &["3", "4", "5", "6"] {
+            debugger.execute(DebuggerCommand::HardwareBreakpoint(line.to_string()));
+        }
+        debugger.execute(DebuggerCommand::Disable(vec![1]));
+        debugger.execute(DebuggerCommand::HardwareBreakpoint("8".to_string()));
+        debugger.execute(DebuggerCommand::Enable(vec![1]));
+        assert!(!debugger.breakpoints.get(1).unwrap().enabled);
+        debugger.execute(DebuggerCommand::Disable(vec![5]));
+        debugger.execute(DebuggerCommand::Enable(vec![1]));
+        assert!(debugger.breakpoints.get(1).unwrap().enabled);
+    }
+
+    #[test]
+    fn test_breakpoint_commands() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        let commands = vec!["print i".to_string(), "continue".to_string()];
+        debugger.breakpoints.get_mut(1).unwrap().commands = commands;
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        while let Some(cmd) = debugger.next_pending_command() {
+            debugger.execute(cmd);
+        }
+        assert_eq!(debugger.value_history, (0..10).collect::<Vec<i64>>());
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 10);
+        assert!(!debugger.inferior.as_ref().unwrap().is_alive());
+    }
+
+    #[test]
+    fn test_regex_breakpoint() {
+        let mut debugger = Debugger::new("samples/multi_file", false);
+        debugger.execute(DebuggerCommand::RegexBreakpoint("^(add|mul)$".to_string()));
+        let mut locations: Vec<&str> =
+            debugger.breakpoints.iter().map(|bp| bp.location.as_str()).collect();
+        locations.sort();
+        assert_eq!(locations, vec!["add", "mul"]);
+        debugger.execute(DebuggerCommand::RegexBreakpoint("(".to_string()));
+        assert_eq!(debugger.breakpoints.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_save_and_source_breakpoints() {
+        let path = std::env::temp_dir().join(format!("deet-test-{}.bp", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Disable(vec![1]));
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Condition(2, Some("i == 3".to_string())));
+        debugger.breakpoints.get_mut(2).unwrap().commands = vec!["print i".to_string()];
+        debugger.execute(DebuggerCommand::SaveBreakpoints(path.clone()));
+
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Source(path.clone()));
+        while let Some(cmd) = debugger.next_pending_command() {
+            debugger.execute(cmd);
+        }
+        std::fs::remove_file(&path).unwrap();
+        let main = debugger.breakpoints.get(1).unwrap();
+        assert_eq!((main.location.as_str(), main.enabled), ("main", false));
+        let line = debugger.breakpoints.get(2).unwrap();
+        assert_eq!(line.location, "6");
+        assert_eq!(line.condition.as_deref(), Some("i == 3"));
+        assert_eq!(line.commands, vec!["print i"]);
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(3));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_save_thread_breakpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), Some(1)));
+        debugger.breakpoints.get_mut(1).unwrap().thread = Some(Pid::from_raw(4321));
+        assert_eq!(debugger.breakpoints_script(), "break 6 -count 1 thread 4321\n");
+    }
+
+    #[test]
+    fn test_catch_syscall() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::CatchSyscall(vec!["write".to_string()]));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Stopped at the entry of write(1, "Hello world!\n", 13)
+        assert_eq!(debugger.dollar("rdi"), Ok(1));
+        assert_eq!(debugger.dollar("rdx"), Ok(13));
+        match debugger.continue_inferior() {
+            Ok(Status::SyscallExit(1, 13)) => {}
+            _ => panic!("write should have returned 13"),
+        }
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/hello should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_stop_on_syscall() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let setting = |value: &str| vec!["stop-on-syscall".to_string(), value.to_string()];
+        debugger.execute(DebuggerCommand::Set(setting("on")));
+        // printf may make other system calls before the write, each stopping on entry and exit
+        let mut entered_write = false;
+        for _ in 0..10 {
+            match debugger.continue_inferior() {
+                Ok(Status::SyscallEntry(1, args)) => {
+                    assert_eq!(args[2], 13);
+                    entered_write = true;
+                    break;
+                }
+                Ok(Status::SyscallEntry(..)) | Ok(Status::SyscallExit(..)) => {}
+                _ => panic!("expected a syscall stop"),
+            }
+        }
+        assert!(entered_write);
+        match debugger.continue_inferior() {
+            Ok(Status::SyscallExit(1, 13)) => {}
+            _ => panic!("write should have returned 13"),
+        }
+        debugger.execute(DebuggerCommand::Set(setting("off")));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/hello should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_catch_signal() {
+        // SIGALRM is passed to the program's handler without stopping it
+        let mut debugger = Debugger::new("samples/alarm", false);
+        debugger.execute(DebuggerCommand::Breakpoint("14".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("result"), Ok(1));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+
+        let mut debugger = Debugger::new("samples/alarm", false);
+        debugger.execute(DebuggerCommand::CatchSignal(vec!["SIGALRM".to_string()]));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let inferior = debugger.inferior.as_ref().unwrap();
+        assert_eq!(inferior.pending_signal.get(), Some(Signal::SIGALRM));
+        // The handler still runs once the program continues, so it exits with 0
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/alarm should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_catch_fork() {
+        let mut debugger = Debugger::new("samples/fork", false);
+        // Only the child calls twice(); it must not die on our trap
+        debugger.execute(DebuggerCommand::Breakpoint("twice".to_string(), None));
+        debugger.execute(DebuggerCommand::Catch(Catch::Fork));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/fork should have run to completion"),
+        }
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 0);
+    }
+
+    #[test]
+    fn test_breakpoint_after_vfork() {
+        let mut debugger = Debugger::new("samples/vfork", false);
+        // The vfork child shares the parent's memory while it runs, and our traps must be back
+        // in place once it's gone
+        debugger.execute(DebuggerCommand::Breakpoint("report".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 1);
+        let addr = debugger.breakpoints.get(1).unwrap().addr;
+        assert_eq!(debugger.dollar("pc"), Ok(addr as i64));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/vfork should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_in_thread() {
+        let mut debugger = Debugger::new("samples/threads", false);
+        debugger.execute(DebuggerCommand::Breakpoint("work".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // main is waiting in pthread_join while the worker thread calls work first
+        let inferior = debugger.inferior.as_ref().unwrap();
+        let (pid, worker) = (inferior.pid(), inferior.tid());
+        assert_ne!(worker, pid);
+        assert_eq!(inferior.threads(), vec![pid, worker]);
+        assert_eq!(debugger.variable("calls"), Ok(0));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.inferior.as_ref().unwrap().tid(), pid);
+        assert_eq!(debugger.inferior.as_ref().unwrap().threads(), vec![pid]);
+        assert_eq!(debugger.variable("calls"), Ok(1));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/threads should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_thread_breakpoint() {
+        let mut debugger = Debugger::new("samples/threads", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let pid = debugger.inferior.as_ref().unwrap().pid();
+        let tokens = vec!["break", "work", "thread", "1"];
+        debugger.execute(DebuggerCommand::from_tokens(&tokens).unwrap());
+        assert!(debugger.breakpoints.get(2).is_none());
+        let tid = pid.to_string();
+        let tokens = vec!["break", "work", "thread", &tid];
+        debugger.execute(DebuggerCommand::from_tokens(&tokens).unwrap());
+        assert_eq!(debugger.breakpoints.get(2).unwrap().thread, Some(pid));
+        // The worker thread hits the trap first, and is stepped past it without stopping
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.inferior.as_ref().unwrap().tid(), pid);
+        assert_eq!(debugger.variable("calls"), Ok(1));
+        assert_eq!(debugger.breakpoints.get(2).unwrap().hit_count, 1);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_format_printf() {
+        assert_eq!(format_printf("i = %d, %x%%\n", &[-3, 255]), Ok("i = -3, ff%\n".to_string()));
+        assert_eq!(format_printf("%ld %c", &[7, 65]), Ok("7 A".to_string()));
+        assert!(format_printf("%d %d", &[1]).is_err());
+        assert!(format_printf("%d", &[1, 2]).is_err());
+        assert!(format_printf("%s", &[0]).is_err());
+    }
+
+    #[test]
+    fn test_tracepoint_does_not_stop() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        let tokens = vec!["trace", "6", "\"i", "=", "%d\\n\",", "i"];
+        debugger.execute(DebuggerCommand::from_tokens(&tokens).unwrap());
+        let bp = debugger.breakpoints.get(1).unwrap();
+        assert_eq!(bp.printf, Some(("i = %d\n".to_string(), vec!["i".to_string()])));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert!(!debugger.inferior.as_ref().unwrap().is_alive());
+        assert_eq!(debugger.breakpoints.get(1).unwrap().hit_count, 10);
+    }
+
+    #[test]
+    fn test_breakpoint_outside_code_refused() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        let elf = debugger.elf.as_ref().expect("Could not read samples/hello. Have you run make?");
+        let data = elf.segments.iter().find(|s| s.p_type == 1 && s.flags & 2 != 0).unwrap().vaddr;
+        debugger.execute(DebuggerCommand::Breakpoint(format!("{:#x}", data), None));
+        assert!(debugger.breakpoints.is_empty());
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Once running, the process's memory map is checked instead
+        debugger.execute(DebuggerCommand::Breakpoint(format!("{:#x}", data), None));
+        assert_eq!(debugger.breakpoints.len(), 1);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("5".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Watch("total".to_string(), WatchKind::Write));
+        debugger.execute(debugger.parse_command("delete breakpoint 1").unwrap());
+        // Each iteration's total += i is a write, whether or not it changes total
+        for _ in 0..3 {
+            debugger.execute(DebuggerCommand::Continue(1, false));
+        }
+        assert_eq!(debugger.variable("i"), Ok(2));
+        assert_eq!(debugger.last_watchpoint, Some((1, Some(1))));
+        assert_eq!(debugger.watchpoints[0].value, Some(3));
+        debugger.execute(debugger.parse_command("delete watchpoint 1").unwrap());
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/loop should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_read_watchpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("5".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Watch("total".to_string(), WatchKind::Read));
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        // total += 0 reads total without changing it, so it looks like a plain read
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        // The other iterations change total and are skipped, up to the read for the printf
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.last_watchpoint, Some((1, Some(45))));
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        assert_eq!(debugger.debug_data.get_line_from_addr(pc).map(|line| line.number), Some(8));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_access_watchpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("5".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Watch("total".to_string(), WatchKind::Access));
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        // total += 0, then total += 1; unlike rwatch, the second one stops too
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.last_watchpoint, Some((1, Some(0))));
+        assert_eq!(debugger.watchpoints[0].value, Some(0));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.last_watchpoint, Some((1, Some(0))));
+        assert_eq!(debugger.watchpoints[0].value, Some(1));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_software_watchpoint() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Breakpoint("5".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Take every debug register with breakpoints that won't be hit during the loop
+        for line in &["3", "8", "9", "10"] {
+            debugger.execute(DebuggerCommand::HardwareBreakpoint(line.to_string()));
+        }
+        debugger.execute(DebuggerCommand::Watch("total".to_string(), WatchKind::Write));
+        assert!(debugger.watchpoints[0].software);
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Breakpoints, vec![1]));
+        // Single-stepping only notices the writes that change total, so total += 0 is missed
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        assert_eq!(debugger.last_watchpoint, Some((1, Some(0))));
+        assert_eq!(debugger.watchpoints[0].value, Some(1));
+        // The hardware breakpoints still stop the program
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Watchpoints, vec![1]));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.last_breakpoint.map(|(number, _)| number), Some(3));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_local_watchpoint_deleted_on_return() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        // After func2 has set sum
+        debugger.execute(DebuggerCommand::Breakpoint("12".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Watch("sum".to_string(), WatchKind::Write));
+        assert!(debugger.watchpoints[0].scope.is_some());
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        // Stopped back in func1, with the watchpoint and its trap gone
+        assert!(debugger.watchpoints.is_empty());
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        assert_eq!(debugger.debug_data.get_function_from_addr(pc), Some("func1".to_string()));
+        assert!(!debugger.inferior.as_ref().unwrap().replaced_values.contains_key(&pc));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/function_calls should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Into func1 and func2 past their prologues, and over the printf calls
+        let expected = [
+            ("main", 24),
+            ("func1", 17),
+            ("func1", 18),
+            ("func2", 10),
+            ("func2", 11),
+            ("func2", 12),
+        ];
+        for &(func, line) in expected.iter() {
+            debugger.execute(DebuggerCommand::Step);
+            let pc = debugger.dollar("pc").unwrap() as usize;
+            assert_eq!(debugger.debug_data.get_function_from_addr(pc), Some(func.to_string()));
+            assert_eq!(debugger.current_line().map(|line| line.number), Some(line));
+        }
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_next() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("17".to_string(), None));
+        // Stepping over the call to func2 still stops at its breakpoint, and next carries on
+        // from there back out into func1
+        debugger.execute(DebuggerCommand::Breakpoint("12".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        for &line in [18, 12, 13, 14, 19, 20].iter() {
+            debugger.execute(DebuggerCommand::Next);
+            assert_eq!(debugger.current_line().map(|line| line.number), Some(line));
+        }
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_breakpoint_with_stop_count() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        // Line 6 is the loop body, total += i
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), Some(3)));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.variable("i"), Ok(0));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(1));
+        assert_eq!(debugger.breakpoints.get(1).unwrap().delete_after, Some(1));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.variable("i"), Ok(2));
+        assert!(debugger.breakpoints.is_empty());
+        // The stop is still reported as breakpoint 1's
+        assert_eq!(debugger.last_breakpoint.map(|(number, _)| number), Some(1));
+        match debugger.continue_inferior() {
+            Ok(Status::Exited(code)) => assert_eq!(code, 0),
+            _ => panic!("samples/loop should have run to completion"),
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_relative_to_current_line() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        assert!(debugger.parse_addr("+2").is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("4".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Breakpoint("+2".to_string(), None));
+        let line_6 = debugger.debug_data.get_addr_for_line(None, 6).unwrap();
+        assert_eq!(debugger.breakpoints.get(2).unwrap().addr, line_6);
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.dollar("pc"), Ok(line_6 as i64));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_breakpoint_at_function_offset() {
+        let debugger = Debugger::new("samples/hello", false);
+        let main = debugger.parse_addr("main").unwrap();
+        assert_eq!(debugger.parse_addr("main+0x1c"), Ok(main + 0x1c));
+        assert_eq!(debugger.parse_addr("main+4"), Ok(main + 4));
+        assert_eq!(debugger.parse_addr("main-8"), Ok(main - 8));
+        assert!(debugger.parse_addr("main+0xzz").is_err());
+        assert!(debugger.parse_addr("nosuchfunc+4").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Display(None, Some("total".to_string())));
+        debugger.execute(DebuggerCommand::Display(None, Some("i * 2".to_string())));
+        debugger.execute(DebuggerCommand::Display(None, Some("1 +".to_string())));
+        let exprs = |debugger: &Debugger| {
+            debugger.displays.iter().map(|display| display.expr.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(exprs(&debugger), vec!["total", "i * 2"]);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        debugger.execute(DebuggerCommand::Continue(1, false));
+        assert_eq!(debugger.evaluate("total", None), Ok((1, "1".to_string())));
+        assert_eq!(debugger.evaluate("i * 2", None), Ok((4, "4".to_string())));
+        let pc = debugger.dollar("pc").unwrap();
+        assert_eq!(debugger.evaluate("$pc", None), Ok((pc, format!("{:#x}", pc))));
+        // Displays don't add to the value history
+        assert!(debugger.value_history.is_empty());
+        debugger.execute(DebuggerCommand::Delete(DeleteTarget::Displays, vec![1]));
+        assert_eq!(exprs(&debugger), vec!["i * 2"]);
+        assert_eq!(debugger.displays[0].number, 2);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_disassemble_next_line() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        debugger.execute(DebuggerCommand::Set(vec![
+            "disassemble-next-line".to_string(),
+            "on".to_string(),
+        ]));
+        assert!(debugger.disassemble_next_line);
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // The instruction shown at a breakpoint is the program's, not the int3 patched over it
+        let pc = debugger.dollar("pc").unwrap() as usize;
+        let inferior = debugger.inferior.as_ref().unwrap();
+        assert_eq!(inferior.read_memory(pc, 1).unwrap(), vec![0xcc]);
+        let insns = disasm::disassemble(&debugger.read_code(pc, 16).unwrap(), pc).unwrap();
+        assert_eq!(insns[0].mnemonic, "movl");
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_value_history() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::Print(None, "6 * 7".to_string()));
+        debugger.execute(DebuggerCommand::Print(None, "$1 + 4".to_string()));
+        assert_eq!(debugger.value_history, vec![42, 46]);
+        assert_eq!(debugger.dollar("2"), Ok(46));
+        assert!(debugger.dollar("3").is_err());
+        assert!(debugger.dollar("0").is_err());
+    }
+
+    #[test]
+    fn test_print_typed_variables() {
+        let mut debugger = Debugger::new("samples/types", false);
+        debugger.execute(DebuggerCommand::Breakpoint("14".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let show = |name: &str| {
+            let (ty, bytes) = debugger.variable_value(name)?;
+            debugger.format_value(&ty, &bytes).map(|(_, text)| text)
+        };
+        assert_eq!(show("count"), Ok("-3".to_string()));
+        assert_eq!(show("letter"), Ok("120 'x'".to_string()));
+        assert_eq!(show("byte"), Ok("200 '\\310'".to_string()));
+        assert_eq!(show("done"), Ok("true".to_string()));
+        assert_eq!(show("ratio"), Ok("2.5".to_string()));
+        assert_eq!(show("half"), Ok("0.5".to_string()));
+        let name = show("name").unwrap();
+        assert!(name.starts_with("(const char *) 0x") && name.ends_with(" \"deet\""));
+        assert!(show("counter").unwrap().starts_with("(int *) 0x"));
+        assert_eq!(show("grade"), Ok("66 'B'".to_string()));
+        // Floating point variables can be printed, but not used in expressions
+        assert!(debugger.variable("ratio").is_err());
+        let name = debugger.variable("name").unwrap() as usize;
+        let inferior = debugger.inferior.as_ref().unwrap();
+        assert_eq!(inferior.read_string(name, MAX_STRING_LEN), Ok(b"deet".to_vec()));
+        assert_eq!(inferior.read_string(name + 1, 2), Ok(b"ee".to_vec()));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_print_formats() {
+        let mut debugger = Debugger::new("samples/types", false);
+        debugger.execute(DebuggerCommand::Breakpoint("14".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let show = |expr: &str, format: char| debugger.evaluate(expr, Some(format));
+        assert_eq!(show("count", 'x'), Ok((-3, "0xfffffffd".to_string())));
+        assert_eq!(show("count", 'u'), Ok((-3, "4294967293".to_string())));
+        assert_eq!(show("letter", 'd'), Ok((120, "120".to_string())));
+        assert_eq!(show("byte", 't'), Ok((200, "11001000".to_string())));
+        assert_eq!(show("done", 'c'), Ok((1, "1 '\\001'".to_string())));
+        // Floats show their bits
+        assert_eq!(show("ratio", 'x'), Ok((0x40200000, "0x40200000".to_string())));
+        assert_eq!(show("6 * 7", 't'), Ok((42, "101010".to_string())));
+        assert_eq!(show("$pc", 'd').unwrap().1, debugger.dollar("pc").unwrap().to_string());
+        debugger.execute(DebuggerCommand::Print(Some('x'), "count".to_string()));
+        assert_eq!(debugger.value_history, vec![-3]);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+
+        let mut debugger = Debugger::new("samples/arrays", false);
+        debugger.execute(DebuggerCommand::Breakpoint("22".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(
+            debugger.evaluate("primes", Some('x')).unwrap().1,
+            "{0x2, 0x3, 0x5, 0x7, 0xb}"
+        );
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_print_structs_and_enums() {
+        let mut debugger = Debugger::new("samples/structs", false);
+        debugger.execute(DebuggerCommand::Breakpoint("32".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let show = |name: &str| {
+            let (ty, bytes) = debugger.variable_value(name)?;
+            debugger.format_value(&ty, &bytes).map(|(_, text)| text)
+        };
+        assert_eq!(show("origin"), Ok("{x = 3, y = 7}".to_string()));
+        // A typedef'd struct, with a null pointer to its own type
+        assert_eq!(
+            show("tail"),
+            Ok("{value = 2, next = (struct node *) 0x0, where = {x = 0, y = 1}, color = BLUE}"
+                .to_string())
+        );
+        assert!(show("head").unwrap().ends_with("where = {x = 5, y = -5}, color = GREEN}"));
+        // A struct that points to itself through a typedef declared before it
+        assert_eq!(show("last"), Ok("{id = 2, next = (link_t *) 0x0}".to_string()));
+        assert!(show("first").unwrap().starts_with("{id = 1, next = (link_t *) 0x"));
+        assert_eq!(show("shade"), Ok("RED".to_string()));
+        assert_eq!(show("odd"), Ok("3".to_string()));
+        // Enums are still integers in expressions, but structs aren't
+        assert_eq!(debugger.variable("odd"), Ok(3));
+        assert!(debugger.variable("origin").is_err());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_info_functions() {
+        let debugger = Debugger::new("samples/function_calls", false);
+        let lines = debugger.function_lines(None).unwrap();
+        assert_eq!(lines[0], "All defined functions:");
+        assert!(lines[2].starts_with("File ") && lines[2].ends_with("function_calls.c:"));
+        let signatures: Vec<&str> =
+            lines[3..].iter().map(|line| line.split('\t').nth(1).unwrap()).collect();
+        assert_eq!(
+            signatures,
+            vec![
+                "void func1(int a);",
+                "void func2(int a, int b);",
+                "void func3(int a);",
+                "int main();",
+            ]
+        );
+        let lines = debugger.function_lines(Some("^func[12]$")).unwrap();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(debugger.function_lines(Some("nothing")).unwrap().len(), 1);
+        assert!(debugger.function_lines(Some("(")).is_err());
+    }
+
+    #[test]
+    fn test_info_variables() {
+        let debugger = Debugger::new("samples/arrays", false);
+        let lines = debugger.variable_lines(None).unwrap();
+        assert_eq!(lines[0], "All defined variables:");
+        assert!(lines[2].ends_with("arrays.c:"));
+        let declarations: Vec<&str> =
+            lines[3..].iter().map(|line| line.split('\t').nth(1).unwrap()).collect();
+        assert_eq!(declarations, vec!["const char *motto;", "int primes[5];"]);
+        let primes = match debugger.debug_data.lookup_global("primes").unwrap().location {
+            Location::Address(addr) => addr,
+            _ => panic!("primes is a global"),
+        };
+        let lines = debugger.variable_lines(Some("^pr")).unwrap();
+        assert_eq!(lines[3], format!("{:#x}  8:\tint primes[5];", primes));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn test_info_proc_mappings() {
+        let mut debugger = Debugger::new("samples/loop", false);
+        assert!(debugger.mapping_lines().is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let lines = debugger.mapping_lines().unwrap();
+        let pid = debugger.inferior.as_ref().unwrap().pid();
+        assert_eq!(lines[0], format!("process {}", pid));
+        assert!(lines.iter().any(|line| line.contains(" r-xp ") && line.ends_with("samples/loop")));
+        assert!(lines.iter().any(|line| line.ends_with(" [stack]")));
+        // The code we stopped in is in an executable mapping
+        let rip = ptrace::getregs(pid).unwrap().rip as usize;
+        assert!(debugger.inferior.as_ref().unwrap().is_executable(rip).unwrap());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_info_symbol() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        let func2 = debugger.debug_data.get_addr_for_function(None, "func2").unwrap();
+        assert_eq!(
+            debugger.symbol_info(&format!("{:#x}", func2 + 4)),
+            Ok("func2 + 4 in section .text of samples/function_calls".to_string())
+        );
+        assert_eq!(
+            debugger.symbol_info("func1"),
+            Ok("func1 in section .text of samples/function_calls".to_string())
+        );
+        assert_eq!(debugger.symbol_info("0"), Err("No symbol matches 0.".to_string()));
+        debugger.execute(DebuggerCommand::Breakpoint("6".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // Once running, the object file comes from the process's mappings
+        let info = debugger.symbol_info("$pc").unwrap();
+        assert!(info.starts_with("func3 + "));
+        assert!(info.contains(" in section .text of /"));
+        assert!(info.ends_with("samples/function_calls"));
+        let global = match debugger.debug_data.lookup_global("global").unwrap().location {
+            Location::Address(addr) => addr,
+            _ => panic!("global should have a fixed address"),
+        };
+        let info = debugger.symbol_info(&format!("{:#x}", global)).unwrap();
+        assert!(info.starts_with("global in section .data of "));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_ptype() {
+        let mut debugger = Debugger::new("samples/structs", false);
+        assert!(debugger.expression_type("head").is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("32".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let ptype = |expr: &str| type_lines(&debugger.expression_type(expr).unwrap());
+        assert_eq!(
+            ptype("head"),
+            vec![
+                "type = struct node {",
+                "    int value;           /* offset 0, size 4 */",
+                "    struct node *next;   /* offset 8, size 8 */",
+                "    struct point where;  /* offset 16, size 8 */",
+                "    enum color color;    /* offset 24, size 4 */",
+                "} /* size 32 */",
+            ]
+        );
+        assert_eq!(ptype("shade"), vec!["type = enum color {RED, GREEN, BLUE = 4}"]);
+        assert_eq!(ptype("shade + 1"), vec!["type = long"]);
+        assert_eq!(debugger.expression_type("tail").unwrap().name, "node_t");
+        assert!(debugger.expression_type("nothing + 1").is_err());
+        assert_eq!(declaration("int [2][3]", "grid"), "int grid[2][3]");
+        assert_eq!(declaration("const char *", "name"), "const char *name");
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let mut debugger = Debugger::new("samples/arrays", false);
+        let primes = match debugger.debug_data.lookup_global("primes").unwrap().location {
+            Location::Address(addr) => addr,
+            _ => panic!("primes should have a fixed address"),
+        };
+        let expected = vec![
+            format!(
+                "{:08x}  02 00 00 00 03 00 00 00  05 00 00 00 07 00 00 00  |................|",
+                primes
+            ),
+            format!("{:08x}  0b 00 00 00{:39}|....|", primes + 16, ""),
+            format!("{:08x}", primes + 20),
+        ];
+        let addr = format!("{:#x}", primes);
+        // Before the program runs, memory comes from the executable
+        assert_eq!(debugger.hexdump_lines(&addr, "20"), Ok(expected.clone()));
+        assert!(debugger.hexdump_lines(&addr, "0").is_err());
+        assert!(debugger.hexdump_lines(&addr, "100000000").is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("main".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(debugger.hexdump_lines(&addr, "4 * 5"), Ok(expected));
+        assert_eq!(
+            hexdump(0x10, b"deet\n"),
+            vec![
+                "00000010  64 65 65 74 0a                                    |deet.|",
+                "00000015"
+            ]
+        );
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_print_arrays() {
+        let mut debugger = Debugger::new("samples/arrays", false);
+        let show = |debugger: &Debugger, name: &str| {
+            let (ty, bytes) = debugger.variable_value(name)?;
+            debugger.format_value(&ty, &bytes).map(|(_, text)| text)
+        };
+        assert_eq!(show(&debugger, "primes"), Ok("{2, 3, 5, 7, 11}".to_string()));
+        // Read from the executable, like the string it points to
+        assert!(show(&debugger, "motto").unwrap().ends_with(" \"print me\""));
+        debugger.execute(DebuggerCommand::Breakpoint("22".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        assert_eq!(show(&debugger, "name"), Ok("\"deet\"".to_string()));
+        assert_eq!(show(&debugger, "grid"), Ok("{{1, 2, 3}, {4, 5, 6}}".to_string()));
+        assert_eq!(show(&debugger, "path"), Ok("{{x = 0, y = 0}, {x = 3, y = 4}}".to_string()));
+        // An array of a typedef'd array type
+        assert_eq!(show(&debugger, "corners"), Ok("{{0, 0, 0}, {1, 2, 3}}".to_string()));
+        debugger.execute(DebuggerCommand::Set(vec![
+            "print".to_string(),
+            "elements".to_string(),
+            "3".to_string(),
+        ]));
+        assert_eq!(show(&debugger, "squares"), Ok("{0, 1, 4, ...}".to_string()));
+        assert!(show(&debugger, "motto").unwrap().ends_with(" \"pri\"..."));
+
+        // A Rust slice is a pointer and a length
+        let (int, _) = debugger.variable_value("primes").unwrap();
+        let int = *int.element.unwrap();
+        let mut pointer = Type::new("*const i32".to_string(), 8, TypeKind::Pointer);
+        pointer.element = Some(Box::new(int));
+        let usize_type = Type::new("usize".to_string(), 8, TypeKind::Unsigned);
+        let mut slice = Type::new("&[i32]".to_string(), 16, TypeKind::Struct);
+        slice.members = vec![
+            Member { name: "data_ptr".to_string(), offset: 0, ty: pointer },
+            Member { name: "length".to_string(), offset: 8, ty: usize_type },
+        ];
+        let primes = debugger.debug_data.lookup_global("primes").unwrap();
+        let addr = match primes.location {
+            Location::Address(addr) => addr,
+            _ => panic!("primes is a global"),
+        };
+        let mut bytes = (addr + 4).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&4usize.to_le_bytes());
+        assert_eq!(debugger.format_value(&slice, &bytes), Ok((0, "{3, 5, 7, ...}".to_string())));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_set_register() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        debugger.execute(DebuggerCommand::Breakpoint("func3".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        debugger.execute(DebuggerCommand::Set(vec!["$rax = 0x10".to_string()]));
+        assert_eq!(debugger.dollar("rax"), Ok(0x10));
+        debugger.execute(DebuggerCommand::Set(vec!["$rdi=$rax * 2 + 1".to_string()]));
+        assert_eq!(debugger.dollar("rdi"), Ok(33));
+        // Registers aren't turned into convenience variables
+        assert!(!debugger.convenience_vars.contains_key("rax"));
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_info_registers() {
+        let mut debugger = Debugger::new("samples/function_calls", false);
+        assert!(debugger.register_lines(&[]).is_err());
+        debugger.execute(DebuggerCommand::Breakpoint("func3".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        let lines = debugger.register_lines(&[]).unwrap();
+        assert_eq!(lines.len(), registers::REGISTER_NAMES.len());
+        assert!(lines.iter().any(|line| line.starts_with("rip") && line.ends_with("<func3>")));
+        let lines = debugger.register_lines(&["pc".to_string(), "rdi".to_string()]).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("pc             0x"));
+        assert!(debugger.register_lines(&["xyz".to_string()]).is_err());
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_vector_registers() {
+        let mut debugger = Debugger::new("samples/floats", false);
+        debugger.execute(DebuggerCommand::Breakpoint("4".to_string(), None));
+        debugger.execute(DebuggerCommand::Run(Vec::new(), false));
+        // scale(2.5, 0.5f) gets its arguments in %xmm0 and %xmm1
+        let lines = debugger.register_lines(&["xmm0".to_string(), "xmm1".to_string()]).unwrap();
+        assert!(lines[0].starts_with("xmm0           {v4_float = {0, 2.0625, 0, 0}, "));
+        assert!(lines[0].contains(" v2_double = {2.5, 0}, "));
+        assert!(lines[1].starts_with("xmm1           {v4_float = {0.5, 0, 0, 0}, "));
+        assert!(lines[1].ends_with(" uint128 = 0x3f000000}"));
+        let lines = debugger.register_lines(&["vector".to_string()]).unwrap();
+        assert_eq!(lines.len(), 17);
+        assert!(lines[16].starts_with("mxcsr          0x"));
+        let lines = debugger.register_lines(&["all".to_string()]).unwrap();
+        assert_eq!(lines.len(), registers::REGISTER_NAMES.len() + 17);
+        debugger.inferior.as_mut().unwrap().terminate().unwrap();
+    }
+
+    #[test]
+    fn test_convenience_variables() {
+        let mut debugger = Debugger::new("samples/hello", false);
+        debugger.execute(DebuggerCommand::Set(vec![
+            "$base".to_string(),
+            "=".to_string(),
+            "0x400000".to_string(),
+        ]));
+        debugger.execute(DebuggerCommand::Set(vec!["$off=$base+0x136".to_string()]));
+        assert_eq!(debugger.dollar("base"), Ok(0x400000));
+        assert_eq!(debugger.dollar("off"), Ok(0x400136));
+        // Registers can't be shadowed
+        debugger.execute(DebuggerCommand::Set(vec!["$rip=1".to_string()]));
+        assert!(debugger.dollar("rip").is_err());
+        assert!(debugger.dollar("nosuch").is_err());
     }
 }
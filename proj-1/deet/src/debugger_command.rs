@@ -1,29 +1,562 @@
+use crate::breakpoint::{Catch, WatchKind};
+use crate::format;
+
+/// The `/<count><format><size>` suffix of the examine command, e.g. `x/4xg`.
+#[derive(Debug, PartialEq)]
+pub struct ExamineFormat {
+    pub count: usize,
+    /// One of x (hex), d (signed decimal), u (unsigned decimal), t (binary), c (characters),
+    /// s (strings) or i (instructions)
+    pub format: char,
+    /// Unit size in bytes: b = 1, h = 2, w = 4, g = 8. Ignored for strings and instructions.
+    pub size: usize,
+}
+
+impl ExamineFormat {
+    /// Parses the part after the slash; missing pieces default to a single hex word, or a
+    /// single byte for characters.
+    pub fn parse(spec: &str) -> Option<ExamineFormat> {
+        let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits.parse::<usize>().ok()?
+        };
+        let mut format = 'x';
+        let mut size = None;
+        for c in spec[digits.len()..].chars() {
+            match c {
+                's' | 'i' => format = c,
+                _ if format::INTEGER_FORMATS.contains(&c) => format = c,
+                'b' => size = Some(1),
+                'h' => size = Some(2),
+                'w' => size = Some(4),
+                'g' => size = Some(8),
+                _ => return None,
+            }
+        }
+        Some(ExamineFormat {
+            count,
+            format,
+            size: size.unwrap_or(if format == 'c' || format == 's' { 1 } else { 4 }),
+        })
+    }
+}
+
+/// The kind of numbered item a `delete` command removes.
+pub enum DeleteTarget {
+    Breakpoints,
+    Catchpoints,
+    Watchpoints,
+    Checkpoints,
+    Displays,
+}
+
 pub enum DebuggerCommand {
-    Quit,
-    Run(Vec<String>),
-    Continue,
-    BackTrace,
-    Breakpoint(String),
+    /// Optional exit code for DEET itself (`quit 2`)
+    Quit(Option<i32>),
+    /// Arguments for the target, and whether to run it in the background (`run ... &`)
+    Run(Vec<String>, bool),
+    /// Number of breakpoint stops to continue through, counting the last one (`continue 3`),
+    /// and whether to continue in the background (`continue &`)
+    Continue(usize, bool),
+    Wait,
+    /// Run to the next source line, stepping into calls (`step`)
+    Step,
+    /// Run to the next source line in this function or its callers, stepping over calls
+    Next,
+    /// Execute a single machine instruction (`stepi`)
+    StepInstruction,
+    /// Execute a single machine instruction, stepping over calls (`nexti`)
+    NextInstruction,
+    /// Run until the current function returns, and show its return value
+    Finish,
+    /// Run to a location in the current function, or until the function returns
+    Until(String),
+    /// Run to a location anywhere, or until the current function returns
+    Advance(String),
+    /// Whether to also show each frame's local variables (`backtrace full`)
+    BackTrace(bool),
+    /// Select the numbered stack frame, or just show the selected one
+    Frame(Option<usize>),
+    /// Select the frame N levels further out (towards `main`)
+    Up(usize),
+    /// Select the frame N levels further in
+    Down(usize),
+    /// Location, and the number of stops after which the breakpoint deletes itself
+    Breakpoint(String, Option<usize>),
+    /// Like `Breakpoint`, for a breakpoint that only stops the thread with the given id
+    ThreadBreakpoint(String, Option<usize>, i32),
+    /// Location of a breakpoint kept in a debug register instead of patched into the code
+    HardwareBreakpoint(String),
+    /// Regular expression; a breakpoint is set on every function whose name matches it
+    RegexBreakpoint(String),
+    /// Stop at entry to and return from the named (or numbered) syscalls; all of them if none
+    CatchSyscall(Vec<String>),
+    /// Always stop when the program receives the named signals, or any signal if none are named
+    CatchSignal(Vec<String>),
+    /// Stop when the program forks, vforks or execs
+    Catch(Catch),
+    /// Variable or address to stop the program on writes to (`watch`), reads of (`rwatch`) or
+    /// either (`awatch`), using a debug register
+    Watch(String, WatchKind),
+    /// Location, format and argument expressions of a tracepoint, which prints a message and
+    /// carries on (`trace 6 "i = %d\n" i`)
+    Trace(String, String, Vec<String>),
+    /// Delete the numbered items of some kind; no numbers means all of them
+    Delete(DeleteTarget, Vec<usize>),
+    /// Disable the numbered breakpoints, or all of them if none are given
+    Disable(Vec<usize>),
+    /// Enable the numbered breakpoints, or all of them if none are given
+    Enable(Vec<usize>),
+    /// Breakpoint number, and the new condition (None to make it unconditional)
+    Condition(usize, Option<String>),
+    /// Breakpoint number, and how many of its next hits to continue through
+    Ignore(usize, usize),
+    /// Read the commands to run when the numbered (or most recent) breakpoint stops the program
+    Commands(Option<usize>),
+    /// Evaluate and show an expression, optionally in a format such as x (`print/x`)
+    Print(Option<char>, String),
+    /// Show an expression (in a format, if given) every time the program stops; with none, show
+    /// them all now
+    Display(Option<char>, Option<String>),
+    /// List the expressions shown at every stop
+    InfoDisplay,
+    /// Show the type of a variable or expression (`whatis`)
+    WhatIs(String),
+    /// Show the type of a variable or expression, with the layout of structs and the values of
+    /// enums (`ptype`)
+    PType(String),
+    /// Dump the raw DWARF line table, optionally for a single file
+    MaintenanceInfoLineTable(Option<String>),
+    /// Compare each installed breakpoint's byte in memory with the original we recorded
+    MaintenanceInfoBreakpointBytes,
+    /// List the source files the program was built from
+    InfoSources,
+    /// List the functions, or those whose names match a regular expression
+    InfoFunctions(Option<String>),
+    /// List the global and static variables, or those whose names match a regular expression
+    InfoVariables(Option<String>),
+    /// Add a new inferior, without a process, for running the target a second time
+    AddInferior,
+    /// Switch to the numbered inferior
+    Inferior(usize),
+    /// List the inferiors
+    InfoInferiors,
+    /// List the threads of the program and where each is
+    InfoThreads,
+    /// Describe the selected stack frame: where it is and where its registers were saved
+    InfoFrame,
+    /// List the memory mappings of the program
+    InfoProcMappings,
+    /// Name the function or variable containing an address, and the section and file it's in
+    InfoSymbol(String),
+    /// Fork the stopped program to save its state for going back to later
+    Checkpoint,
+    /// Go back to the numbered checkpoint
+    Restart(usize),
+    /// Go back to the most recent checkpoint
+    ReverseContinue,
+    InfoCheckpoints,
+    /// Show the range of addresses belonging to a source line, or to the current one
+    InfoLine(Option<String>),
+    /// List all breakpoints, or show the details of a single one
+    InfoBreakpoints(Option<usize>),
+    InfoWatchpoints,
+    /// Show the named registers, or all of them if none are named
+    InfoRegisters(Vec<String>),
+    /// Show the source around a location, or around the selected frame's line
+    List(Option<String>),
+    /// Disassemble a function (by name or any address inside it), or the current one
+    Disassemble(Option<String>),
+    /// Examine memory at the address an expression evaluates to (`x/4xg $sp`)
+    Examine(ExamineFormat, String),
+    /// Dump a range of memory as hex and ASCII, 16 bytes a line (`hexdump ADDRESS LENGTH`)
+    Hexdump(String, String),
+    /// Overwrite the inferior's memory at a location with raw bytes (`patch 0x401136 90 90`)
+    Patch(String, Vec<u8>),
+    /// Save a copy of the memory between two addresses under a name (`snapshot NAME START END`)
+    Snapshot(String, String, String),
+    /// Show the bytes that changed since the named snapshot was taken
+    Diff(String),
+    /// Write the breakpoints to a file as commands that recreate them when sourced
+    SaveBreakpoints(String),
+    /// Execute the debugger commands in a file
+    Source(String),
+    /// Show a debugger setting or property, e.g. `show architecture`
+    Show(Vec<String>),
+    /// Change a debugger setting, e.g. `set pagination off`
+    Set(Vec<String>),
 }
 
 impl DebuggerCommand {
     pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+        // A trailing "&" asks for the target to be resumed in the background
+        let background = tokens.len() > 1 && *tokens.last().unwrap() == "&";
+        let tokens = if background {
+            &tokens[..tokens.len() - 1]
+        } else {
+            &tokens[..]
+        };
         match tokens[0] {
-            "q" | "quit" => Some(DebuggerCommand::Quit),
+            "q" | "quit" => match tokens.get(1) {
+                Some(code) => Some(DebuggerCommand::Quit(Some(code.parse::<i32>().ok()?))),
+                None => Some(DebuggerCommand::Quit(None)),
+            },
             "r" | "run" => {
                 let args = tokens[1..].to_vec();
                 Some(DebuggerCommand::Run(
                     args.iter().map(|s| s.to_string()).collect(),
+                    background,
+                ))
+            },
+            "c" | "cont" | "continue" => match tokens.get(1) {
+                Some(count) => match count.parse::<usize>().ok()? {
+                    0 => None,
+                    count => Some(DebuggerCommand::Continue(count, background)),
+                },
+                None => Some(DebuggerCommand::Continue(1, background)),
+            },
+            "wait" => Some(DebuggerCommand::Wait),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "ni" | "nexti" => Some(DebuggerCommand::NextInstruction),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "u" | "until" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Until(tokens[1].to_string()))
+            }
+            "advance" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Advance(tokens[1].to_string()))
+            }
+            "checkpoint" => Some(DebuggerCommand::Checkpoint),
+            "restart" => Some(DebuggerCommand::Restart(tokens.get(1)?.parse::<usize>().ok()?)),
+            "rc" | "reverse-continue" => Some(DebuggerCommand::ReverseContinue),
+            "add-inferior" => Some(DebuggerCommand::AddInferior),
+            "inferior" => Some(DebuggerCommand::Inferior(tokens.get(1)?.parse::<usize>().ok()?)),
+            "bt" | "back" | "backtrace" => match tokens.get(1) {
+                Some(&"full") => Some(DebuggerCommand::BackTrace(true)),
+                Some(_) => None,
+                None => Some(DebuggerCommand::BackTrace(false)),
+            },
+            "f" | "frame" => Some(DebuggerCommand::Frame(
+                tokens.get(1).map(|n| n.parse::<usize>()).transpose().ok()?,
+            )),
+            "up" => Some(DebuggerCommand::Up(
+                tokens.get(1).map_or(Ok(1), |n| n.parse::<usize>()).ok()?,
+            )),
+            "down" => Some(DebuggerCommand::Down(
+                tokens.get(1).map_or(Ok(1), |n| n.parse::<usize>()).ok()?,
+            )),
+            "b" | "bp" | "break" | "breakpoint" => {
+                let addr = String::from(*tokens.get(1)?);
+                let mut count = None;
+                let mut thread = None;
+                for option in tokens[2..].chunks(2) {
+                    match *option {
+                        ["-count", n] => count = Some(n.parse::<usize>().ok().filter(|n| *n > 0)?),
+                        ["thread", tid] => thread = Some(tid.parse::<i32>().ok()?),
+                        _ => return None,
+                    }
+                }
+                Some(match thread {
+                    Some(tid) => DebuggerCommand::ThreadBreakpoint(addr, count, tid),
+                    None => DebuggerCommand::Breakpoint(addr, count),
+                })
+            }
+            // A temporary breakpoint is one that deletes itself after its first stop
+            "tb" | "tbreak" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Breakpoint(tokens[1].to_string(), Some(1)))
+            }
+            "hb" | "hbreak" if tokens.len() == 2 => {
+                Some(DebuggerCommand::HardwareBreakpoint(tokens[1].to_string()))
+            }
+            "watch" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Watch(tokens[1].to_string(), WatchKind::Write))
+            }
+            "rwatch" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Watch(tokens[1].to_string(), WatchKind::Read))
+            }
+            "awatch" if tokens.len() == 2 => {
+                Some(DebuggerCommand::Watch(tokens[1].to_string(), WatchKind::Access))
+            }
+            "rbreak" if tokens.len() == 2 => {
+                Some(DebuggerCommand::RegexBreakpoint(tokens[1].to_string()))
+            }
+            "trace" | "dprintf" => {
+                let location = tokens.get(1)?.to_string();
+                let spec = tokens.get(2..)?.join(" ");
+                let (format, rest) = parse_quoted(&spec)?;
+                // Arguments are separated by commas, or by spaces if there are no commas
+                let args: Vec<String> = if rest.contains(',') {
+                    rest.split(',')
+                        .map(|arg| arg.trim())
+                        .filter(|arg| !arg.is_empty())
+                        .map(|arg| arg.to_string())
+                        .collect()
+                } else {
+                    rest.split_whitespace().map(|arg| arg.to_string()).collect()
+                };
+                Some(DebuggerCommand::Trace(location, format, args))
+            }
+            "d" | "delete" => {
+                let (target, args) = match tokens.get(1) {
+                    Some(&"breakpoint") | Some(&"breakpoints") => {
+                        (DeleteTarget::Breakpoints, &tokens[2..])
+                    }
+                    Some(&"catchpoint") | Some(&"catchpoints") => {
+                        (DeleteTarget::Catchpoints, &tokens[2..])
+                    }
+                    Some(&"watchpoint") | Some(&"watchpoints") => {
+                        (DeleteTarget::Watchpoints, &tokens[2..])
+                    }
+                    Some(&"checkpoint") | Some(&"checkpoints") => {
+                        (DeleteTarget::Checkpoints, &tokens[2..])
+                    }
+                    Some(&"display") => (DeleteTarget::Displays, &tokens[2..]),
+                    _ => (DeleteTarget::Breakpoints, &tokens[1..]),
+                };
+                let numbers = args
+                    .iter()
+                    .map(|s| s.parse::<usize>().ok())
+                    .collect::<Option<Vec<usize>>>()?;
+                Some(DebuggerCommand::Delete(target, numbers))
+            }
+            "undisplay" => {
+                let numbers = tokens[1..]
+                    .iter()
+                    .map(|s| s.parse::<usize>().ok())
+                    .collect::<Option<Vec<usize>>>()?;
+                Some(DebuggerCommand::Delete(DeleteTarget::Displays, numbers))
+            }
+            "disable" | "enable" => {
+                let numbers = tokens[1..]
+                    .iter()
+                    .map(|s| s.parse::<usize>().ok())
+                    .collect::<Option<Vec<usize>>>()?;
+                if tokens[0] == "disable" {
+                    Some(DebuggerCommand::Disable(numbers))
+                } else {
+                    Some(DebuggerCommand::Enable(numbers))
+                }
+            }
+            "ignore" => match tokens[1..] {
+                [number, count] => Some(DebuggerCommand::Ignore(
+                    number.parse::<usize>().ok()?,
+                    count.parse::<usize>().ok()?,
+                )),
+                _ => None,
+            },
+            "commands" => Some(DebuggerCommand::Commands(
+                tokens.get(1).map(|n| n.parse::<usize>()).transpose().ok()?,
+            )),
+            "catch" => match tokens.get(1) {
+                Some(&"syscall") => Some(DebuggerCommand::CatchSyscall(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                Some(&"signal") => Some(DebuggerCommand::CatchSignal(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                Some(&"fork") => Some(DebuggerCommand::Catch(Catch::Fork)),
+                Some(&"vfork") => Some(DebuggerCommand::Catch(Catch::VFork)),
+                Some(&"exec") => Some(DebuggerCommand::Catch(Catch::Exec)),
+                _ => None,
+            },
+            "condition" => {
+                let number = tokens.get(1)?.parse::<usize>().ok()?;
+                let expr = if tokens.len() > 2 {
+                    Some(tokens[2..].join(" "))
+                } else {
+                    None
+                };
+                Some(DebuggerCommand::Condition(number, expr))
+            }
+            "p" | "print" => {
+                if tokens.len() < 2 {
+                    return None;
+                }
+                Some(DebuggerCommand::Print(None, tokens[1..].join(" ")))
+            }
+            p if p.starts_with("p/") || p.starts_with("print/") => {
+                let format = format::parse_format(&p[p.find('/').unwrap() + 1..])?;
+                Some(DebuggerCommand::Print(
+                    Some(format),
+                    tokens.get(1..).filter(|rest| !rest.is_empty())?.join(" "),
                 ))
+            }
+            "display" => Some(DebuggerCommand::Display(
+                None,
+                if tokens.len() > 1 {
+                    Some(tokens[1..].join(" "))
+                } else {
+                    None
+                },
+            )),
+            d if d.starts_with("display/") => Some(DebuggerCommand::Display(
+                Some(format::parse_format(&d["display/".len()..])?),
+                Some(tokens.get(1..).filter(|rest| !rest.is_empty())?.join(" ")),
+            )),
+            "whatis" if tokens.len() > 1 => Some(DebuggerCommand::WhatIs(tokens[1..].join(" "))),
+            "ptype" if tokens.len() > 1 => Some(DebuggerCommand::PType(tokens[1..].join(" "))),
+            "i" | "info" => match *tokens.get(1)? {
+                "b" | "break" | "breakpoints" => match tokens.get(2) {
+                    Some(number) => Some(DebuggerCommand::InfoBreakpoints(Some(
+                        number.parse::<usize>().ok()?,
+                    ))),
+                    None => Some(DebuggerCommand::InfoBreakpoints(None)),
+                },
+                "watch" | "watchpoints" => Some(DebuggerCommand::InfoWatchpoints),
+                "display" => Some(DebuggerCommand::InfoDisplay),
+                "r" | "reg" | "registers" => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..]
+                        .iter()
+                        .map(|name| name.trim_start_matches('$').to_string())
+                        .collect(),
+                )),
+                "all-registers" => Some(DebuggerCommand::InfoRegisters(vec!["all".to_string()])),
+                "vector" => Some(DebuggerCommand::InfoRegisters(vec!["vector".to_string()])),
+                "sources" => Some(DebuggerCommand::InfoSources),
+                "functions" => {
+                    Some(DebuggerCommand::InfoFunctions(tokens.get(2).map(|s| s.to_string())))
+                }
+                "variables" => {
+                    Some(DebuggerCommand::InfoVariables(tokens.get(2).map(|s| s.to_string())))
+                }
+                "inferiors" => Some(DebuggerCommand::InfoInferiors),
+                "threads" => Some(DebuggerCommand::InfoThreads),
+                "f" | "frame" => Some(DebuggerCommand::InfoFrame),
+                "proc" => match tokens.get(2) {
+                    Some(&"mappings") | Some(&"map") => Some(DebuggerCommand::InfoProcMappings),
+                    _ => None,
+                },
+                "symbol" if tokens.len() > 2 => {
+                    Some(DebuggerCommand::InfoSymbol(tokens[2..].join(" ")))
+                }
+                "checkpoints" => Some(DebuggerCommand::InfoCheckpoints),
+                "line" => Some(DebuggerCommand::InfoLine(tokens.get(2).map(|s| s.to_string()))),
+                _ => None,
             },
-            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
-            "bt" | "back" | "backtrace" => Some(DebuggerCommand::BackTrace),
-            "b" | "bp" | "breakpoint" => {
-                let addr = String::from(tokens[1]);
-                Some(DebuggerCommand::Breakpoint(addr))
+            "l" | "list" => Some(DebuggerCommand::List(tokens.get(1).map(|s| s.to_string()))),
+            "disas" | "disassemble" => {
+                Some(DebuggerCommand::Disassemble(tokens.get(1).map(|s| s.to_string())))
             }
+            "x" => Some(DebuggerCommand::Examine(
+                ExamineFormat::parse("")?,
+                tokens.get(1..).filter(|rest| !rest.is_empty())?.join(" "),
+            )),
+            x if x.starts_with("x/") => Some(DebuggerCommand::Examine(
+                ExamineFormat::parse(&x[2..])?,
+                tokens.get(1..).filter(|rest| !rest.is_empty())?.join(" "),
+            )),
+            "hexdump" => match tokens[1..] {
+                [addr, len] => Some(DebuggerCommand::Hexdump(addr.to_string(), len.to_string())),
+                _ => None,
+            },
+            "patch" => {
+                let location = tokens.get(1)?.to_string();
+                let bytes = tokens[2..]
+                    .iter()
+                    .map(|s| {
+                        let s = if s.starts_with("0x") { &s[2..] } else { s };
+                        u8::from_str_radix(s, 16).ok()
+                    })
+                    .collect::<Option<Vec<u8>>>()?;
+                if bytes.is_empty() {
+                    return None;
+                }
+                Some(DebuggerCommand::Patch(location, bytes))
+            }
+            "snapshot" => match tokens[1..] {
+                [name, start, end] => Some(DebuggerCommand::Snapshot(
+                    name.to_string(),
+                    start.to_string(),
+                    end.to_string(),
+                )),
+                _ => None,
+            },
+            "diff" => Some(DebuggerCommand::Diff(tokens.get(1)?.to_string())),
+            "save" => match tokens[1..] {
+                ["breakpoints", file] => Some(DebuggerCommand::SaveBreakpoints(file.to_string())),
+                _ => None,
+            },
+            "source" if tokens.len() == 2 => Some(DebuggerCommand::Source(tokens[1].to_string())),
+            "show" => Some(DebuggerCommand::Show(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "set" => Some(DebuggerCommand::Set(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "mt" | "maint" | "maintenance" => match (tokens.get(1), tokens.get(2)) {
+                (Some(&"info"), Some(&"line-table")) => Some(
+                    DebuggerCommand::MaintenanceInfoLineTable(tokens.get(3).map(|s| s.to_string())),
+                ),
+                (Some(&"info"), Some(&"breakpoint-bytes"))
+                | (Some(&"print"), Some(&"breakpoint-bytes")) => {
+                    Some(DebuggerCommand::MaintenanceInfoBreakpointBytes)
+                }
+                _ => None,
+            },
             // Default case:
             _ => None,
         }
     }
+
+    /// Returns whether this command may be issued while the target is running in the
+    /// background. Anything that needs to touch the (running) inferior is refused.
+    pub fn allowed_in_background(&self) -> bool {
+        matches!(self, DebuggerCommand::Quit(_) | DebuggerCommand::Wait)
+    }
+}
+
+/// Parses a double-quoted string with C escapes (\n, \t, \\ and \") at the start of `s`.
+/// Returns its contents and the rest of `s`.
+fn parse_quoted(s: &str) -> Option<(String, &str)> {
+    if !s.starts_with('"') {
+        return None;
+    }
+    let mut contents = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((contents, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => contents.push('\n'),
+                't' => contents.push('\t'),
+                c => contents.push(c),
+            },
+            c => contents.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_examine_format() {
+        let fmt = |count, format, size| ExamineFormat {
+            count,
+            format,
+            size,
+        };
+        assert_eq!(ExamineFormat::parse(""), Some(fmt(1, 'x', 4)));
+        assert_eq!(ExamineFormat::parse("5i"), Some(fmt(5, 'i', 4)));
+        assert_eq!(ExamineFormat::parse("4xg"), Some(fmt(4, 'x', 8)));
+        assert_eq!(ExamineFormat::parse("bd"), Some(fmt(1, 'd', 1)));
+        assert_eq!(ExamineFormat::parse("8c"), Some(fmt(8, 'c', 1)));
+        assert_eq!(ExamineFormat::parse("s"), Some(fmt(1, 's', 1)));
+        assert_eq!(ExamineFormat::parse("2tb"), Some(fmt(2, 't', 1)));
+        assert_eq!(ExamineFormat::parse("3z"), None);
+    }
+
+    #[test]
+    fn test_parse_quoted() {
+        let quoted = |contents: &str, rest| Some((contents.to_string(), rest));
+        assert_eq!(parse_quoted(r#""i = %d\n", i"#), quoted("i = %d\n", ", i"));
+        assert_eq!(parse_quoted(r#""say \"hi\"""#), quoted("say \"hi\"", ""));
+        assert_eq!(parse_quoted(r#""unterminated"#), None);
+        assert_eq!(parse_quoted("bare"), None);
+    }
 }
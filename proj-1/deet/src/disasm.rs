@@ -0,0 +1,73 @@
+//! x86-64 disassembly, backed by capstone.
+
+use capstone::arch::x86::X86OperandType;
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+
+pub struct Instruction {
+    pub address: usize,
+    /// Length of the encoded instruction in bytes
+    pub size: usize,
+    pub mnemonic: String,
+    pub operands: String,
+    /// Target of a direct call or jump
+    pub branch_target: Option<usize>,
+    /// Address referenced by a %rip-relative memory operand
+    pub rip_relative_target: Option<usize>,
+}
+
+/// Disassembles `code`, which was read from address `addr`, in AT&T syntax. Trailing bytes
+/// that don't form a whole instruction are ignored.
+pub fn disassemble(code: &[u8], addr: usize) -> Result<Vec<Instruction>, String> {
+    let cs = Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .syntax(arch::x86::ArchSyntax::Att)
+        .detail(true)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let insns = cs
+        .disasm_all(code, addr as u64)
+        .map_err(|err| err.to_string())?;
+    let mut instructions = Vec::new();
+    for insn in insns.iter() {
+        let mnemonic = insn.mnemonic().unwrap_or("").to_string();
+        let next_addr = insn.address() as usize + insn.bytes().len();
+        let mut branch_target = None;
+        let mut rip_relative_target = None;
+        if let Ok(detail) = cs.insn_detail(&insn) {
+            for op in detail.arch_detail().operands() {
+                if let ArchOperand::X86Operand(op) = op {
+                    match op.op_type {
+                        X86OperandType::Imm(imm)
+                            if mnemonic.starts_with("call") || mnemonic.starts_with('j') =>
+                        {
+                            branch_target = Some(imm as usize);
+                        }
+                        X86OperandType::Mem(mem)
+                            if cs.reg_name(mem.base()).as_deref() == Some("rip") =>
+                        {
+                            rip_relative_target = Some((next_addr as i64 + mem.disp()) as usize);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        instructions.push(Instruction {
+            address: insn.address() as usize,
+            size: insn.bytes().len(),
+            mnemonic,
+            operands: insn.op_str().unwrap_or("").to_string(),
+            branch_target,
+            rip_relative_target,
+        });
+    }
+    Ok(instructions)
+}
+
+impl Instruction {
+    pub fn is_call(&self) -> bool {
+        self.mnemonic.starts_with("call")
+    }
+}
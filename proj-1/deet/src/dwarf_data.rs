@@ -2,6 +2,7 @@ use crate::gimli_wrapper;
 use addr2line::Context;
 use object::Object;
 use std::convert::TryInto;
+use std::path::Path;
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -44,18 +45,52 @@ impl DwarfData {
         })
     }
 
-    #[allow(dead_code)]
+    /// Returns whether the executable contained any usable DWARF compilation units. Binaries
+    /// built without -g (or stripped) have none, and every symbolic lookup will fail.
+    pub fn has_debug_info(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// Returns the names of the compilation units matching `file`: an exact match (by name or
+    /// by full path) if there is one, otherwise every unit whose path ends with `file` (so a
+    /// basename such as `utils.c` matches `/full/path/utils.c`).
+    pub fn matching_files(&self, file: &str) -> Vec<&str> {
+        if let Some(f) = self
+            .files
+            .iter()
+            .find(|f| f.name == file || Path::new(&f.comp_dir).join(&f.name) == Path::new(file))
+        {
+            return vec![f.name.as_str()];
+        }
+        let suffix = format!("/{}", file);
+        self.files
+            .iter()
+            .filter(|f| f.name.ends_with(&suffix))
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+
+    /// Returns the compilation unit for `file`, or None if no unit or more than one matches.
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
-        })
+        match self.matching_files(file).as_slice() {
+            [name] => self.files.iter().find(|f| f.name == *name),
+            _ => None,
+        }
+    }
+
+    /// The compilation unit used when no file is given: the one defining `main`, if any.
+    fn get_default_file(&self) -> Option<&File> {
+        self.files
+            .iter()
+            .find(|f| f.functions.iter().any(|func| func.name == "main"))
+            .or_else(|| self.files.get(0))
     }
 
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.get_default_file()?,
         };
         Some(
             target_file
@@ -73,12 +108,17 @@ impl DwarfData {
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(|func| func.name == func_name && func.text_length > 0)?
                     .address,
             ),
             None => {
                 for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
+                    // Skip declarations of functions defined in another unit
+                    if let Some(func) = file
+                        .functions
+                        .iter()
+                        .find(|func| func.name == func_name && func.text_length > 0)
+                    {
                         return Some(func.address);
                     }
                 }
@@ -87,6 +127,24 @@ impl DwarfData {
         }
     }
 
+    /// Returns the addresses at which `func_name` was inlined, in the compilation unit for
+    /// `file` or in any of them.
+    pub fn get_inline_sites(&self, file: Option<&str>, func_name: &str) -> Vec<usize> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_file(filename).into_iter().collect(),
+            None => self.files.iter().collect(),
+        };
+        let mut addrs: Vec<usize> = files
+            .into_iter()
+            .flat_map(|file| file.inline_sites.iter())
+            .filter(|site| site.name == func_name)
+            .map(|site| site.address)
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
@@ -111,6 +169,159 @@ impl DwarfData {
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the raw line-number program rows of each compilation unit, or only those of the
+    /// unit for `file` if one is given.
+    pub fn line_tables(&self, file: Option<&str>) -> Vec<(&str, &[LineRow])> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_file(filename).into_iter().collect(),
+            None => self.files.iter().collect(),
+        };
+        files
+            .into_iter()
+            .map(|f| (f.name.as_str(), f.line_rows.as_slice()))
+            .collect()
+    }
+
+    /// Returns the line containing `addr`, with the address at which that line's code starts,
+    /// and the address just past its end. Adjacent rows for the same line are merged.
+    pub fn get_line_range(&self, addr: usize) -> Option<(Line, usize)> {
+        for file in &self.files {
+            let rows = &file.line_rows;
+            let same_line = |a: &LineRow, b: &LineRow| a.line == b.line && a.file == b.file;
+            for i in 0..rows.len().saturating_sub(1) {
+                if rows[i].end_sequence || addr < rows[i].address || addr >= rows[i + 1].address {
+                    continue;
+                }
+                let mut start = i;
+                while start > 0
+                    && !rows[start - 1].end_sequence
+                    && same_line(&rows[start - 1], &rows[i])
+                {
+                    start -= 1;
+                }
+                let mut end = i + 1;
+                while end + 1 < rows.len()
+                    && !rows[end].end_sequence
+                    && same_line(&rows[end], &rows[i])
+                {
+                    end += 1;
+                }
+                let line = Line {
+                    file: rows[i].file.clone(),
+                    number: rows[i].line,
+                    address: rows[start].address,
+                };
+                return Some((line, rows[end].address));
+            }
+        }
+        None
+    }
+
+    /// Returns whether `addr` is where a row of the line-number program starts, i.e. at the
+    /// beginning of (part of) a line's code rather than in the middle of it.
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.files
+            .iter()
+            .flat_map(|file| file.line_rows.iter())
+            .any(|row| row.address == addr && !row.end_sequence)
+    }
+
+    /// Returns the full paths of all source files that contributed code, deduplicated and
+    /// sorted. Relative names are resolved against their unit's compilation directory.
+    pub fn source_files(&self) -> Vec<String> {
+        let mut files: Vec<String> = self
+            .files
+            .iter()
+            .flat_map(|f| {
+                f.line_rows
+                    .iter()
+                    .map(move |row| Path::new(&f.comp_dir).join(&row.file))
+            })
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Returns every function with code in the program, skipping declarations.
+    pub fn functions(&self) -> Vec<&Function> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .filter(|func| func.text_length > 0)
+            .collect()
+    }
+
+    /// Returns the full path of each compilation unit's source file, with the unit's functions
+    /// that have code, sorted by name.
+    pub fn functions_by_file(&self) -> Vec<(String, Vec<&Function>)> {
+        self.files
+            .iter()
+            .map(|file| {
+                let mut functions: Vec<&Function> =
+                    file.functions.iter().filter(|func| func.text_length > 0).collect();
+                functions.sort_by(|a, b| a.name.cmp(&b.name));
+                (unit_path(file), functions)
+            })
+            .collect()
+    }
+
+    /// Returns the full path of each compilation unit's source file, with the unit's global and
+    /// static variables sorted by name.
+    pub fn globals_by_file(&self) -> Vec<(String, Vec<&Variable>)> {
+        self.files
+            .iter()
+            .map(|file| {
+                let mut globals: Vec<&Variable> = file.global_variables.iter().collect();
+                globals.sort_by(|a, b| a.name.cmp(&b.name));
+                (unit_path(file), globals)
+            })
+            .collect()
+    }
+
+    /// Returns the function whose code contains `addr`.
+    pub fn get_function_containing(&self, addr: usize) -> Option<&Function> {
+        self.files.iter().flat_map(|file| file.functions.iter()).find(|func| {
+            func.address <= addr && addr < func.address + func.text_length
+        })
+    }
+
+    /// Returns the global variable occupying `addr`, along with the offset of `addr` into it.
+    pub fn get_global_containing(&self, addr: usize) -> Option<(&Variable, usize)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find_map(|var| match var.location {
+                Location::Address(start)
+                    if start <= addr && addr < start + std::cmp::max(var.entity_type.size, 1) =>
+                {
+                    Some((var, addr - start))
+                }
+                _ => None,
+            })
+    }
+
+    /// Looks up a variable visible from `addr`: a local or parameter of the enclosing function,
+    /// or failing that a global.
+    pub fn lookup_variable(&self, addr: usize, name: &str) -> Option<&Variable> {
+        if let Some(func) = self.get_function_containing(addr) {
+            // Search backwards so that variables in inner scopes shadow outer ones
+            if let Some(var) = func.variables.iter().rev().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        self.lookup_global(name)
+    }
+
+    /// Returns the global (or file-level static) variable called `name`.
+    pub fn lookup_global(&self, name: &str) -> Option<&Variable> {
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -148,25 +359,69 @@ impl DwarfData {
     }
 }
 
+/// Returns the full path of a compilation unit's source file.
+fn unit_path(file: &File) -> String {
+    Path::new(&file.comp_dir).join(&file.name).to_string_lossy().into_owned()
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    /// Fields of a struct or union, in declaration order
+    pub members: Vec<Member>,
+    /// Named values of an enum
+    pub enumerators: Vec<(String, i64)>,
+    /// The type of an array's elements, or of what a pointer points to (None for `void *`)
+    pub element: Option<Box<Type>>,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
+    pub fn new(name: String, size: usize, kind: TypeKind) -> Self {
         Type {
             name: name,
             size: size,
+            kind: kind,
+            members: Vec::new(),
+            enumerators: Vec::new(),
+            element: None,
         }
     }
 }
 
+/// A field of a struct or union, at `offset` bytes from the start of it.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize,
+    pub ty: Type,
+}
+
+/// How the bytes of a value are to be read, from the DWARF base type encoding. Typedefs and
+/// qualified types take the kind of the type they're built on.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TypeKind {
+    #[default]
+    Signed,
+    Unsigned,
+    /// `char`, `signed char` or `unsigned char`
+    Char,
+    Boolean,
+    Float,
+    Pointer,
+    /// A struct or union
+    Struct,
+    Enum,
+    Array,
+}
+
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Offset of a thread-local variable within the executable's TLS block
+    TlsOffset(usize),
 }
 
 impl fmt::Display for Location {
@@ -174,6 +429,7 @@ impl fmt::Display for Location {
         match *self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::TlsOffset(offset) => write!(f, "TlsOffset({:#x})", offset),
         }
     }
 }
@@ -191,6 +447,8 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    /// Whether this is one of its function's formal parameters
+    pub is_parameter: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -200,14 +458,31 @@ pub struct Function {
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    /// None for functions returning void, or types we don't parse
+    pub return_type: Option<Type>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    /// The directory the compiler ran in, which relative file names are relative to
+    pub comp_dir: String,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
+    /// Every row of the compilation unit's DWARF line-number program, unfiltered
+    pub line_rows: Vec<LineRow>,
+    /// Places where a function's body was inlined into its caller
+    pub inline_sites: Vec<InlineSite>,
+}
+
+/// A copy of an inline function's body within another function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineSite {
+    /// Name of the inlined function
+    pub name: String,
+    /// Where the copy's code starts
+    pub address: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -217,10 +492,125 @@ pub struct Line {
     pub address: usize,
 }
 
+/// A raw row of a DWARF line-number program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineRow {
+    pub address: usize,
+    pub file: String,
+    pub line: usize,
+    pub is_stmt: bool,
+    pub end_sequence: bool,
+}
+
 impl fmt::Display for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:{}", self.file, self.number)
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
 
+    #[test]
+    fn test_has_debug_info() {
+        let debug_data = DwarfData::from_file("samples/hello")
+            .expect("Could not load samples/hello. Have you run make?");
+        assert!(debug_data.has_debug_info());
+    }
+
+    #[test]
+    fn test_stripped_binary_has_no_debug_info() {
+        let debug_data = DwarfData::from_file("samples/hello_nodebug")
+            .expect("Could not load samples/hello_nodebug. Have you run make?");
+        assert!(!debug_data.has_debug_info());
+        assert_eq!(debug_data.get_addr_for_function(None, "main"), None);
+        assert_eq!(debug_data.get_addr_for_line(None, 3), None);
+    }
+
+    #[test]
+    fn test_unsupported_dwarf_version() {
+        match DwarfData::from_file("samples/hello_dwarf5") {
+            Err(Error::DwarfFormatError(gimli_wrapper::Error::UnsupportedVersion(5))) => {}
+            Err(Error::ErrorOpeningFile) => {
+                panic!("Could not open samples/hello_dwarf5. Have you run make?")
+            }
+            _ => panic!("expected samples/hello_dwarf5 to be rejected as DWARF version 5"),
+        }
+    }
+
+    #[test]
+    fn test_line_range() {
+        let debug_data = DwarfData::from_file("samples/loop")
+            .expect("Could not load samples/loop. Have you run make?");
+        let start = debug_data.get_addr_for_line(None, 6).unwrap();
+        let (line, end) = debug_data.get_line_range(start).unwrap();
+        assert_eq!((line.number, line.address), (6, start));
+        assert!(end > start);
+        // any address inside the line gives the same range
+        assert_eq!(debug_data.get_line_range(end - 1), Some((line, end)));
+        assert_eq!(debug_data.get_line_range(0), None);
+    }
+
+    #[test]
+    fn test_inline_sites() {
+        let debug_data = DwarfData::from_file("samples/inline")
+            .expect("Could not load samples/inline. Have you run make?");
+        // square is always inlined, so there's no out-of-line copy to break at
+        assert_eq!(debug_data.get_addr_for_function(None, "square"), None);
+        let sites = debug_data.get_inline_sites(None, "square");
+        assert_eq!(sites.len(), 2);
+        for site in sites {
+            assert_eq!(debug_data.get_function_from_addr(site), Some("square".to_string()));
+        }
+        assert!(debug_data.get_inline_sites(None, "main").is_empty());
+    }
+
+    fn load_multi_file() -> DwarfData {
+        DwarfData::from_file("samples/multi_file")
+            .expect("Could not load samples/multi_file. Have you run make?")
+    }
+
+    #[test]
+    fn test_line_in_secondary_file() {
+        let debug_data = load_multi_file();
+        // main.c is not the first compilation unit, but it is the default one
+        let main_line = debug_data.get_addr_for_line(None, 7).unwrap();
+        assert_eq!(debug_data.get_addr_for_line(Some("main.c"), 7), Some(main_line));
+        let add_line = debug_data.get_addr_for_line(Some("multi/utils.c"), 4).unwrap();
+        assert_ne!(add_line, main_line);
+        assert_eq!(
+            debug_data.get_function_from_addr(add_line).as_deref(),
+            Some("add")
+        );
+        let mul_line = debug_data.get_addr_for_line(Some("lib/utils.c"), 4).unwrap();
+        assert_eq!(
+            debug_data.get_function_from_addr(mul_line).as_deref(),
+            Some("mul")
+        );
+    }
+
+    #[test]
+    fn test_source_files() {
+        let debug_data = load_multi_file();
+        let files = debug_data.source_files();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|f| f.starts_with('/')));
+        assert!(files[0].ends_with("samples/multi/lib/utils.c"));
+        assert!(files[1].ends_with("samples/multi/main.c"));
+        assert!(files[2].ends_with("samples/multi/utils.c"));
+    }
+
+    #[test]
+    fn test_ambiguous_basename() {
+        let debug_data = load_multi_file();
+        let mut candidates = debug_data.matching_files("utils.c");
+        candidates.sort();
+        assert_eq!(
+            candidates,
+            vec!["samples/multi/lib/utils.c", "samples/multi/utils.c"]
+        );
+        assert_eq!(debug_data.get_addr_for_line(Some("utils.c"), 4), None);
+        assert!(debug_data.matching_files("nosuch.c").is_empty());
+    }
+}
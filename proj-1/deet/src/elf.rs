@@ -0,0 +1,228 @@
+//! Just enough ELF parsing to get at the program headers (e.g. the thread-local storage
+//! segment) and section names, which the DWARF libraries don't expose.
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_TLS: u32 = 7;
+/// Segment flag for executable segments
+pub const PF_X: u32 = 1;
+/// Section flag for sections that occupy memory while the program runs
+pub const SHF_ALLOC: usize = 2;
+
+/// The word size an ELF file was built for, from the `EI_CLASS` byte of its header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+/// Reads the class from the start of an ELF header, or returns None if `data` isn't ELF.
+pub fn class_of(data: &[u8]) -> Option<ElfClass> {
+    if data.get(0..4)? != b"\x7fELF" {
+        return None;
+    }
+    match *data.get(4)? {
+        1 => Some(ElfClass::Elf32),
+        2 => Some(ElfClass::Elf64),
+        _ => None,
+    }
+}
+
+/// Returns the class of the ELF file at `path`.
+pub fn class_of_file(path: &str) -> Option<ElfClass> {
+    let mut ident = [0u8; 5];
+    fs::File::open(path).ok()?.read_exact(&mut ident).ok()?;
+    class_of(&ident)
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub p_type: u32,
+    pub flags: u32,
+    pub offset: usize,
+    pub vaddr: usize,
+    pub filesz: usize,
+    pub memsz: usize,
+    pub align: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub flags: usize,
+    pub addr: usize,
+    pub size: usize,
+}
+
+#[derive(Debug)]
+pub struct ElfInfo {
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+    data: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?) as usize)
+}
+
+impl ElfInfo {
+    pub fn from_file(path: &str) -> Option<ElfInfo> {
+        ElfInfo::parse(fs::read(path).ok()?)
+    }
+
+    /// Parses the program and section headers of a little-endian ELF64 image.
+    pub fn parse(data: Vec<u8>) -> Option<ElfInfo> {
+        let segments = ElfInfo::parse_segments(&data)?;
+        // Stripped or truncated files may lack usable section headers; they're only needed for
+        // naming sections, so carry on without them
+        let sections = ElfInfo::parse_sections(&data).unwrap_or_default();
+        Some(ElfInfo { segments, sections, data })
+    }
+
+    fn parse_segments(data: &[u8]) -> Option<Vec<Segment>> {
+        if class_of(data)? != ElfClass::Elf64 {
+            return None;
+        }
+        let phoff = read_u64(data, 0x20)?;
+        let phentsize = read_u16(data, 0x36)? as usize;
+        let phnum = read_u16(data, 0x38)? as usize;
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            segments.push(Segment {
+                p_type: read_u32(data, ph)?,
+                flags: read_u32(data, ph + 0x4)?,
+                offset: read_u64(data, ph + 0x8)?,
+                vaddr: read_u64(data, ph + 0x10)?,
+                filesz: read_u64(data, ph + 0x20)?,
+                memsz: read_u64(data, ph + 0x28)?,
+                align: read_u64(data, ph + 0x30)?,
+            });
+        }
+        Some(segments)
+    }
+
+    fn parse_sections(data: &[u8]) -> Option<Vec<Section>> {
+        let shoff = read_u64(data, 0x28)?;
+        let shentsize = read_u16(data, 0x3a)? as usize;
+        let shnum = read_u16(data, 0x3c)? as usize;
+        let shstrndx = read_u16(data, 0x3e)? as usize;
+        let strtab = read_u64(data, shoff + shstrndx * shentsize + 0x18)?;
+        let mut sections = Vec::new();
+        for i in 0..shnum {
+            let sh = shoff + i * shentsize;
+            let name_start = strtab + read_u32(data, sh)? as usize;
+            let name_len = data.get(name_start..)?.iter().position(|&b| b == 0)?;
+            sections.push(Section {
+                name: String::from_utf8_lossy(&data[name_start..name_start + name_len])
+                    .into_owned(),
+                flags: read_u64(data, sh + 0x8)?,
+                addr: read_u64(data, sh + 0x10)?,
+                size: read_u64(data, sh + 0x20)?,
+            });
+        }
+        Some(sections)
+    }
+
+    /// Reads `len` bytes at virtual address `addr` from the file image, as they would be when
+    /// the program is loaded (before it runs). Parts of segments not backed by the file, such as
+    /// .bss, read as zero.
+    pub fn read(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let seg = self.segments.iter().find(|seg| {
+            seg.p_type == PT_LOAD && seg.vaddr <= addr && addr + len <= seg.vaddr + seg.memsz
+        })?;
+        let mut bytes = vec![0u8; len];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let seg_offset = addr + i - seg.vaddr;
+            if seg_offset < seg.filesz {
+                *byte = *self.data.get(seg.offset + seg_offset)?;
+            }
+        }
+        Some(bytes)
+    }
+
+    /// Returns whether `addr` is in a loadable segment that is mapped executable.
+    pub fn is_executable(&self, addr: usize) -> bool {
+        self.segments.iter().any(|seg| {
+            seg.p_type == PT_LOAD
+                && seg.flags & PF_X != 0
+                && seg.vaddr <= addr
+                && addr < seg.vaddr + seg.memsz
+        })
+    }
+
+    /// Returns the section that `addr` falls in when the program is loaded, if any.
+    pub fn section_containing(&self, addr: usize) -> Option<&Section> {
+        self.sections.iter().find(|section| {
+            section.flags & SHF_ALLOC != 0
+                && section.addr <= addr
+                && addr < section.addr + section.size
+        })
+    }
+
+    /// Returns the executable's thread-local storage template segment, if it has one.
+    pub fn tls_segment(&self) -> Option<&Segment> {
+        self.segments.iter().find(|seg| seg.p_type == PT_TLS)
+    }
+
+    /// Returns the offset of the executable's TLS block below the thread pointer (%fs_base).
+    /// On x86-64 the main executable's block sits immediately below the thread pointer, rounded
+    /// up to the segment's alignment.
+    pub fn tls_block_offset(&self) -> Option<usize> {
+        let tls = self.tls_segment()?;
+        let align = std::cmp::max(tls.align, 1);
+        Some((tls.memsz + align - 1) / align * align)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dwarf_data::DwarfData;
+
+    #[test]
+    fn test_class_of() {
+        assert_eq!(class_of(b"\x7fELF\x01\x01\x01"), Some(ElfClass::Elf32));
+        assert_eq!(class_of(b"\x7fELF\x02\x01\x01"), Some(ElfClass::Elf64));
+        assert_eq!(class_of(b"#!/bin/sh"), None);
+        assert_eq!(class_of(b"\x7fEL"), None);
+        assert_eq!(class_of_file("samples/hello"), Some(ElfClass::Elf64));
+    }
+
+    #[test]
+    fn test_is_executable() {
+        let elf = ElfInfo::from_file("samples/hello")
+            .expect("Could not load samples/hello. Have you run make?");
+        let main = DwarfData::from_file("samples/hello")
+            .unwrap()
+            .get_addr_for_function(None, "main")
+            .unwrap();
+        assert!(elf.is_executable(main));
+        // The data segment is writable, not executable
+        let data = elf.segments.iter().find(|seg| seg.p_type == PT_LOAD && seg.flags & 2 != 0);
+        assert!(!elf.is_executable(data.unwrap().vaddr));
+        assert!(!elf.is_executable(0));
+    }
+
+    #[test]
+    fn test_section_containing() {
+        let elf = ElfInfo::from_file("samples/function_calls")
+            .expect("Could not load samples/function_calls. Have you run make?");
+        let debug_data = DwarfData::from_file("samples/function_calls").unwrap();
+        let main = debug_data.get_addr_for_function(None, "main").unwrap();
+        assert_eq!(elf.section_containing(main).unwrap().name, ".text");
+        assert!(elf.section_containing(0).is_none());
+    }
+}
@@ -0,0 +1,349 @@
+//! A small C-like integer expression language, used for breakpoint conditions and `print`.
+//!
+//! Expressions are made of integer literals (decimal or 0x-prefixed hex), variable names,
+//! `$`-prefixed names (registers such as `$rax`) and the usual C operators. Everything is
+//! evaluated as a 64-bit signed integer; comparisons and logical operators produce 0 or 1.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    /// A program variable, resolved through the debug info
+    Variable(String),
+    /// A `$`-prefixed name (without the `$`), e.g. a register
+    Dollar(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Supplies the values of names referenced by an expression.
+pub trait Context {
+    fn variable(&self, name: &str) -> Result<i64, String>;
+    fn dollar(&self, name: &str) -> Result<i64, String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Dollar(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+// Longer operators must come before their prefixes
+const OPERATORS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "!", "~",
+    "&", "|", "^", "(", ")",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = if text.to_lowercase().starts_with("0x") {
+                u64::from_str_radix(&text[2..], 16).map(|v| v as i64)
+            } else {
+                text.parse::<i64>()
+            };
+            tokens.push(Token::Number(
+                value.or(Err(format!("invalid number \"{}\"", text)))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if c == '$' {
+                tokens.push(Token::Dollar(text[1..].to_string()));
+            } else {
+                tokens.push(Token::Ident(text));
+            }
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or(format!("unexpected character '{}'", c))?;
+            tokens.push(match *op {
+                "(" => Token::LParen,
+                ")" => Token::RParen,
+                op => Token::Op(op),
+            });
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Binary operators from lowest to highest precedence
+const PRECEDENCE: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) => PRECEDENCE[level].iter().find(|(s, _)| s == op),
+                _ => None,
+            };
+            match op {
+                Some((_, op)) => {
+                    self.pos += 1;
+                    let rhs = self.parse_binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                }
+                None => return Ok(lhs),
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        let op = match self.peek() {
+            Some(Token::Op("-")) => Some(UnaryOp::Neg),
+            Some(Token::Op("!")) => Some(UnaryOp::Not),
+            Some(Token::Op("~")) => Some(UnaryOp::BitNot),
+            _ => None,
+        };
+        match op {
+            Some(op) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            None => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Dollar(name)) => Ok(Expr::Dollar(name)),
+            Some(Token::LParen) => {
+                let expr = self.parse_binary(0)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("expected ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Parses an expression, returning a description of the problem if it is malformed.
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let expr = parser.parse_binary(0)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {:?}", token)),
+    }
+}
+
+impl Expr {
+    /// Returns whether evaluating the expression reads any program variable.
+    pub fn uses_variables(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Dollar(_) => false,
+            Expr::Variable(_) => true,
+            Expr::Unary(_, expr) => expr.uses_variables(),
+            Expr::Binary(_, lhs, rhs) => lhs.uses_variables() || rhs.uses_variables(),
+        }
+    }
+
+    /// Returns the names of the program variables the expression reads.
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) | Expr::Dollar(_) => Vec::new(),
+            Expr::Variable(name) => vec![name.as_str()],
+            Expr::Unary(_, expr) => expr.variables(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut names = lhs.variables();
+                names.extend(rhs.variables());
+                names
+            }
+        }
+    }
+
+    pub fn eval(&self, ctx: &dyn Context) -> Result<i64, String> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Variable(name) => ctx.variable(name),
+            Expr::Dollar(name) => ctx.dollar(name),
+            Expr::Unary(op, expr) => {
+                let val = expr.eval(ctx)?;
+                Ok(match op {
+                    UnaryOp::Neg => val.wrapping_neg(),
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::BitNot => !val,
+                })
+            }
+            Expr::Binary(BinaryOp::And, lhs, rhs) => {
+                Ok((lhs.eval(ctx)? != 0 && rhs.eval(ctx)? != 0) as i64)
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+                Ok((lhs.eval(ctx)? != 0 || rhs.eval(ctx)? != 0) as i64)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (l, r) = (lhs.eval(ctx)?, rhs.eval(ctx)?);
+                Ok(match op {
+                    BinaryOp::Add => l.wrapping_add(r),
+                    BinaryOp::Sub => l.wrapping_sub(r),
+                    BinaryOp::Mul => l.wrapping_mul(r),
+                    BinaryOp::Div | BinaryOp::Rem if r == 0 => {
+                        return Err("division by zero".to_string())
+                    }
+                    BinaryOp::Div => l.wrapping_div(r),
+                    BinaryOp::Rem => l.wrapping_rem(r),
+                    BinaryOp::Shl => l.wrapping_shl(r as u32),
+                    BinaryOp::Shr => l.wrapping_shr(r as u32),
+                    BinaryOp::BitAnd => l & r,
+                    BinaryOp::BitOr => l | r,
+                    BinaryOp::BitXor => l ^ r,
+                    BinaryOp::Eq => (l == r) as i64,
+                    BinaryOp::Ne => (l != r) as i64,
+                    BinaryOp::Lt => (l < r) as i64,
+                    BinaryOp::Le => (l <= r) as i64,
+                    BinaryOp::Gt => (l > r) as i64,
+                    BinaryOp::Ge => (l >= r) as i64,
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestContext;
+
+    impl Context for TestContext {
+        fn variable(&self, name: &str) -> Result<i64, String> {
+            match name {
+                "i" => Ok(5),
+                _ => Err(format!("no symbol \"{}\" in current context", name)),
+            }
+        }
+
+        fn dollar(&self, name: &str) -> Result<i64, String> {
+            match name {
+                "rax" => Ok(0x10),
+                _ => Err(format!("unknown register ${}", name)),
+            }
+        }
+    }
+
+    fn eval(s: &str) -> Result<i64, String> {
+        parse(s)?.eval(&TestContext)
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("1 + 2 == 3 && 4 > 3"), Ok(1));
+        assert_eq!(eval("-0x10 + 1"), Ok(-15));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(eval("i == 5"), Ok(1));
+        assert_eq!(eval("$rax + 8"), Ok(0x18));
+        assert!(eval("j == 5").is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(parse("i ==").is_err());
+        assert!(parse("(i == 5").is_err());
+        assert!(parse("i 5").is_err());
+        assert!(parse("i # 5").is_err());
+        assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+    }
+}
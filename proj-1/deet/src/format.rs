@@ -0,0 +1,85 @@
+//! Output formats shared by `print/FMT`, `display/FMT` and `x/FMT`, which show a value a
+//! particular way regardless of its type, along with C literal escaping.
+
+/// Format letters that apply to any integer: x (hex), d (signed decimal), u (unsigned
+/// decimal), t (binary) and c (character)
+pub const INTEGER_FORMATS: [char; 5] = ['x', 'd', 'u', 't', 'c'];
+
+/// Parses the letter after the slash in `print/x`, if it is one of the integer formats.
+pub fn parse_format(spec: &str) -> Option<char> {
+    let mut chars = spec.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if INTEGER_FORMATS.contains(&c) => Some(c),
+        _ => None,
+    }
+}
+
+/// Formats the low `size` bytes (1 to 8) of `value` in one of the integer formats. With `pad`,
+/// hex and binary are zero-padded to the full width, as `x` shows memory.
+pub fn format_integer(value: u64, size: usize, format: char, pad: bool) -> String {
+    let bits = 8 * size as u32;
+    let shift = 64 - bits;
+    let unsigned = (value << shift) >> shift;
+    let signed = ((value << shift) as i64) >> shift;
+    match format {
+        'd' => signed.to_string(),
+        'u' => unsigned.to_string(),
+        // Only the low byte is a character, as in C
+        'c' => format!("{} {}", value as u8 as i8, char_literal(value as u8)),
+        't' if pad => format!("{:0width$b}", unsigned, width = bits as usize),
+        't' => format!("{:b}", unsigned),
+        _ if pad => format!("{:#0width$x}", unsigned, width = 2 + 2 * size),
+        _ => format!("{:#x}", unsigned),
+    }
+}
+
+/// Writes a byte as a C character literal, escaping it if it isn't printable.
+pub fn char_literal(byte: u8) -> String {
+    format!("'{}'", escape_char(byte, b'\''))
+}
+
+/// Writes bytes as a C string literal, escaping the ones that aren't printable.
+pub fn string_literal(bytes: &[u8]) -> String {
+    let chars: String = bytes.iter().map(|&byte| escape_char(byte, b'"')).collect();
+    format!("\"{}\"", chars)
+}
+
+/// Escapes a byte for use between `quote`s in C source.
+fn escape_char(byte: u8, quote: u8) -> String {
+    match byte {
+        b'\\' => "\\\\".to_string(),
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        _ if byte == quote => format!("\\{}", byte as char),
+        0x20..=0x7e => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_integer() {
+        assert_eq!(format_integer(42, 4, 'x', false), "0x2a");
+        assert_eq!(format_integer(42, 4, 'x', true), "0x0000002a");
+        assert_eq!(format_integer(-1i64 as u64, 4, 'x', false), "0xffffffff");
+        assert_eq!(format_integer(-1i64 as u64, 4, 'd', false), "-1");
+        assert_eq!(format_integer(-1i64 as u64, 2, 'u', false), "65535");
+        assert_eq!(format_integer(5, 8, 'd', false), "5");
+        assert_eq!(format_integer(5, 1, 't', false), "101");
+        assert_eq!(format_integer(5, 1, 't', true), "00000101");
+        assert_eq!(format_integer(65, 4, 'c', false), "65 'A'");
+        assert_eq!(format_integer(200, 4, 'c', false), "-56 '\\310'");
+        assert_eq!(parse_format("x"), Some('x'));
+        assert_eq!(parse_format("s"), None);
+        assert_eq!(parse_format("xd"), None);
+    }
+
+    #[test]
+    fn test_string_literal() {
+        assert_eq!(string_literal(b"a\"b\n\x01"), "\"a\\\"b\\n\\001\"");
+        assert_eq!(char_literal(b'\''), "'\\''");
+    }
+}
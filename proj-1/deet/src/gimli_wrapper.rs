@@ -10,7 +10,9 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, Function, InlineSite, Line, LineRow, Location, Member, Type, TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
@@ -47,7 +49,23 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     // Iterate over the compilation units.
     let mut iter = dwarf.units();
     while let Some(header) = iter.next()? {
+        // Later versions moved file names and line tables into forms we don't read, which would
+        // leave us with empty line tables rather than an error.
+        let version = header.version();
+        if version < 2 || version > 4 {
+            return Err(Error::UnsupportedVersion(version));
+        }
         let unit = dwarf.unit(header)?;
+        offset_to_type.extend(collect_types(&unit, &dwarf)?);
+
+        // Functions whose return type hasn't been seen yet, as (index, type offset)
+        let mut pending_return_types: Vec<(usize, usize)> = Vec::new();
+        // Names of the subprogram DIEs by offset, for resolving DW_AT_abstract_origin
+        let mut subprogram_names: HashMap<usize, String> = HashMap::new();
+        // Out-of-line copies of inline functions, which only name their abstract origin
+        let mut pending_names: Vec<(usize, usize)> = Vec::new();
+        // Inlined copies, as (abstract origin, address)
+        let mut pending_inline_sites: Vec<(usize, usize)> = Vec::new();
 
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
@@ -67,42 +85,23 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    let comp_dir = unit
+                        .comp_dir
+                        .map(|dir| dir.to_string_lossy().to_string())
+                        .unwrap_or_default();
                     compilation_units.push(File {
                         name,
+                        comp_dir,
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
+                        line_rows: Vec::new(),
+                        inline_sites: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
+                    let mut origin = None;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -129,10 +128,52 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_type => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    let functions = &compilation_units.last().unwrap().functions;
+                                    pending_return_types.push((functions.len(), offset));
+                                }
+                            }
+                            gimli::DW_AT_abstract_origin => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    origin = Some(offset);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    let functions = &mut compilation_units.last_mut().unwrap().functions;
+                    if !func.name.is_empty() {
+                        let offset = section_offset(entry.offset(), &unit);
+                        subprogram_names.insert(offset, func.name.clone());
+                    } else if let Some(origin) = origin {
+                        pending_names.push((functions.len(), origin));
+                    }
+                    functions.push(func);
+                }
+                gimli::DW_TAG_inlined_subroutine => {
+                    let mut origin = None;
+                    let mut low_pc = None;
+                    let mut entry_pc = None;
+                    let mut attrs = entry.attrs();
+                    while let Some(attr) = attrs.next()? {
+                        match (attr.name(), get_attr_value(&attr, &unit, &dwarf)) {
+                            (gimli::DW_AT_abstract_origin, Ok(DebugValue::Size(offset))) => {
+                                origin = Some(offset)
+                            }
+                            (gimli::DW_AT_low_pc, Ok(DebugValue::Uint(addr))) => {
+                                low_pc = Some(addr as usize)
+                            }
+                            (gimli::DW_AT_entry_pc, Ok(DebugValue::Uint(addr))) => {
+                                entry_pc = Some(addr as usize)
+                            }
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
+                    // Copies that only have DW_AT_ranges (and no entry_pc) are skipped
+                    if let (Some(origin), Some(addr)) = (origin, entry_pc.or(low_pc)) {
+                        pending_inline_sites.push((origin, addr));
+                    }
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
@@ -175,6 +216,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             entity_type: entity_type.unwrap(),
                             location: location.unwrap(),
                             line_number: line_number.try_into().unwrap(),
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
                         };
                         if depth == 1 {
                             compilation_units
@@ -200,26 +242,57 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
 
+        // Return types and abstract origins may be defined after the DIEs that refer to them
+        if let Some(file) = compilation_units.last_mut() {
+            for (index, offset) in pending_return_types {
+                file.functions[index].return_type = offset_to_type.get(&offset).cloned();
+            }
+            for (index, origin) in pending_names {
+                if let Some(name) = subprogram_names.get(&origin) {
+                    file.functions[index].name = name.clone();
+                }
+            }
+            for (origin, address) in pending_inline_sites {
+                if let Some(name) = subprogram_names.get(&origin) {
+                    file.inline_sites.push(InlineSite {
+                        name: name.clone(),
+                        address,
+                    });
+                }
+            }
+        }
+
         // Get line numbers
         if let Some(program) = unit.line_program.clone() {
             // Iterate over the line program rows.
             let mut rows = program.rows();
             while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
-                        }
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
+                // Determine the path. Real applications should cache this for performance.
+                let mut path = path::PathBuf::new();
+                if let Some(file) = row.file(header) {
+                    if let Some(dir) = file.directory(header) {
+                        path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
                     }
+                    path.push(
+                        dwarf
+                            .attr_string(&unit, file.path_name())?
+                            .to_string_lossy()
+                            .as_ref(),
+                    );
+                }
+
+                // Keep every row of the unit's line program, for debugging the line table
+                if let Some(unit_file) = compilation_units.last_mut() {
+                    unit_file.line_rows.push(LineRow {
+                        address: row.address().try_into().unwrap(),
+                        file: path.to_string_lossy().to_string(),
+                        line: row.line().unwrap_or(0).try_into().unwrap(),
+                        is_stmt: row.is_stmt(),
+                        end_sequence: row.end_sequence(),
+                    });
+                }
 
+                if !row.end_sequence() {
                     // Get the File
                     let file = compilation_units
                         .iter_mut()
@@ -257,9 +330,26 @@ pub enum Error {
     GimliError(gimli::Error),
     Addr2lineError(addr2line::gimli::Error),
     ObjectError(String),
+    UnsupportedVersion(u16),
     IoError,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::GimliError(err) => write!(f, "malformed DWARF data: {}", err),
+            Error::Addr2lineError(err) => write!(f, "malformed DWARF data: {}", err),
+            Error::ObjectError(msg) => write!(f, "could not parse object file: {}", msg),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "DWARF version {} is not supported (try recompiling with -gdwarf-4)",
+                version
+            ),
+            Error::IoError => write!(f, "I/O error"),
+        }
+    }
+}
+
 impl From<gimli::Error> for Error {
     fn from(err: gimli::Error) -> Self {
         Error::GimliError(err)
@@ -291,6 +381,250 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
+/// A type built on another one, as (offset, tag, name, size, offset of the other type)
+type Derived = (usize, gimli::DwTag, Option<String>, Option<usize>, Option<usize>);
+
+/// A struct, union or enum as (offset, type, members as (name, offset, type offset))
+type Aggregate = (usize, Type, Vec<(String, usize, usize)>);
+
+/// Reads the unit's types, keyed by their .debug_info offset: base types, and the pointers,
+/// typedefs and const/volatile qualifiers built on them. Types may refer to ones defined further
+/// on, so they are all collected before the variables that use them are read.
+fn collect_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<HashMap<usize, Type>, Error> {
+    let mut types = HashMap::new();
+    let mut derived: Vec<Derived> = Vec::new();
+    // Structs, unions and enums are complete once the types of all their members are
+    let mut aggregates: Vec<Aggregate> = Vec::new();
+    // Depths of the aggregates whose members are being read, and their index in `aggregates`
+    let mut parents: Vec<(isize, usize)> = Vec::new();
+    // Arrays as (offset, offset of the element type, length of each dimension)
+    let mut arrays: Vec<(usize, Option<usize>, Vec<usize>)> = Vec::new();
+    // Depth of the array whose dimensions are being read
+    let mut array_depth = None;
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta, entry)) = entries.next_dfs()? {
+        depth += delta;
+        while parents.last().map_or(false, |(parent, _)| *parent >= depth) {
+            parents.pop();
+        }
+        if array_depth.map_or(false, |array_depth| array_depth >= depth) {
+            array_depth = None;
+        }
+        let mut name = None;
+        let mut size = None;
+        let mut target = None;
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            match (attr.name(), get_attr_value(&attr, unit, dwarf)) {
+                (gimli::DW_AT_name, Ok(DebugValue::Str(value))) => name = Some(value),
+                (gimli::DW_AT_byte_size, Ok(DebugValue::Uint(value))) => {
+                    size = Some(value as usize)
+                }
+                (gimli::DW_AT_type, Ok(DebugValue::Size(offset))) => target = Some(offset),
+                _ => {}
+            }
+        }
+        let offset = section_offset(entry.offset(), unit);
+        match entry.tag() {
+            gimli::DW_TAG_base_type => {
+                let kind = match entry.attr_value(gimli::DW_AT_encoding)? {
+                    Some(gimli::AttributeValue::Encoding(encoding)) => match encoding {
+                        gimli::DW_ATE_unsigned => TypeKind::Unsigned,
+                        gimli::DW_ATE_signed_char | gimli::DW_ATE_unsigned_char => TypeKind::Char,
+                        gimli::DW_ATE_boolean => TypeKind::Boolean,
+                        gimli::DW_ATE_float => TypeKind::Float,
+                        _ => TypeKind::Signed,
+                    },
+                    _ => TypeKind::Signed,
+                };
+                let name = name.unwrap_or_else(|| "<unknown>".to_string());
+                types.insert(offset, Type::new(name, size.unwrap_or(0), kind));
+            }
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type => {
+                derived.push((offset, entry.tag(), name, size, target));
+            }
+            gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_enumeration_type => {
+                let (keyword, kind) = match entry.tag() {
+                    gimli::DW_TAG_structure_type => ("struct", TypeKind::Struct),
+                    gimli::DW_TAG_union_type => ("union", TypeKind::Struct),
+                    _ => ("enum", TypeKind::Enum),
+                };
+                let name = format!("{} {}", keyword, name.unwrap_or_else(|| "{...}".to_string()));
+                parents.push((depth, aggregates.len()));
+                aggregates.push((offset, Type::new(name, size.unwrap_or(0), kind), Vec::new()));
+            }
+            gimli::DW_TAG_array_type => {
+                array_depth = Some(depth);
+                arrays.push((offset, target, Vec::new()));
+            }
+            gimli::DW_TAG_subrange_type if array_depth.is_some() => {
+                let count = match entry.attr_value(gimli::DW_AT_count)? {
+                    Some(count) => count.udata_value(),
+                    None => entry
+                        .attr_value(gimli::DW_AT_upper_bound)?
+                        .and_then(|bound| bound.udata_value())
+                        .map(|bound| bound + 1),
+                };
+                // Flexible array members have no bound
+                arrays.last_mut().unwrap().2.push(count.unwrap_or(0) as usize);
+            }
+            gimli::DW_TAG_member => {
+                let member_offset = match entry.attr_value(gimli::DW_AT_data_member_location)? {
+                    Some(value) => value.udata_value(),
+                    // Union members don't have one
+                    None => Some(0),
+                };
+                if let (Some((_, parent)), Some(name), Some(member_offset), Some(target)) =
+                    (parents.last(), name, member_offset, target)
+                {
+                    aggregates[*parent].2.push((name, member_offset as usize, target));
+                }
+            }
+            gimli::DW_TAG_enumerator => {
+                // Negative values are the ones in signed forms
+                let value = entry.attr_value(gimli::DW_AT_const_value)?.and_then(|value| {
+                    value.udata_value().map(|value| value as i64).or_else(|| value.sdata_value())
+                });
+                if let (Some((_, parent)), Some(name), Some(value)) = (parents.last(), name, value)
+                {
+                    aggregates[*parent].1.enumerators.push((name, value));
+                }
+            }
+            _ => {}
+        }
+    }
+    // Resolve chains such as `const char *` a link at a time, until nothing more can be
+    loop {
+        let unresolved = derived.len() + aggregates.len() + arrays.len();
+        arrays.retain(|(offset, element, dimensions)| {
+            let mut ty = match element.and_then(|element| types.get(&element)) {
+                Some(element) => element.clone(),
+                None => return true,
+            };
+            // int m[2][3] is an array of 2 arrays of 3 ints
+            for (i, length) in dimensions.iter().enumerate().rev() {
+                let suffix: String = dimensions[i..].iter().map(|n| format!("[{}]", n)).collect();
+                let element_name = match ty.name.find(" [") {
+                    Some(end) if ty.kind == TypeKind::Array => ty.name[..end].to_string(),
+                    // Including typedefs of arrays, such as `vec3` for float [3]
+                    _ => ty.name.clone(),
+                };
+                let mut array = Type::new(
+                    format!("{} {}", element_name, suffix),
+                    length * ty.size,
+                    TypeKind::Array,
+                );
+                array.element = Some(Box::new(ty));
+                ty = array;
+            }
+            types.insert(*offset, ty);
+            false
+        });
+        aggregates.retain(|(offset, ty, members)| {
+            if members.iter().any(|(_, _, target)| !types.contains_key(target)) {
+                return true;
+            }
+            let mut ty = ty.clone();
+            for (name, member_offset, target) in members {
+                ty.members.push(Member {
+                    name: name.clone(),
+                    offset: *member_offset,
+                    ty: types[target].clone(),
+                });
+            }
+            types.insert(*offset, ty);
+            false
+        });
+        let waiting = derived.clone();
+        derived.retain(|(offset, tag, name, size, target)| {
+            let target = match target {
+                Some(target) => match types.get(target) {
+                    Some(ty) => Some(ty.clone()),
+                    // A pointer only needs the name of what it points to, so pointers to
+                    // incomplete structs (such as a list node's `next`) resolve straight away
+                    None if *tag == gimli::DW_TAG_pointer_type => {
+                        match incomplete_type(*target, &waiting, &aggregates) {
+                            Some(ty) => Some(ty),
+                            None => return true,
+                        }
+                    }
+                    None => return true,
+                },
+                None => None,
+            };
+            let ty = match (*tag, target) {
+                (gimli::DW_TAG_pointer_type, target) => {
+                    let mut pointer = Type::new(
+                        match &target {
+                            Some(target) if target.kind == TypeKind::Pointer => {
+                                format!("{}*", target.name)
+                            }
+                            Some(target) => format!("{} *", target.name),
+                            None => "void *".to_string(),
+                        },
+                        size.unwrap_or(8),
+                        TypeKind::Pointer,
+                    );
+                    pointer.element = target.map(Box::new);
+                    pointer
+                }
+                (tag, Some(target)) => qualified_type(tag, name, target),
+                // Qualified or renamed void isn't a type a variable can have
+                _ => return false,
+            };
+            types.insert(*offset, ty);
+            false
+        });
+        if derived.len() + aggregates.len() + arrays.len() == unresolved {
+            break;
+        }
+    }
+    Ok(types)
+}
+
+/// Builds the typedef, const or volatile type `tag` on `target`. It is the target under another
+/// name.
+fn qualified_type(tag: gimli::DwTag, name: &Option<String>, target: Type) -> Type {
+    let name = match tag {
+        gimli::DW_TAG_typedef => name.clone().unwrap_or_else(|| target.name.clone()),
+        gimli::DW_TAG_const_type => format!("const {}", target.name),
+        _ => format!("volatile {}", target.name),
+    };
+    Type { name, ..target }
+}
+
+/// Returns as much as is known of type `offset` while it is incomplete, if it is a struct, union
+/// or enum (without its members yet), or a typedef or qualifier of one. That's enough for a
+/// pointer to it, such as the `node_t *next` of a `typedef struct node node_t`.
+fn incomplete_type(offset: usize, derived: &[Derived], aggregates: &[Aggregate]) -> Option<Type> {
+    if let Some((_, ty, _)) = aggregates.iter().find(|(aggregate, _, _)| *aggregate == offset) {
+        return Some(ty.clone());
+    }
+    let (_, tag, name, _, target) = derived.iter().find(|(other, ..)| *other == offset)?;
+    if *tag == gimli::DW_TAG_pointer_type {
+        return None;
+    }
+    Some(qualified_type(*tag, name, incomplete_type((*target)?, derived, aggregates)?))
+}
+
+/// Converts the offset of a DIE within its unit to an offset within the section, which is what
+/// references to the DIE resolve to.
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
 fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
@@ -304,6 +638,13 @@ fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) ->
                     gimli::Operation::Address { address } => {
                         return Some(Location::Address(address.try_into().unwrap()));
                     }
+                    // Thread-local variables: the offset is pushed as a constant, followed by
+                    // DW_OP_form_tls_address (or the GNU equivalent)
+                    gimli::Operation::UnsignedConstant { value } => {
+                        if let Ok(gimli::Operation::TLS) = gimli::Operation::parse(&mut pc, encoding) {
+                            return Some(Location::TlsOffset(value.try_into().unwrap()));
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -1,16 +1,46 @@
 use gimli::StableDeref;
+use libc::user_regs_struct;
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::mem::size_of;
+use std::os::unix::fs::FileExt;
 use std::os::unix::process::CommandExt;
-use std::process::Child;
 use std::process::Command;
+use std::time::Duration;
 
 use crate::dwarf_data::DwarfData;
 
+/// The resume flag in %eflags, which suppresses instruction breakpoints for one instruction
+const EFLAGS_RF: u64 = 1 << 16;
+
+/// Number of debug registers (DR0-DR3) that can hold a hardware breakpoint or watchpoint
+/// address
+pub const HW_BREAKPOINT_SLOTS: usize = 4;
+
+/// How long to sleep between rounds of polling the threads of a multi-threaded inferior
+const THREAD_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// What a debug register traps on. x86 can't trap on reads alone, only on reads or writes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    Execute,
+    Write,
+    ReadWrite,
+}
+
+/// A debug register in use: the address it watches, how many bytes from there (1, 2, 4 or 8;
+/// always 1 for `Execute`), and the kind of access that traps.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DebugSlot {
+    pub addr: usize,
+    pub len: usize,
+    pub access: Access,
+}
+
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
     /// current instruction pointer that it is stopped at.
@@ -22,6 +52,25 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior called exec and is stopped at the entry point of the new program.
+    Execed,
+
+    /// Indicates the inferior forked (or vforked, if the flag is set). Contains the pid of the
+    /// child, which is not debugged and carries on by itself.
+    Forked(Pid, bool),
+
+    /// Indicates a vforked child has exec'd or exited, so the inferior no longer shares its
+    /// memory. The traps taken out of the shared memory for the child have been put back.
+    VforkDone,
+
+    /// Indicates the inferior is entering a system call. Contains the syscall number and its six
+    /// arguments.
+    SyscallEntry(u64, [u64; 6]),
+
+    /// Indicates the inferior is returning from a system call. Contains the syscall number and
+    /// the return value.
+    SyscallExit(u64, i64),
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -33,30 +82,148 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// Returns the human-readable description of a signal, matching the strings printed by
+/// `strsignal(3)` / `kill -l`.
+pub fn signal_description(signal: signal::Signal) -> &'static str {
+    use signal::Signal::*;
+    match signal {
+        SIGHUP => "Hangup",
+        SIGINT => "Interrupt",
+        SIGQUIT => "Quit",
+        SIGILL => "Illegal instruction",
+        SIGTRAP => "Trace/breakpoint trap",
+        SIGABRT => "Aborted",
+        SIGBUS => "Bus error",
+        SIGFPE => "Floating point exception",
+        SIGKILL => "Killed",
+        SIGUSR1 => "User defined signal 1",
+        SIGSEGV => "Segmentation fault",
+        SIGUSR2 => "User defined signal 2",
+        SIGPIPE => "Broken pipe",
+        SIGALRM => "Alarm clock",
+        SIGTERM => "Terminated",
+        SIGSTKFLT => "Stack fault",
+        SIGCHLD => "Child exited",
+        SIGCONT => "Continued",
+        SIGSTOP => "Stopped (signal)",
+        SIGTSTP => "Stopped",
+        SIGTTIN => "Stopped (tty input)",
+        SIGTTOU => "Stopped (tty output)",
+        SIGURG => "Urgent I/O condition",
+        SIGXCPU => "CPU time limit exceeded",
+        SIGXFSZ => "File size limit exceeded",
+        SIGVTALRM => "Virtual timer expired",
+        SIGPROF => "Profiling timer expired",
+        SIGWINCH => "Window changed",
+        SIGIO => "I/O possible",
+        SIGPWR => "Power failure",
+        SIGSYS => "Bad system call",
+    }
+}
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// A stack frame: its level (0 for the innermost frame), the address execution is at (or
+/// returns to) and the frame pointer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub level: usize,
+    pub rip: usize,
+    pub rbp: usize,
+}
+
+impl Frame {
+    /// Returns the address to look the frame's source line up by. An outer frame's %rip is a
+    /// return address, which may already be on the line after the call.
+    pub fn line_addr(&self) -> usize {
+        if self.level > 0 {
+            self.rip - 1
+        } else {
+            self.rip
+        }
+    }
+}
+
+/// A region of a process's address space, as listed in /proc/PID/maps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// Permissions such as "r-xp": read, write, execute, and private or shared
+    pub perms: String,
+    /// Offset of the mapping into the file it maps
+    pub offset: usize,
+    /// The mapped file, a pseudo-path such as "[stack]", or empty for anonymous memory
+    pub path: String,
+}
+
+impl Mapping {
+    /// Parses a line of /proc/PID/maps, e.g.
+    /// "00400000-00401000 r--p 00000000 08:01 1234   /usr/bin/cat".
+    fn parse(line: &str) -> Option<Mapping> {
+        let mut fields = line.splitn(6, ' ');
+        let mut bounds = fields.next()?.split('-').map(|n| usize::from_str_radix(n, 16));
+        let (start, end) = (bounds.next()?.ok()?, bounds.next()?.ok()?);
+        let perms = fields.next().filter(|perms| perms.len() == 4)?.to_string();
+        let offset = usize::from_str_radix(fields.next()?, 16).ok()?;
+        // Skip the device and inode
+        let path = fields.nth(2).unwrap_or("").trim().to_string();
+        Some(Mapping {
+            start,
+            end,
+            perms,
+            offset,
+            path,
+        })
+    }
+}
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
     pub replaced_values: HashMap<usize, u8>,
+    /// Registers as they were before the last single-step, for `set step-register-diff`
+    pub last_regs: Option<user_regs_struct>,
+    /// Hardware breakpoints and watchpoints in debug registers DR0-DR3
+    pub debug_slots: [Option<DebugSlot>; HW_BREAKPOINT_SLOTS],
+    /// Whether resuming stops the inferior at every system call entry and exit
+    pub trace_syscalls: bool,
+    /// Whether the last syscall stop was an entry, so the next one is the matching exit
+    in_syscall: Cell<bool>,
+    /// Signal to deliver to the inferior when it is next resumed
+    pub pending_signal: Cell<Option<signal::Signal>>,
+    /// Threads of the process, the main one first, in the order they were created. ptrace
+    /// attaches new threads to us as they start.
+    threads: RefCell<Vec<Pid>>,
+    /// The thread that last stopped, whose registers are the ones read and written
+    current: Cell<Pid>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: &Vec<usize>) -> Option<Inferior> {
+    /// an error is encountered. The process is left stopped before its first instruction.
+    ///
+    /// `env` is added to the environment, which starts out as a copy of ours if `inherit_env` is
+    /// set and empty otherwise.
+    pub fn new(
+        target: &str,
+        args: &Vec<String>,
+        env: &HashMap<String, String>,
+        inherit_env: bool,
+    ) -> Option<Inferior> {
         let mut cmd = Command::new(target);
         cmd.args(args);
+        if !inherit_env {
+            cmd.env_clear();
+        }
+        cmd.envs(env);
         unsafe {
             cmd.pre_exec(child_traceme);
         }
         let child = cmd.spawn().expect("fail to spawn target programme");
-        let mut inferior = Inferior {
-            child,
-            replaced_values: HashMap::new(),
-        };
-        match inferior.wait(None) {
+        let inferior = Inferior::from_pid(Pid::from_raw(child.id() as i32));
+        match inferior.wait() {
             Ok(status) => match status {
                 Status::Exited(exit_code) => {
                     println!("target programme exited prematurely (status {})", exit_code);
@@ -70,19 +237,25 @@ impl Inferior {
                 }
                 Status::Stopped(signal, _) => {
                     if signal.eq(&signal::Signal::SIGTRAP) {
-                        for addr in breakpoints.iter() {
-                            // install breakpoints
-                            match inferior.write_byte(*addr, 0xcc) {
-                                Ok(_) => {}
-                                Err(err) => println!(
-                                    "failed to set breakpoint at position {:#x}, {}",
-                                    *addr, err
-                                ),
-                            }
+                        // Report exec events rather than a plain SIGTRAP, and mark syscall stops
+                        // so they can't be mistaken for one
+                        let options = ptrace::Options::PTRACE_O_TRACEEXEC
+                            | ptrace::Options::PTRACE_O_TRACEFORK
+                            | ptrace::Options::PTRACE_O_TRACEVFORK
+                            | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+                            | ptrace::Options::PTRACE_O_TRACECLONE
+                            | ptrace::Options::PTRACE_O_TRACESYSGOOD;
+                        if let Err(err) = ptrace::setoptions(inferior.pid(), options) {
+                            println!("failed to trace exec events, {}", err);
                         }
                         return Some(inferior);
                     }
                 }
+                Status::Execed
+                | Status::Forked(..)
+                | Status::VforkDone
+                | Status::SyscallEntry(..)
+                | Status::SyscallExit(..) => {}
             },
             Err(err) => {
                 println!("failed to stop target programme, {}", err);
@@ -93,70 +266,862 @@ impl Inferior {
         None
     }
 
+    /// Wraps a stopped process we're already tracing, with no breakpoints installed yet.
+    fn from_pid(pid: Pid) -> Inferior {
+        Inferior {
+            pid,
+            replaced_values: HashMap::new(),
+            last_regs: None,
+            debug_slots: [None; HW_BREAKPOINT_SLOTS],
+            trace_syscalls: false,
+            in_syscall: Cell::new(false),
+            pending_signal: Cell::new(None),
+            threads: RefCell::new(vec![pid]),
+            current: Cell::new(pid),
+        }
+    }
+
+    /// Starts a new inferior as a copy of checkpoint process `pid`, stopped where the checkpoint
+    /// was taken. The checkpoint itself is left as it is, so it can be restored again.
+    pub fn from_checkpoint(pid: Pid) -> Result<Inferior, nix::Error> {
+        Ok(Inferior::from_pid(fork_stopped(pid)?))
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns whether the inferior is stopped at a system call entry, where it can't be made to
+    /// run anything else until the call completes.
+    pub fn in_syscall(&self) -> bool {
+        self.in_syscall.get()
+    }
+
+    /// Forks the stopped inferior into a checkpoint process: a copy of it as it is now, with our
+    /// traps taken out, that stays stopped until it is restored or deleted.
+    pub fn checkpoint(&self) -> Result<Pid, nix::Error> {
+        let snapshot = fork_stopped(self.pid())?;
+        for (addr, orig_byte) in &self.replaced_values {
+            write_byte_in(snapshot, *addr, *orig_byte)?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Returns the id of the thread that last stopped, whose registers the debugger works with.
+    /// It's the pid until the program starts more threads.
+    pub fn tid(&self) -> Pid {
+        self.current.get()
+    }
+
+    /// Returns the ids of the process's threads, the main thread first.
+    pub fn threads(&self) -> Vec<Pid> {
+        self.threads.borrow().clone()
+    }
+
+    /// Returns the path of the program the inferior is currently running.
+    pub fn exe_path(&self) -> std::io::Result<std::path::PathBuf> {
+        std::fs::read_link(format!("/proc/{}/exe", self.pid()))
+    }
+
+    /// Returns whether `addr` lies in one of the process's executable mappings.
+    pub fn is_executable(&self, addr: usize) -> std::io::Result<bool> {
+        Ok(self.mappings()?.iter().any(|mapping| {
+            mapping.start <= addr && addr < mapping.end && mapping.perms.as_bytes()[2] == b'x'
+        }))
+    }
+
+    /// Returns the process's memory mappings, from /proc/PID/maps.
+    pub fn mappings(&self) -> std::io::Result<Vec<Mapping>> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid()))?;
+        Ok(maps.lines().filter_map(Mapping::parse).collect())
+    }
+
+    /// Returns whether the process still exists, i.e. it hasn't exited or been killed.
+    pub fn is_alive(&self) -> bool {
+        signal::kill(self.pid(), None).is_ok()
+    }
+
+    /// Waits for a thread of this inferior to stop and returns a Status to indicate the state of
+    /// the process. The other threads are stopped too, and the one that stopped becomes current.
+    pub fn wait(&self) -> Result<Status, nix::Error> {
+        Ok(self.next_stop(false)?.expect("a blocking wait returned no status"))
+    }
+
+    /// Polls the inferior without blocking. Returns None if it is still running.
+    pub fn try_wait(&self) -> Result<Option<Status>, nix::Error> {
+        self.next_stop(true)
+    }
+
+    /// Waits for the next stop of any thread and stops the others, so that the whole process
+    /// holds still while it's looked at. Threads starting and exiting are dealt with here and
+    /// not reported. With `nohang`, returns None if no thread has stopped yet.
+    fn next_stop(&self, nohang: bool) -> Result<Option<Status>, nix::Error> {
+        loop {
+            let (tid, status) = match self.poll_threads(nohang)? {
+                Some(stop) => stop,
+                None => return Ok(None),
+            };
+            match status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid() => {
+                    self.threads.borrow_mut().retain(|&thread| thread != tid);
+                    continue;
+                }
+                WaitStatus::PtraceEvent(_pid, _signal, event)
+                    if event == ptrace::Event::PTRACE_EVENT_CLONE as i32 =>
+                {
+                    let thread = self.add_thread(tid)?;
+                    self.resume_thread(thread, None)?;
+                    self.resume_thread(tid, None)?;
+                    continue;
+                }
+                // The other threads are gone: exec kills them, and the main thread's exit is
+                // only reported after theirs
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.threads.replace(vec![self.pid()]);
+                }
+                WaitStatus::PtraceEvent(_pid, _signal, event)
+                    if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+                {
+                    let flags = WaitPidFlag::WNOHANG | WaitPidFlag::__WALL;
+                    for thread in self.threads.replace(vec![self.pid()]) {
+                        if thread != self.pid() {
+                            let _ = waitpid(thread, Some(flags));
+                        }
+                    }
+                }
+                _ => self.stop_other_threads(tid)?,
+            }
+            self.current.set(tid);
+            return Ok(Some(self.rewind_breakpoint(self.to_status(status)?)?));
+        }
+    }
+
+    /// Returns the next thread to change state, along with its new state. A single thread is
+    /// waited for in waitpid; several are polled in turn, so that we never reap a process that
+    /// isn't ours. With `nohang`, returns None if no thread has changed state.
+    fn poll_threads(&self, nohang: bool) -> Result<Option<(Pid, WaitStatus)>, nix::Error> {
+        let threads = self.threads();
+        if threads.len() == 1 && !nohang {
+            return Ok(Some((self.pid(), waitpid(self.pid(), Some(WaitPidFlag::__WALL))?)));
+        }
+        loop {
+            for &tid in &threads {
+                match waitpid(tid, Some(WaitPidFlag::WNOHANG | WaitPidFlag::__WALL))? {
+                    WaitStatus::StillAlive => {}
+                    status => return Ok(Some((tid, status))),
+                }
+            }
+            if nohang {
+                return Ok(None);
+            }
+            std::thread::sleep(THREAD_POLL_INTERVAL);
+        }
+    }
+
+    /// Stops every thread but `stopped` with SIGSTOP. A thread that stops for another reason on
+    /// the way is resumed to take the SIGSTOP; if it hit one of our breakpoints it is wound back
+    /// first, so that it hits it again once the process is resumed.
+    fn stop_other_threads(&self, stopped: Pid) -> Result<(), nix::Error> {
+        let mut done = vec![stopped];
+        // Threads created while this goes on are stopped as well
+        while let Some(tid) = self.threads().into_iter().find(|tid| !done.contains(tid)) {
+            done.push(tid);
+            unsafe {
+                libc::syscall(libc::SYS_tgkill, self.pid().as_raw(), tid.as_raw(), libc::SIGSTOP);
+            }
+            loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                    Ok(WaitStatus::Stopped(_pid, signal::Signal::SIGSTOP)) => break,
+                    Ok(WaitStatus::Stopped(_pid, signal::Signal::SIGTRAP)) => {
+                        let mut regs = ptrace::getregs(tid)?;
+                        if self.replaced_values.contains_key(&(regs.rip as usize - 1)) {
+                            regs.rip -= 1;
+                            ptrace::setregs(tid, regs)?;
+                        }
+                        ptrace::cont(tid, None)?;
+                    }
+                    Ok(WaitStatus::Stopped(_pid, signal)) => ptrace::cont(tid, signal)?,
+                    Ok(WaitStatus::PtraceEvent(_pid, _signal, event))
+                        if event == ptrace::Event::PTRACE_EVENT_CLONE as i32 =>
+                    {
+                        // The new thread is left in the stop it starts out in
+                        done.push(self.add_thread(tid)?);
+                        ptrace::cont(tid, None)?;
+                    }
+                    Ok(WaitStatus::PtraceEvent(_pid, _signal, event))
+                        if event == ptrace::Event::PTRACE_EVENT_FORK as i32
+                            || event == ptrace::Event::PTRACE_EVENT_VFORK as i32 =>
+                    {
+                        self.detach_fork_child(Pid::from_raw(ptrace::getevent(tid)? as i32))?;
+                        ptrace::cont(tid, None)?;
+                    }
+                    Ok(WaitStatus::Exited(..))
+                    | Ok(WaitStatus::Signaled(..))
+                    | Err(nix::Error::Sys(nix::errno::Errno::ECHILD)) => {
+                        self.threads.borrow_mut().retain(|&thread| thread != tid);
+                        break;
+                    }
+                    Ok(_) => ptrace::cont(tid, None)?,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts tracking the thread that `creator` has just created. The new thread is given our
+    /// hardware breakpoints and watchpoints, which threads don't inherit, and left stopped.
+    /// Returns its id.
+    fn add_thread(&self, creator: Pid) -> Result<Pid, nix::Error> {
+        let thread = Pid::from_raw(ptrace::getevent(creator)? as i32);
+        // The thread starts out stopped; wait for that before touching it
+        waitpid(thread, Some(WaitPidFlag::__WALL))?;
+        self.threads.borrow_mut().push(thread);
+        for (index, slot) in self.debug_slots.iter().enumerate() {
+            if let Some(slot) = slot {
+                poke_debug_register(thread, index, slot.addr)?;
+            }
+        }
+        poke_debug_register(thread, 7, self.debug_control())?;
+        Ok(thread)
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
+    /// After hitting one of our breakpoints %rip points just past the int3; moves it back onto
+    /// the breakpoint so that the stop is reported at the breakpoint's own address.
+    fn rewind_breakpoint(&self, status: Status) -> Result<Status, nix::Error> {
+        if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+            if self.replaced_values.contains_key(&(rip - 1)) {
+                let mut regs = ptrace::getregs(self.tid())?;
+                regs.rip = (rip - 1) as u64;
+                ptrace::setregs(self.tid(), regs)?;
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Executes a single instruction. The caller must make sure there's no breakpoint under
+    /// %rip. The registers from before the step are kept in `last_regs`.
+    pub fn step(&mut self) -> Result<Status, nix::Error> {
+        self.last_regs = Some(ptrace::getregs(self.tid())?);
+        // A syscall stepped over from its entry stop completes without an exit stop
+        self.in_syscall.set(false);
+        // The other threads stay stopped
+        ptrace::step(self.tid(), None)?;
+        // A single-step trap must not be mistaken for a breakpoint hit, so don't rewind
+        self.to_status(waitpid(self.tid(), Some(WaitPidFlag::__WALL))?)
+    }
+
+    fn to_status(&self, status: WaitStatus) -> Result<Status, nix::Error> {
+        Ok(match status {
             WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
             WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
             WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
+                let regs = ptrace::getregs(self.tid())?;
                 Status::Stopped(signal, regs.rip as usize)
             }
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+            {
+                Status::Execed
+            }
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == ptrace::Event::PTRACE_EVENT_FORK as i32
+                    || event == ptrace::Event::PTRACE_EVENT_VFORK as i32 =>
+            {
+                let child = Pid::from_raw(ptrace::getevent(self.tid())? as i32);
+                self.detach_fork_child(child)?;
+                Status::Forked(child, event == ptrace::Event::PTRACE_EVENT_VFORK as i32)
+            }
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == ptrace::Event::PTRACE_EVENT_VFORK_DONE as i32 =>
+            {
+                for addr in self.replaced_values.keys() {
+                    write_byte_in(self.pid(), *addr, 0xcc)?;
+                }
+                Status::VforkDone
+            }
+            WaitStatus::PtraceSyscall(_pid) => {
+                let regs = ptrace::getregs(self.tid())?;
+                let entry = !self.in_syscall.get();
+                self.in_syscall.set(entry);
+                if entry {
+                    let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+                    Status::SyscallEntry(regs.orig_rax, args)
+                } else {
+                    Status::SyscallExit(regs.orig_rax, regs.rax as i64)
+                }
+            }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 
+    /// Resumes all threads of the inferior without waiting for it to stop. With `trace_syscalls`
+    /// set, it also stops at the next system call entry or exit.
+    pub fn resume(&self) -> Result<(), nix::Error> {
+        let signal = self.pending_signal.take();
+        if self.watching() {
+            // Otherwise a watchpoint trap nobody looked at would be blamed for the next stop
+            self.write_debug_register(6, 0)?;
+        }
+        if !self.trace_syscalls {
+            // Without syscall stops we won't see the exit of a syscall we're in
+            self.in_syscall.set(false);
+        }
+        for thread in self.threads() {
+            if thread != self.tid() {
+                self.resume_thread(thread, None)?;
+            }
+        }
+        // The pending signal is for the thread that stopped
+        self.resume_thread(self.tid(), signal)
+    }
+
+    /// Resumes a single thread, to stop at the next system call too if `trace_syscalls` is set.
+    fn resume_thread(&self, tid: Pid, signal: Option<signal::Signal>) -> Result<(), nix::Error> {
+        if self.trace_syscalls {
+            ptrace::syscall(tid, signal)
+        } else {
+            ptrace::cont(tid, signal)
+        }
+    }
+
     pub fn cont(&self) -> Result<Status, nix::Error> {
-        let _ = ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+        self.resume()?;
+        self.wait()
+    }
+
+    /// Returns the faulting address (`si_addr`) of the signal that last stopped the inferior.
+    /// Only meaningful for memory-related signals such as SIGSEGV and SIGBUS.
+    pub fn get_fault_addr(&self) -> Result<usize, nix::Error> {
+        let siginfo = ptrace::getsiginfo(self.tid())?;
+        Ok(unsafe { siginfo.si_addr() } as usize)
     }
 
     pub fn terminate(&mut self) -> Result<Status, nix::Error> {
-        let _ = self.child.kill();
-        self.wait(None)
+        let _ = signal::kill(self.pid(), signal::Signal::SIGKILL);
+        self.wait()
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let mut rip = ptrace::getregs(self.pid())?.rip as usize;
-        let mut rbp = ptrace::getregs(self.pid())?.rbp as usize;
+    /// Walks the stack by following the frame pointers, innermost frame first. The walk stops at
+    /// `main` unless `past_main` is set, in which case it continues into the C runtime's startup
+    /// code until the chain ends.
+    pub fn frames(
+        &self,
+        debug_data: &DwarfData,
+        past_main: bool,
+    ) -> Result<Vec<Frame>, nix::Error> {
+        let mut frames = Vec::new();
+        let mut rip = ptrace::getregs(self.tid())?.rip as usize;
+        let mut rbp = ptrace::getregs(self.tid())?.rbp as usize;
         loop {
-            let func = debug_data.get_function_from_addr(rip as usize).unwrap();
-            println!(
-                "%rip {:#x} {} ({})",
+            frames.push(Frame {
+                level: frames.len(),
                 rip,
-                func,
-                debug_data.get_line_from_addr(rip).unwrap()
-            );
-            if func == "main" {
+                rbp,
+            });
+            let func = debug_data.get_function_from_addr(rip as usize);
+            if func.as_deref() == Some("main") && !past_main {
                 break;
             }
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
+            if rbp == 0 {
+                break;
+            }
+            let caller = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)
+                .and_then(|rip| Ok((rip, ptrace::read(self.pid(), rbp as ptrace::AddressType)?)));
+            match caller {
+                Ok((next_rip, next_rbp)) if next_rip != 0 => {
+                    rip = next_rip as usize;
+                    rbp = next_rbp as usize;
+                }
+                Ok(_) => break,
+                // Startup code built without frame pointers can leave garbage in %rbp
+                Err(_) if past_main => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Reads `len` bytes of inferior memory starting at `addr`.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = align_addr_to_word(addr);
+        let skip = addr - word_addr;
+        while bytes.len() < len + skip {
+            let word = ptrace::read(self.pid(), word_addr as ptrace::AddressType)? as u64;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            word_addr += size_of::<usize>();
+        }
+        Ok(bytes[skip..skip + len].to_vec())
+    }
+
+    /// Reads `len` bytes of inferior memory starting at `addr` with a single read of
+    /// /proc/PID/mem, rather than a ptrace call per word like `read_memory`.
+    pub fn read_memory_bulk(&self, addr: usize, len: usize) -> std::io::Result<Vec<u8>> {
+        let mem = std::fs::File::open(format!("/proc/{}/mem", self.pid()))?;
+        let mut bytes = vec![0u8; len];
+        mem.read_exact_at(&mut bytes, addr as u64)?;
+        Ok(bytes)
+    }
+
+    /// Reads the NUL-terminated string at `addr`, without the NUL. At most `max` bytes are read
+    /// if no NUL comes first.
+    pub fn read_string(&self, addr: usize, max: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::new();
+        let mut word_addr = align_addr_to_word(addr);
+        let mut skip = addr - word_addr;
+        while bytes.len() < max {
+            let word = ptrace::read(self.pid(), word_addr as ptrace::AddressType)? as u64;
+            for &byte in &word.to_le_bytes()[skip..] {
+                if byte == 0 || bytes.len() == max {
+                    return Ok(bytes);
+                }
+                bytes.push(byte);
+            }
+            skip = 0;
+            word_addr += size_of::<usize>();
+        }
+        Ok(bytes)
+    }
+
+    /// Returns whether a SIGTRAP stop at `rip` came from an int3 that is part of the program
+    /// itself, rather than from one of our breakpoints.
+    pub fn hit_foreign_int3(&self, rip: usize) -> bool {
+        if rip == 0 || self.replaced_values.contains_key(&rip) {
+            return false;
+        }
+        match self.read_memory(rip - 1, 1) {
+            Ok(bytes) => bytes[0] == 0xcc && !self.replaced_values.contains_key(&(rip - 1)),
+            Err(_) => false,
+        }
+    }
+
+    /// Installs a breakpoint at `addr` and records the byte it replaced. Installing an address
+    /// twice is a no-op, so a trap byte is never recorded as the original instruction byte.
+    pub fn install_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if self.replaced_values.contains_key(&addr) {
+            return Ok(());
+        }
+        let orig_byte = self.write_byte(addr, 0xcc)?;
+        // An int3 that's part of the program has nothing to restore, so it isn't recorded
+        if orig_byte != 0xcc {
+            self.replaced_values.insert(addr, orig_byte);
+        }
+        Ok(())
+    }
+
+    /// Installs a trap at `addr` for the debugger's own use, e.g. to stop at a return address.
+    /// Returns false if there already was one, which must then be left in place when the
+    /// temporary one is done with.
+    pub fn install_temporary_breakpoint(&mut self, addr: usize) -> Result<bool, nix::Error> {
+        if self.replaced_values.contains_key(&addr) {
+            return Ok(false);
+        }
+        self.install_breakpoint(addr)?;
+        Ok(true)
+    }
+
+    /// Removes the breakpoint at `addr`, putting the original byte back.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.replaced_values.remove(&addr) {
+            assert_ne!(orig_byte, 0xcc, "recorded a trap byte as the original at {:#x}", addr);
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
+    /// Overwrites memory starting at `addr`. Where a breakpoint is installed the trap stays in
+    /// place and the new byte becomes the one restored when the breakpoint is removed.
+    pub fn patch(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        for (i, byte) in bytes.iter().enumerate() {
+            match self.replaced_values.get_mut(&(addr + i)) {
+                Some(orig_byte) => *orig_byte = *byte,
+                None => {
+                    self.write_byte(addr + i, *byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Installs a hardware breakpoint at `addr` in a free debug register. Nothing is written to
+    /// the program's code. Returns false if all four debug registers are in use.
+    pub fn install_hw_breakpoint(&mut self, addr: usize) -> Result<bool, nix::Error> {
+        self.install_debug_slot(DebugSlot { addr, len: 1, access: Access::Execute })
+    }
+
+    /// Frees the debug register holding the hardware breakpoint at `addr`.
+    pub fn remove_hw_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        self.remove_debug_slot(DebugSlot { addr, len: 1, access: Access::Execute })
+    }
+
+    /// Returns whether a hardware breakpoint at `addr` is in a debug register.
+    pub fn has_hw_breakpoint(&self, addr: usize) -> bool {
+        self.debug_slots.contains(&Some(DebugSlot { addr, len: 1, access: Access::Execute }))
+    }
+
+    /// Watches the `len` bytes at `addr` for the given kind of access. `len` must be 1, 2, 4 or
+    /// 8 and `addr` aligned to it. Returns false if all four debug registers are in use.
+    pub fn install_watchpoint(
+        &mut self,
+        addr: usize,
+        len: usize,
+        access: Access,
+    ) -> Result<bool, nix::Error> {
+        self.install_debug_slot(DebugSlot { addr, len, access })
+    }
+
+    /// Frees the debug register holding the watchpoint.
+    pub fn remove_watchpoint(
+        &mut self,
+        addr: usize,
+        len: usize,
+        access: Access,
+    ) -> Result<(), nix::Error> {
+        self.remove_debug_slot(DebugSlot { addr, len, access })
+    }
+
+    /// Returns the watchpoints whose debug registers caused the last SIGTRAP, going by the
+    /// status bits in DR6, and clears those bits for the next stop.
+    pub fn triggered_watchpoints(&self) -> Result<Vec<DebugSlot>, nix::Error> {
+        if !self.watching() {
+            return Ok(Vec::new());
+        }
+        let dr6 = self.read_debug_register(6)?;
+        let triggered = (0..HW_BREAKPOINT_SLOTS)
+            .filter(|i| dr6 & 1 << i != 0)
+            .filter_map(|i| self.debug_slots[i])
+            .filter(|slot| slot.access != Access::Execute)
+            .collect();
+        // The processor never clears DR6 itself
+        self.write_debug_register(6, 0)?;
+        Ok(triggered)
+    }
+
+    /// Returns whether any debug register holds a watchpoint.
+    fn watching(&self) -> bool {
+        self.debug_slots
+            .iter()
+            .any(|slot| slot.map_or(false, |slot| slot.access != Access::Execute))
+    }
+
+    fn install_debug_slot(&mut self, slot: DebugSlot) -> Result<bool, nix::Error> {
+        if self.debug_slots.contains(&Some(slot)) {
+            return Ok(true);
+        }
+        let index = match self.debug_slots.iter().position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        self.write_debug_register(index, slot.addr)?;
+        self.debug_slots[index] = Some(slot);
+        self.write_debug_control()?;
+        Ok(true)
+    }
+
+    fn remove_debug_slot(&mut self, slot: DebugSlot) -> Result<(), nix::Error> {
+        if let Some(index) = self.debug_slots.iter().position(|s| *s == Some(slot)) {
+            self.debug_slots[index] = None;
+            self.write_debug_control()?;
+        }
+        Ok(())
+    }
+
+    /// Gets the inferior past the breakpoint it is stopped at, so that resuming doesn't hit it
+    /// again straight away. A software breakpoint's original instruction is single-stepped and
+    /// the trap put back; the status after the step is returned, or None if there was no
+    /// software breakpoint to step over.
+    pub fn resume_from_breakpoint(&mut self) -> Result<Option<Status>, nix::Error> {
+        self.resume_from_hw_breakpoint()?;
+        let rip = ptrace::getregs(self.tid())?.rip as usize;
+        let orig_byte = match self.replaced_values.get(&rip) {
+            Some(orig_byte) => *orig_byte,
+            None => return Ok(None),
+        };
+        self.write_byte(rip, orig_byte)?;
+        let status = self.step()?;
+        if let Status::Stopped(..) = status {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(Some(status))
+    }
+
+    /// Lets the inferior resume from a hardware breakpoint it is stopped at. These fault before
+    /// the instruction runs, so without the resume flag continuing would hit them again.
+    fn resume_from_hw_breakpoint(&self) -> Result<(), nix::Error> {
+        let mut regs = ptrace::getregs(self.tid())?;
+        if self.has_hw_breakpoint(regs.rip as usize) {
+            regs.eflags |= EFLAGS_RF;
+            ptrace::setregs(self.tid(), regs)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites DR7 to enable exactly the debug registers in use. Each register gets a local
+    /// enable bit, and R/W and LEN fields saying what access traps and how many bytes are
+    /// watched (both zero for an execution breakpoint).
+    fn write_debug_control(&self) -> Result<(), nix::Error> {
+        self.write_debug_register(7, self.debug_control())
+    }
+
+    /// Returns the DR7 value that enables the debug registers in `debug_slots`.
+    fn debug_control(&self) -> usize {
+        self.debug_slots.iter().enumerate().fold(0, |dr7, (i, slot)| match slot {
+            Some(slot) => {
+                let rw = match slot.access {
+                    Access::Execute => 0b00,
+                    Access::Write => 0b01,
+                    Access::ReadWrite => 0b11,
+                };
+                let len = match slot.len {
+                    2 => 0b01,
+                    8 => 0b10,
+                    4 => 0b11,
+                    _ => 0b00,
+                };
+                dr7 | 1 << (2 * i) | rw << (16 + 4 * i) | len << (18 + 4 * i)
+            }
+            None => dr7,
+        })
+    }
+
+    /// Reads the x87 and SSE state (including %xmm0-15 and %mxcsr) with PTRACE_GETFPREGS.
+    pub fn get_fpregs(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.tid().as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut fpregs as *mut _ as *mut libc::c_void,
+            )
+        };
+        nix::errno::Errno::result(res).map(|_| fpregs)
+    }
+
+    /// Returns the offset of debug register `index` in `struct user`, which is how
+    /// PTRACE_PEEKUSER and PTRACE_POKEUSER address it.
+    fn debug_register_offset(index: usize) -> usize {
+        let user: libc::user = unsafe { std::mem::zeroed() };
+        &user.u_debugreg[index] as *const u64 as usize - &user as *const _ as usize
+    }
+
+    fn read_debug_register(&self, index: usize) -> Result<usize, nix::Error> {
+        let offset = Inferior::debug_register_offset(index);
+        // PEEKUSER returns the value itself, so -1 is only an error if errno says so
+        let res = unsafe {
+            nix::errno::Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                self.tid().as_raw(),
+                offset as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if res == -1 && nix::errno::errno() != 0 {
+            return Err(nix::Error::last());
+        }
+        Ok(res as usize)
+    }
+
+    /// Writes debug register `index` in every thread, as each has its own.
+    fn write_debug_register(&self, index: usize, value: usize) -> Result<(), nix::Error> {
+        for thread in self.threads() {
+            poke_debug_register(thread, index, value)?;
         }
         Ok(())
     }
 
+    /// Writes a single byte and returns the byte it replaced. Use `install_breakpoint` rather
+    /// than writing 0xcc directly so that the original byte is recorded.
     pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
-        let aligned_addr = align_addr_to_word(addr);
-        let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let origin_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        ptrace::write(
-            self.pid(),
-            aligned_addr as ptrace::AddressType,
-            updated_word as *mut std::ffi::c_void,
-        )?;
-        if val == 0xcc {
-            self.replaced_values.insert(addr, origin_byte as u8);
-        }
-        Ok(origin_byte as u8)
+        write_byte_in(self.pid(), addr, val)
+    }
+
+    /// Lets go of a child the inferior just forked, which ptrace attached to us. The child has
+    /// a copy of our traps, which would kill it with SIGTRAP, so they're taken out first. A
+    /// vforked child shares the inferior's memory, so this takes them out of the inferior too;
+    /// they go back in at the PTRACE_EVENT_VFORK_DONE stop, and the inferior is suspended until
+    /// then anyway.
+    fn detach_fork_child(&self, child: Pid) -> Result<(), nix::Error> {
+        // The child starts out stopped; wait for that before touching it
+        waitpid(child, None)?;
+        for (addr, orig_byte) in &self.replaced_values {
+            write_byte_in(child, *addr, *orig_byte)?;
+        }
+        ptrace::detach(child, None)
+    }
+}
+
+/// A stopped copy of the inferior made by `checkpoint`, to go back to later.
+pub struct Checkpoint {
+    /// Checkpoints are numbered from 1, like breakpoints, and numbers aren't reused
+    pub number: usize,
+    pub pid: Pid,
+    /// Where the inferior was stopped when the checkpoint was taken
+    pub rip: usize,
+}
+
+/// The checkpoints taken so far. Their processes are killed when they are deleted or the table
+/// is dropped, as a checkpoint left behind would carry on running once we stop tracing it.
+pub struct Checkpoints {
+    list: Vec<Checkpoint>,
+    next_number: usize,
+}
+
+impl Checkpoints {
+    pub fn new() -> Checkpoints {
+        Checkpoints {
+            list: Vec::new(),
+            next_number: 1,
+        }
+    }
+
+    /// Records checkpoint process `pid`, stopped at `rip`, and returns its number.
+    pub fn add(&mut self, pid: Pid, rip: usize) -> usize {
+        let number = self.next_number;
+        self.next_number += 1;
+        self.list.push(Checkpoint { number, pid, rip });
+        number
+    }
+
+    pub fn get(&self, number: usize) -> Option<&Checkpoint> {
+        self.list.iter().find(|checkpoint| checkpoint.number == number)
+    }
+
+    /// Returns the most recently taken checkpoint that still exists.
+    pub fn last(&self) -> Option<&Checkpoint> {
+        self.list.last()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Checkpoint> {
+        self.list.iter()
+    }
+
+    /// Deletes checkpoint `number` and kills its process. Returns whether it existed.
+    pub fn remove(&mut self, number: usize) -> bool {
+        match self.list.iter().position(|checkpoint| checkpoint.number == number) {
+            Some(index) => {
+                kill_checkpoint(self.list.remove(index).pid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes every checkpoint.
+    pub fn clear(&mut self) {
+        for checkpoint in self.list.drain(..) {
+            kill_checkpoint(checkpoint.pid);
+        }
+    }
+}
+
+impl Drop for Checkpoints {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+fn kill_checkpoint(pid: Pid) {
+    if signal::kill(pid, signal::Signal::SIGKILL).is_ok() {
+        let _ = waitpid(pid, None);
+    }
+}
+
+/// Makes stopped process `pid` fork, by having it run a fork system call in place of its next
+/// instruction, and returns the child. Both processes are left stopped as `pid` was, with the
+/// same registers and code, and we trace the child as well.
+fn fork_stopped(pid: Pid) -> Result<Pid, nix::Error> {
+    let regs = ptrace::getregs(pid)?;
+    let rip = regs.rip as usize;
+    let code = ptrace::read(pid, rip as ptrace::AddressType)? as u64;
+    // A `syscall` instruction is the two bytes 0f 05
+    let syscall = (code & !0xffff) | 0x050f;
+    ptrace::write(pid, rip as ptrace::AddressType, syscall as *mut std::ffi::c_void)?;
+    let mut fork = regs;
+    fork.rax = libc::SYS_fork as u64;
+    ptrace::setregs(pid, fork)?;
+    let child = loop {
+        ptrace::step(pid, None)?;
+        match waitpid(pid, None)? {
+            // A signal that came in while the process was stopped, such as the SIGCHLD a
+            // checkpoint gets when a copy made of it is killed, is delivered first. It's dropped.
+            WaitStatus::Stopped(_pid, signal) if signal != signal::Signal::SIGTRAP => {}
+            // The fork is reported before the syscall instruction completes
+            WaitStatus::PtraceEvent(_pid, _signal, event)
+                if event == ptrace::Event::PTRACE_EVENT_FORK as i32 =>
+            {
+                let child = Pid::from_raw(ptrace::getevent(pid)? as i32);
+                ptrace::step(pid, None)?;
+                waitpid(pid, None)?;
+                break Some(child);
+            }
+            _ => break None,
+        }
+    };
+    ptrace::write(pid, rip as ptrace::AddressType, code as *mut std::ffi::c_void)?;
+    ptrace::setregs(pid, regs)?;
+    let child = child.ok_or(nix::Error::Sys(nix::errno::Errno::ECHILD))?;
+    // The child starts out stopped, just past the syscall instruction
+    waitpid(child, None)?;
+    ptrace::write(child, rip as ptrace::AddressType, code as *mut std::ffi::c_void)?;
+    ptrace::setregs(child, regs)?;
+    Ok(child)
+}
+
+/// Writes debug register `index` of traced thread `tid`.
+fn poke_debug_register(tid: Pid, index: usize, value: usize) -> Result<(), nix::Error> {
+    let offset = Inferior::debug_register_offset(index);
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_POKEUSER,
+            tid.as_raw(),
+            offset as *mut libc::c_void,
+            value as *mut libc::c_void,
+        )
+    };
+    nix::errno::Errno::result(res).map(drop)
+}
+
+/// Writes a single byte into the memory of traced process `pid` and returns the byte it
+/// replaced.
+fn write_byte_in(pid: Pid, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    let aligned_addr = align_addr_to_word(addr);
+    let byte_offset = addr - aligned_addr;
+    let word = ptrace::read(pid, aligned_addr as ptrace::AddressType)? as u64;
+    let origin_byte = (word >> 8 * byte_offset) & 0xff;
+    let masked_word = word & !(0xff << 8 * byte_offset);
+    let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+    ptrace::write(
+        pid,
+        aligned_addr as ptrace::AddressType,
+        updated_word as *mut std::ffi::c_void,
+    )?;
+    Ok(origin_byte as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_mapping() {
+        let line = "00401000-00402000 r-xp 00001000 08:01 1234                   /tmp/loop";
+        assert_eq!(
+            Mapping::parse(line),
+            Some(Mapping {
+                start: 0x401000,
+                end: 0x402000,
+                perms: "r-xp".to_string(),
+                offset: 0x1000,
+                path: "/tmp/loop".to_string(),
+            })
+        );
+        let anonymous = Mapping::parse("7f9d68466000-7f9d68469000 rw-p 00000000 00:00 0 ").unwrap();
+        assert_eq!(anonymous.path, "");
+        assert_eq!(Mapping::parse("not a mapping"), None);
     }
 }
@@ -0,0 +1,35 @@
+//! Minimal JSON output for `--json` mode, where DEET emits one JSON object per line for
+//! events a front-end may want to capture.
+
+pub enum JsonValue {
+    Number(i64),
+    Str(String),
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats an event as a single-line JSON object: `{"event":"<event>",<fields>...}`
+pub fn event(event: &str, fields: Vec<(&str, JsonValue)>) -> String {
+    let mut out = format!("{{\"event\":\"{}\"", escape(event));
+    for (key, value) in fields {
+        match value {
+            JsonValue::Number(n) => out.push_str(&format!(",\"{}\":{}", escape(key), n)),
+            JsonValue::Str(s) => out.push_str(&format!(",\"{}\":\"{}\"", escape(key), escape(&s))),
+        }
+    }
+    out.push('}');
+    out
+}
@@ -1,17 +1,31 @@
+mod breakpoint;
 mod debugger;
 mod debugger_command;
+mod disasm;
 mod inferior;
 mod dwarf_data;
+mod elf;
+mod expr;
+mod format;
 mod gimli_wrapper;
+mod json;
+mod pager;
+mod registers;
+mod syscalls;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // --json makes DEET report events (e.g. breakpoint creation) as JSON objects, one per line
+    let json = args.len() == 3 && args[1] == "--json";
+    if json {
+        args.remove(1);
+    }
     if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+        println!("Usage: {} [--json] <target program>", args[0]);
         std::process::exit(1);
     }
     let target = &args[1];
@@ -20,5 +34,6 @@ fn main() {
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let exit_code = Debugger::new(target, json).run();
+    std::process::exit(exit_code);
 }
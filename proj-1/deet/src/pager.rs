@@ -0,0 +1,65 @@
+//! Paging of long command output, like GDB's `--Type <return> to continue--` prompts.
+
+use std::io::{self, BufRead, Write};
+
+pub struct Pager {
+    /// Whether pagination was requested (`set pagination on|off`)
+    pub enabled: bool,
+}
+
+pub fn is_tty(fd: libc::c_int) -> bool {
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+/// Returns the number of rows of the terminal attached to stdout.
+fn terminal_height() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    if size.ws_row == 0 {
+        None
+    } else {
+        Some(size.ws_row as usize)
+    }
+}
+
+impl Pager {
+    pub fn new() -> Pager {
+        Pager {
+            enabled: is_tty(libc::STDOUT_FILENO),
+        }
+    }
+
+    /// Prints `lines`, pausing after every screenful when pagination is enabled. Pagination is
+    /// never applied when stdin or stdout isn't a terminal (e.g. when driven by a script).
+    pub fn print(&self, lines: &[String]) {
+        let height = match terminal_height() {
+            Some(height)
+                if self.enabled && is_tty(libc::STDIN_FILENO) && is_tty(libc::STDOUT_FILENO) =>
+            {
+                // Leave a row for the continuation prompt
+                std::cmp::max(height - 1, 1)
+            }
+            _ => {
+                for line in lines {
+                    println!("{}", line);
+                }
+                return;
+            }
+        };
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 && i % height == 0 {
+                print!("--Type <return> to continue, or q <return> to quit--");
+                let _ = io::stdout().flush();
+                let mut response = String::new();
+                if io::stdin().lock().read_line(&mut response).is_err()
+                    || response.trim() == "q"
+                {
+                    return;
+                }
+            }
+            println!("{}", line);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use libc::{user_fpregs_struct, user_regs_struct};
+use std::convert::TryInto;
+
+/// Maps GDB's architecture-independent pseudo-registers to the x86-64 register they alias.
+fn resolve_alias(name: &str) -> &str {
+    match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        name => name,
+    }
+}
+
+/// Returns true if `name` is a register (or an alias of one).
+pub fn is_register(name: &str) -> bool {
+    REGISTER_NAMES.contains(&resolve_alias(name))
+}
+
+/// Returns true if the named register holds an address, so it's best shown in hex.
+pub fn is_address_register(name: &str) -> bool {
+    match resolve_alias(name) {
+        "rip" | "rsp" | "rbp" | "fs_base" | "gs_base" => true,
+        _ => false,
+    }
+}
+
+/// All registers in `user_regs_struct`, in the order `info registers` and register diffs list them
+pub const REGISTER_NAMES: [&str; 27] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base",
+    "gs_base", "orig_rax",
+];
+
+/// The SSE registers, which `info registers` shows when asked for the vector group
+pub const VECTOR_REGISTER_NAMES: [&str; 17] = [
+    "xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7", "xmm8", "xmm9", "xmm10",
+    "xmm11", "xmm12", "xmm13", "xmm14", "xmm15", "mxcsr",
+];
+
+/// Returns true if `name` is one of the registers fetched with PTRACE_GETFPREGS.
+pub fn is_vector_register(name: &str) -> bool {
+    VECTOR_REGISTER_NAMES.contains(&name)
+}
+
+/// Flag bits of the eflags register, as (bit, name)
+const EFLAGS_BITS: [(u32, &str); 9] = [
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+];
+
+/// Exception flag and mask bits of the mxcsr register, as (bit, name)
+const MXCSR_BITS: [(u32, &str); 14] = [
+    (0, "IE"),
+    (1, "DE"),
+    (2, "ZE"),
+    (3, "OE"),
+    (4, "UE"),
+    (5, "PE"),
+    (6, "DAZ"),
+    (7, "IM"),
+    (8, "DM"),
+    (9, "ZM"),
+    (10, "OM"),
+    (11, "UM"),
+    (12, "PM"),
+    (15, "FZ"),
+];
+
+/// Decodes the set flags of an eflags value, e.g. "[ PF ZF IF ]".
+pub fn decode_eflags(eflags: u64) -> String {
+    decode_flags(eflags, &EFLAGS_BITS)
+}
+
+/// Decodes the set flags of an mxcsr value, e.g. "[ IM DM ZM OM UM PM ]".
+pub fn decode_mxcsr(mxcsr: u32) -> String {
+    decode_flags(mxcsr as u64, &MXCSR_BITS)
+}
+
+fn decode_flags(value: u64, bits: &[(u32, &str)]) -> String {
+    let mut out = String::from("[ ");
+    for (bit, name) in bits.iter() {
+        if value & (1 << bit) != 0 {
+            out.push_str(name);
+            out.push(' ');
+        }
+    }
+    out.push(']');
+    out
+}
+
+/// Returns a mutable reference to the named register, or None if there is no such register.
+pub fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match resolve_alias(name) {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" => &mut regs.rbp,
+        "rsp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        "orig_rax" => &mut regs.orig_rax,
+        _ => return None,
+    })
+}
+
+/// Returns the value of the named register, or None if there is no such register.
+pub fn get_register(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    register_mut(&mut regs, name).map(|val| *val)
+}
+
+/// Returns the 16 bytes of the named %xmm register, or None if `name` isn't one.
+pub fn get_xmm(fpregs: &user_fpregs_struct, name: &str) -> Option<[u8; 16]> {
+    if !name.starts_with("xmm") {
+        return None;
+    }
+    let index = name[3..].parse::<usize>().ok().filter(|&index| index < 16)?;
+    let mut bytes = [0u8; 16];
+    for (i, word) in fpregs.xmm_space[index * 4..index * 4 + 4].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    Some(bytes)
+}
+
+/// Formats an %xmm register the ways it is commonly used, as gdb does: e.g.
+/// "{v4_float = {1.5, 0, 0, 0}, v2_double = {5.28426686e-315, 0}, v4_int32 = {1069547520, 0,
+/// 0, 0}, v2_int64 = {1069547520, 0}, uint128 = 0x3fc00000}".
+pub fn format_xmm(bytes: &[u8; 16]) -> String {
+    let lanes = |size: usize, format: &dyn Fn(&[u8]) -> String| {
+        let lanes: Vec<String> = bytes.chunks(size).map(format).collect();
+        format!("{{{}}}", lanes.join(", "))
+    };
+    let mut low = [0u8; 8];
+    let mut high = [0u8; 8];
+    low.copy_from_slice(&bytes[..8]);
+    high.copy_from_slice(&bytes[8..]);
+    let uint128 = (u64::from_le_bytes(high) as u128) << 64 | u64::from_le_bytes(low) as u128;
+    format!(
+        "{{v4_float = {}, v2_double = {}, v4_int32 = {}, v2_int64 = {}, uint128 = {:#x}}}",
+        lanes(4, &|lane| float_text(f32::from_le_bytes(lane.try_into().unwrap()))),
+        lanes(8, &|lane| float_text(f64::from_le_bytes(lane.try_into().unwrap()))),
+        lanes(4, &|lane| i32::from_le_bytes(lane.try_into().unwrap()).to_string()),
+        lanes(8, &|lane| i64::from_le_bytes(lane.try_into().unwrap()).to_string()),
+        uint128
+    )
+}
+
+/// Formats a float in plain decimal, switching to exponent notation for magnitudes where that
+/// would run to dozens of digits (integers reinterpreted as floats are often tiny).
+fn float_text<T>(value: T) -> String
+where
+    T: Copy + Into<f64> + std::fmt::Display + std::fmt::LowerExp,
+{
+    let magnitude = value.into().abs();
+    if magnitude != 0.0 && magnitude.is_finite() && !(1e-6..1e16).contains(&magnitude) {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Lists the registers whose value differs between two snapshots, as (name, old, new).
+pub fn diff(before: &user_regs_struct, after: &user_regs_struct) -> Vec<(&'static str, u64, u64)> {
+    REGISTER_NAMES
+        .iter()
+        .filter_map(|name| {
+            let old = get_register(before, name)?;
+            let new = get_register(after, name)?;
+            if old != new {
+                Some((*name, old, new))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let before: user_regs_struct = unsafe { std::mem::zeroed() };
+        let mut after = before;
+        after.rax = 42;
+        after.rip = 0x401000;
+        assert_eq!(diff(&before, &after), vec![("rax", 0, 42), ("rip", 0, 0x401000)]);
+        assert!(diff(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_xmm() {
+        let mut fpregs: user_fpregs_struct = unsafe { std::mem::zeroed() };
+        fpregs.xmm_space[4] = 1.5f32.to_bits();
+        fpregs.xmm_space[6] = 2;
+        let xmm1 = get_xmm(&fpregs, "xmm1").unwrap();
+        assert_eq!(xmm1[..4], 1.5f32.to_le_bytes());
+        assert_eq!(
+            format_xmm(&xmm1),
+            "{v4_float = {1.5, 0, 3e-45, 0}, v2_double = {5.28426686e-315, 1e-323}, \
+             v4_int32 = {1069547520, 0, 2, 0}, v2_int64 = {1069547520, 2}, \
+             uint128 = 0x2000000003fc00000}"
+        );
+        assert!(get_xmm(&fpregs, "xmm16").is_none());
+        assert!(get_xmm(&fpregs, "rax").is_none());
+        assert_eq!(decode_mxcsr(0x1f80), "[ IM DM ZM OM UM PM ]");
+    }
+}